@@ -2,58 +2,526 @@
 //!
 //! 负责调用底层 CLI 工具并处理执行结果
 
-use std::process::Stdio;
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
 use std::time::{Duration, Instant};
 
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
-use tokio::time::timeout;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdin, Command};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
+use super::openai::{self, ChatEvent, Message};
+use crate::guard;
+use crate::process::{cleanup_group, terminate_group, TERMINATE_GRACE};
+use crate::record::Recording;
+use crate::redact;
 use crate::types::{
-    AgentConfig, AgentResult, AgentType, CliTool, ErrorDetail, ErrorKind, OmccError,
+    AgentConfig, AgentEvent, AgentResult, AgentType, ApiSettings, AttemptError, BackendSpec,
+    ChangeReport, CliTool, CommandPreview, CustomCommand, DryRunReport, ErrorDetail, ErrorKind,
+    IsolationMode, IsolationReport, Metrics, OmccError, PathPolicy, PromptDelivery, SandboxCheck,
+    SandboxPolicy, TokenUsage, WorktreeAction,
 };
+use crate::workspace::{Checkpoint, Snapshot, TrackedSnapshot, WorkspaceLock, Worktree};
+
+/// 底层 CLI 退出后，继续读取剩余输出的最长等待时间
+const DRAIN_GRACE: Duration = Duration::from_secs(2);
+
+/// 软截止时间到达时发送给 Agent 的收尾消息
+const WRAP_UP_MESSAGE: &str = "【时间提醒】本次任务即将达到时间上限。请停止新的探索，\
+整理目前的进展，并立即按最终回复要求给出完整的工作总结。";
+
+/// 单次成功尝试的输出
+struct AttemptOutput {
+    session_id: String,
+    result: String,
+    usage: Option<TokenUsage>,
+}
 
 /// Agent 执行器
 pub struct AgentExecutor {
     config: AgentConfig,
+    events: Option<UnboundedSender<AgentEvent>>,
+    cancel: CancellationToken,
 }
 
 impl AgentExecutor {
     /// 创建新的执行器
-    pub fn new(config: AgentConfig) -> Self {
-        Self { config }
+    ///
+    /// 启用 `redact_prompt` 时先对提示词脱敏，有替换时在 stderr 输出警告
+    pub fn new(mut config: AgentConfig) -> Self {
+        if config.redact_prompt {
+            let (prompt, count) = redact::redactor().redact_count(&config.prompt);
+            if count > 0 {
+                eprintln!("[OMCC] 警告：提示词中的 {} 处敏感信息已替换为占位符", count);
+                config.prompt = prompt;
+            }
+        }
+        Self {
+            config,
+            events: None,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// 设置取消令牌：令牌触发后终止底层 CLI 进程组并返回 `Cancelled`
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// 订阅执行事件
+    pub fn with_events(mut self, events: UnboundedSender<AgentEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// 获取执行配置
+    pub fn config(&self) -> &AgentConfig {
+        &self.config
+    }
+
+    /// 发送执行事件（无订阅者时忽略），发送前脱敏
+    fn emit(&self, mut event: AgentEvent) {
+        if let Some(ref events) = self.events {
+            redact::redact_event(&mut event);
+            let _ = events.send(event);
+        }
     }
 
     /// 执行 Agent 任务
     pub async fn execute(&self) -> AgentResult {
+        let mut result = if self.config.uses_worktree() {
+            self.execute_isolated().await
+        } else {
+            self.execute_with_retries().await
+        };
+        redact::redact_result(&mut result);
+        self.emit(AgentEvent::Finished {
+            result: result.clone(),
+        });
+        result
+    }
+
+    /// 在隔离工作树中执行 Agent 任务
+    ///
+    /// 成功后按 `on_finish` 处理改动并附加执行报告；失败时丢弃工作树，主工作区始终不受影响
+    async fn execute_isolated(&self) -> AgentResult {
+        let worktree = match Worktree::create(
+            &self.config.working_dir,
+            self.config.isolate_base.as_deref(),
+        )
+        .await
+        {
+            Ok(worktree) => worktree,
+            Err(e) => return self.error_to_result(e, Vec::new(), 0),
+        };
+
+        let mut config = self.config.clone();
+        config.working_dir = worktree.working_dir().to_path_buf();
+        config.isolate = IsolationMode::None;
+        let inner = AgentExecutor {
+            config,
+            events: self.events.clone(),
+            cancel: self.cancel.clone(),
+        };
+        let result = inner.execute_with_retries().await;
+
+        let result = if result.is_success() {
+            match self.finish_worktree(&worktree).await {
+                Ok(report) => result.with_isolation(report),
+                Err(e) => {
+                    // 保留工作树，避免丢失 Agent 的改动
+                    eprintln!(
+                        "[OMCC] 处理隔离工作树失败，改动保留在 {}",
+                        worktree.path.display()
+                    );
+                    return self.error_to_result(e, Vec::new(), 0);
+                }
+            }
+        } else {
+            result
+        };
+
+        if let Err(e) = worktree.remove().await {
+            eprintln!("[OMCC] 删除隔离工作树失败：{}", e);
+        }
+        result
+    }
+
+    /// 按 `on_finish` 处理隔离工作树中的改动，应用失败时改为保存为分支
+    async fn finish_worktree(&self, worktree: &Worktree) -> Result<IsolationReport, OmccError> {
+        let diff = worktree.diff().await?;
+        let mut report = IsolationReport {
+            base: worktree.base.clone(),
+            action: WorktreeAction::Discard,
+            branch: None,
+            apply_error: None,
+            diff,
+        };
+        if report.diff.is_empty() {
+            return Ok(report);
+        }
+
+        if self.config.on_finish == WorktreeAction::Apply {
+            match worktree.apply(&report.diff).await {
+                Ok(()) => {
+                    report.action = WorktreeAction::Apply;
+                    return Ok(report);
+                }
+                Err(e) => report.apply_error = Some(e.to_string()),
+            }
+        }
+
+        if self.config.on_finish != WorktreeAction::Discard || report.apply_error.is_some() {
+            let name = format!(
+                "omcc/{}-{}",
+                self.config.agent_type.name(),
+                &worktree.id[..8]
+            );
+            let summary: String = self
+                .config
+                .prompt
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .take(72)
+                .collect();
+            let message = format!("omcc {}: {}", self.config.agent_type.name(), summary);
+            worktree.branch(&name, &message).await?;
+            report.action = WorktreeAction::Branch;
+            report.branch = Some(name);
+        }
+        Ok(report)
+    }
+
+    /// 按重试策略和后端链执行 Agent 任务
+    ///
+    /// 依次尝试主后端和备用后端，每个后端按重试策略重试；失败的错误类型在 `fallback_on` 中时切换到下一个后端。
+    /// 可写 Agent 执行期间持有工作区写锁，结束后附加执行前后的工作区改动报告；
+    /// 只读 Agent 结束后检查工作区是否被修改。
+    /// 启用检查点时，首次尝试前为工作区创建检查点，每次重试或切换后端前恢复到该检查点，结束后删除
+    async fn execute_with_retries(&self) -> AgentResult {
+        // 可写 Agent 独占工作区，整个执行期间（含重试和切换后端）持有写锁
+        let _lock = if self.config.sandbox.allows_writes() {
+            let wait = self.config.lock_wait.map(Duration::from_secs);
+            match WorkspaceLock::acquire(
+                &self.config.working_dir,
+                self.config.agent_type,
+                wait,
+                &self.cancel,
+            )
+            .await
+            {
+                Ok(lock) => Some(lock),
+                Err(e) => return self.error_to_result(e, Vec::new(), 0),
+            }
+        } else {
+            None
+        };
+
+        // 记录执行前的工作区快照：可写 Agent 用于生成改动报告，只读 Agent 只对工作目录下被跟踪的文件计算哈希，
+        // 用于检查是否违反沙箱（不在 Git 仓库中时跳过）
+        let read_only = !self.config.sandbox.allows_writes();
+        let check_sandbox = read_only && self.config.sandbox_check != SandboxCheck::Off;
+        let snapshot = if !read_only {
+            match Snapshot::take(&self.config.working_dir).await {
+                Ok(snapshot) => Some(snapshot),
+                Err(e) => {
+                    if self.config.log_metrics {
+                        eprintln!("[OMCC] 跳过改动报告：{}", e);
+                    }
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let tracked = if check_sandbox {
+            match TrackedSnapshot::take(&self.config.working_dir).await {
+                Ok(tracked) => Some(tracked),
+                Err(e) => {
+                    if self.config.log_metrics {
+                        eprintln!("[OMCC] 跳过只读校验：{}", e);
+                    }
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        // 路径策略要求回滚时，即使未启用检查点也需要记录执行前的状态
+        let path_policy = self.config.active_path_policy();
+        let needs_rollback = path_policy.is_some_and(|policy| policy.rollback);
+        let checkpoint = if self.config.uses_checkpoint() || needs_rollback {
+            match Checkpoint::create(&self.config.working_dir).await {
+                Ok(checkpoint) => Some(checkpoint),
+                Err(e) => return self.error_to_result(e, Vec::new(), 0),
+            }
+        } else {
+            None
+        };
+
         let start_time = Instant::now();
+        let backends = self.config.backend_chain();
+        let fallback_on = self.config.get_fallback_on();
+        let mut attempts: Vec<AttemptError> = Vec::new();
+        let mut outcome = Err(OmccError::UnexpectedException("未知错误".to_string()));
+        // 只有启用检查点时才在重试和切换后端前恢复工作区
+        let retry_checkpoint = checkpoint
+            .as_ref()
+            .filter(|_| self.config.uses_checkpoint());
+
+        for (index, backend) in backends.iter().enumerate() {
+            if index > 0 {
+                let error = outcome
+                    .as_ref()
+                    .err()
+                    .map(|e| e.to_string())
+                    .unwrap_or_default();
+                self.emit(AgentEvent::Fallback {
+                    from: backends[index - 1].to_string(),
+                    to: backend.to_string(),
+                    error,
+                });
+                if self.config.log_metrics {
+                    eprintln!(
+                        "[OMCC] 后端 {} 失败，切换到 {}",
+                        backends[index - 1],
+                        backend
+                    );
+                }
+                if let Err(e) = self
+                    .restore_checkpoint(retry_checkpoint, attempts.len())
+                    .await
+                {
+                    outcome = Err(e);
+                    break;
+                }
+            }
+
+            // 会话 ID 只对创建它的主后端有效
+            let session_id = if index == 0 {
+                self.config.session_id.as_deref()
+            } else {
+                None
+            };
+            outcome = self
+                .run_attempts(
+                    backend,
+                    session_id,
+                    retry_checkpoint,
+                    start_time,
+                    &mut attempts,
+                )
+                .await
+                .map(|output| (output, backend));
+
+            match outcome {
+                Err(ref e) if fallback_on.contains(&ErrorKind::from(e)) => continue,
+                _ => break,
+            }
+        }
+
+        let changes = match (snapshot, tracked) {
+            (Some(snapshot), _) => Some(snapshot.changes(self.config.report_diff).await),
+            (None, Some(tracked)) => Some(tracked.changes().await),
+            (None, None) => None,
+        };
+        let changes = match changes {
+            Some(Ok(changes)) => Some(changes),
+            Some(Err(e)) => {
+                eprintln!("[OMCC] 生成改动报告失败：{}", e);
+                None
+            }
+            None => None,
+        };
+        // 只读 Agent 不报告空改动
+        let changes = changes.filter(|changes| !read_only || !changes.is_empty());
+
+        if let Some(ref changes) = changes {
+            if check_sandbox {
+                let violation = sandbox_violation(changes);
+                if self.config.sandbox_check == SandboxCheck::Fail && outcome.is_ok() {
+                    outcome = Err(violation);
+                } else {
+                    eprintln!("[OMCC] 警告：{}", violation);
+                }
+            }
+            if let Some(policy) = path_policy {
+                if let Some(violation) = self
+                    .check_path_policy(policy, changes, checkpoint.as_ref())
+                    .await
+                {
+                    outcome = Err(violation);
+                }
+            }
+        }
+
+        if let Some(checkpoint) = checkpoint {
+            if let Err(e) = checkpoint.discard().await {
+                eprintln!("[OMCC] 删除检查点失败：{}", e);
+            }
+        }
+
+        // 尝试总数在事后校验改写结果前统计：校验失败的运行也算一次尝试
+        let retries = retry_count(attempts.len() + usize::from(outcome.is_ok()));
+        let result = match outcome {
+            Ok((output, backend)) => {
+                let result = AgentResult::success(
+                    self.config.agent_type,
+                    output.session_id,
+                    output.result,
+                    start_time.elapsed(),
+                )
+                .with_backend(backend);
+                if self.config.return_metrics {
+                    result.with_metrics(Metrics {
+                        duration_ms: start_time.elapsed().as_millis() as u64,
+                        input_tokens: output.usage.map(|u| u.input_tokens),
+                        output_tokens: output.usage.map(|u| u.output_tokens),
+                        retries,
+                    })
+                } else {
+                    result
+                }
+            }
+            Err(e) => self.error_to_result(e, attempts, retries),
+        };
+        match changes {
+            Some(changes) => result.with_changes(changes),
+            None => result,
+        }
+    }
+
+    /// 按路径策略检查改动的文件，违反时按需回滚工作区并返回 `PolicyViolation`
+    async fn check_path_policy(
+        &self,
+        policy: &PathPolicy,
+        changes: &ChangeReport,
+        checkpoint: Option<&Checkpoint>,
+    ) -> Option<OmccError> {
+        let violations = match policy.check(changed_paths(changes)) {
+            Ok(violations) => violations,
+            Err(e) => return Some(e),
+        };
+        if violations.is_empty() {
+            return None;
+        }
+
+        let paths: Vec<&str> = violations.iter().map(|v| v.path.as_str()).collect();
+        let mut message = format!("改动了不允许的文件：{}", list_paths(&paths));
+        let mut rolled_back = false;
+        if policy.rollback {
+            match checkpoint {
+                Some(checkpoint) => match checkpoint.restore().await {
+                    Ok(()) => {
+                        rolled_back = true;
+                        message.push_str("（已回滚）");
+                    }
+                    Err(e) => message.push_str(&format!("（回滚失败：{}）", e)),
+                },
+                None => message.push_str("（无检查点，无法回滚）"),
+            }
+        }
+        Some(OmccError::PolicyViolation {
+            message,
+            violations,
+            rolled_back,
+        })
+    }
+
+    /// 在指定后端上按重试策略执行，每次尝试的错误记录到 `attempts`
+    async fn run_attempts(
+        &self,
+        backend: &BackendSpec,
+        session_id: Option<&str>,
+        checkpoint: Option<&Checkpoint>,
+        start_time: Instant,
+        attempts: &mut Vec<AttemptError>,
+    ) -> Result<AttemptOutput, OmccError> {
+        // 后端不支持的选项在启动进程前报告
+        for warning in self.config.check_backend(backend, session_id.is_some())? {
+            eprintln!("[OMCC] 警告：{}", warning);
+        }
+
         let max_retries = self.config.get_max_retries();
+        let policy = self.config.get_retry_policy();
         let mut last_error: Option<OmccError> = None;
 
-        for attempt in 0..=max_retries {
-            if attempt > 0 {
-                // 指数退避
-                let delay = Duration::from_millis(500 * 2u64.pow(attempt - 1));
-                tokio::time::sleep(delay).await;
+        for retry in 0..=max_retries {
+            let attempt = attempts.len() as u32;
+            if let Some(ref error) = last_error {
+                let delay = policy.delay(retry);
+
+                // 超出总重试时间预算时不再重试
+                if let Some(budget) = policy.budget() {
+                    if start_time.elapsed() + delay > budget {
+                        if self.config.log_metrics {
+                            eprintln!("[OMCC] 已超出重试时间预算 {}s，停止重试", budget.as_secs());
+                        }
+                        break;
+                    }
+                }
+
+                self.emit(AgentEvent::Retrying {
+                    attempt,
+                    delay_ms: delay.as_millis() as u64,
+                    error: error.to_string(),
+                });
+                tokio::select! {
+                    _ = self.cancel.cancelled() => return Err(OmccError::Cancelled),
+                    _ = tokio::time::sleep(delay) => {}
+                }
+
+                // 撤销失败尝试对工作区的修改
+                self.restore_checkpoint(checkpoint, attempts.len()).await?;
 
                 if self.config.log_metrics {
-                    eprintln!("[OMCC] 重试第 {} 次...", attempt);
+                    eprintln!("[OMCC] 重试第 {} 次...", retry);
                 }
             }
 
-            match self.execute_once().await {
-                Ok((session_id, result)) => {
-                    return AgentResult::success(
-                        self.config.agent_type,
-                        session_id,
-                        result,
-                        start_time.elapsed(),
-                    );
+            self.emit(AgentEvent::AttemptStarted {
+                attempt,
+                cli: backend.name(),
+                model: backend.model.clone(),
+            });
+
+            let attempt_start = Instant::now();
+            let outcome = match guard::acquire(
+                backend.tool,
+                self.config.backend_settings.get(&backend.tool),
+                &self.cancel,
+            )
+            .await
+            {
+                Ok(permit) => {
+                    let outcome = self.execute_once(backend, session_id).await;
+                    if let Some(permit) = permit {
+                        permit.record(outcome.as_ref().err().map(ErrorKind::from));
+                    }
+                    outcome
                 }
+                Err(e) => Err(e),
+            };
+            match outcome {
+                Ok(output) => return Ok(output),
                 Err(e) => {
-                    // 某些错误不应重试
-                    if !self.should_retry(&e) {
-                        return self.error_to_result(e);
+                    let kind = ErrorKind::from(&e);
+                    attempts.push(AttemptError {
+                        attempt,
+                        backend: backend.to_string(),
+                        error_kind: kind,
+                        error: e.to_string(),
+                        duration_ms: attempt_start.elapsed().as_millis() as u64,
+                    });
+                    // 不在重试策略内的错误直接返回
+                    if !policy.should_retry(kind) {
+                        return Err(e);
                     }
                     last_error = Some(e);
                 }
@@ -61,122 +529,376 @@ impl AgentExecutor {
         }
 
         // 所有重试都失败
-        self.error_to_result(last_error.unwrap_or(OmccError::UnexpectedException(
-            "未知错误".to_string(),
-        )))
+        Err(last_error.unwrap_or(OmccError::UnexpectedException("未知错误".to_string())))
+    }
+
+    /// 将工作区恢复到检查点（未启用检查点时不做任何操作）
+    async fn restore_checkpoint(
+        &self,
+        checkpoint: Option<&Checkpoint>,
+        next_attempt: usize,
+    ) -> Result<(), OmccError> {
+        let Some(checkpoint) = checkpoint else {
+            return Ok(());
+        };
+        checkpoint.restore().await?;
+        self.emit(AgentEvent::CheckpointRestored {
+            attempt: next_attempt as u32,
+            commit: checkpoint.commit.clone(),
+        });
+        if self.config.log_metrics {
+            eprintln!("[OMCC] 已将工作区恢复到检查点 {}", checkpoint.commit);
+        }
+        Ok(())
+    }
+
+    /// 在指定后端上执行一次 Agent 任务（启用 `record` 时保存本次尝试的记录，HTTP 后端不记录）
+    async fn execute_once(
+        &self,
+        backend: &BackendSpec,
+        session_id: Option<&str>,
+    ) -> Result<AttemptOutput, OmccError> {
+        if backend.tool == CliTool::OpenAi {
+            return self.run_http(backend, session_id).await;
+        }
+        let Some(ref dir) = self.config.record else {
+            return self.run_once(backend, session_id, &mut None).await;
+        };
+        let mut recording = None;
+        let outcome = self.run_once(backend, session_id, &mut recording).await;
+        if let Some(mut recording) = recording {
+            recording.finish(&outcome);
+            if !self.config.record_raw {
+                recording.redact(redact::redactor());
+            }
+            match recording.save(dir) {
+                Ok(path) if self.config.log_metrics => {
+                    eprintln!("[OMCC] 已保存调用记录 {}", path.display())
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("[OMCC] 保存调用记录失败：{}", e),
+            }
+        }
+        outcome
     }
 
-    /// 执行一次 Agent 任务
-    async fn execute_once(&self) -> Result<(String, String), OmccError> {
-        let cli_tool = self.config.agent_type.cli_tool();
-        let mut cmd = self.build_command(cli_tool)?;
+    /// 启动底层 CLI 并解析其输出
+    async fn run_once(
+        &self,
+        backend: &BackendSpec,
+        session_id: Option<&str>,
+        recording: &mut Option<Recording>,
+    ) -> Result<AttemptOutput, OmccError> {
+        let cli_tool = backend.tool;
 
         // 构建完整的 prompt（包含系统引导提示词）
         let full_prompt = self.build_full_prompt();
 
+        // 自定义命令按配置传递 prompt：作为参数、写入 stdin 或写入临时文件
+        let custom = self.custom_command(backend)?;
+        let delivery = custom.map(|command| command.prompt);
+        let prompt_file = match delivery {
+            Some(PromptDelivery::File) => Some(PromptFile::create(&full_prompt)?),
+            _ => None,
+        };
+        let prompt_arg = match prompt_file {
+            Some(ref file) => file.0.to_string_lossy().into_owned(),
+            None => full_prompt.clone(),
+        };
+        let mut cmd = self.build_command(backend, session_id, &prompt_arg)?;
+
         // 根据 CLI 工具类型决定如何传递 prompt
         match cli_tool {
             CliTool::OpenCode => {
                 // OpenCode CLI: prompt 作为命令行参数
                 cmd.arg("--").arg(&full_prompt);
             }
-            CliTool::Codex | CliTool::Claude => {
-                // Codex/Claude CLI: prompt 通过 stdin 传递
+            CliTool::Codex
+            | CliTool::Claude
+            | CliTool::Gemini
+            | CliTool::Mock
+            | CliTool::Replay
+            | CliTool::OpenAi
+            | CliTool::Custom => {
+                // Codex/Claude/Gemini CLI: prompt 通过 stdin 传递
             }
         }
 
-        // 启动子进程
+        if self.config.record.is_some() {
+            *recording = Some(Recording::start(
+                self.config.agent_type,
+                backend.to_string(),
+                &cmd,
+                &full_prompt,
+            ));
+        }
+
+        if self.cancel.is_cancelled() {
+            return Err(OmccError::Cancelled);
+        }
+
+        // 启动子进程：独立进程组，便于整组终止；执行被中止时随 Child 一同终止
         let mut child = cmd
+            .process_group(0)
+            .kill_on_drop(true)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .stdin(Stdio::piped())
             .spawn()
             .map_err(|e| {
                 if e.kind() == std::io::ErrorKind::NotFound {
-                    OmccError::CommandNotFound(cli_tool.command().to_string())
+                    let program = custom.map_or(cli_tool.command(), |c| c.program.as_str());
+                    OmccError::CommandNotFound(program.to_string())
                 } else {
                     OmccError::IoError(e)
                 }
             })?;
+        // 进程组 ID 即子进程 PID；子进程被回收后 `child.id()` 返回 None，因此在启动时记录
+        let pgid = child
+            .id()
+            .ok_or_else(|| OmccError::IoError(std::io::Error::other("无法获取子进程 PID")))?;
 
-        // 对于 Codex/Claude CLI，通过 stdin 传递 prompt
-        if cli_tool != CliTool::OpenCode {
-            if let Some(stdin) = child.stdin.take() {
-                let prompt = full_prompt.clone();
-                tokio::spawn(async move {
-                    use tokio::io::AsyncWriteExt;
-                    let mut stdin = stdin;
-                    let _ = stdin.write_all(prompt.as_bytes()).await;
-                    let _ = stdin.shutdown().await;
-                });
+        // 传递 prompt：Codex 和 Gemini 一次性写入 stdin 后关闭；Claude 使用 stream-json 输入并保持 stdin 打开，
+        // 以便在软截止时间发送收尾消息；OpenCode 不需要 stdin。
+        // Claude 的首条消息在后台写入，写入期间同样受取消和超时约束，写完后交回 stdin
+        let mut live_stdin: Option<ChildStdin> = None;
+        let mut pending_stdin: Option<JoinHandle<std::io::Result<ChildStdin>>> = None;
+        match cli_tool {
+            CliTool::Codex
+            | CliTool::Gemini
+            | CliTool::Mock
+            | CliTool::Replay
+            | CliTool::Custom
+                if delivery.is_none_or(|d| d == PromptDelivery::Stdin) =>
+            {
+                if let Some(ref mut recording) = recording {
+                    recording.stdin.push(full_prompt.clone());
+                }
+                if let Some(mut stdin) = child.stdin.take() {
+                    let prompt = full_prompt.clone();
+                    tokio::spawn(async move {
+                        let _ = stdin.write_all(prompt.as_bytes()).await;
+                        let _ = stdin.shutdown().await;
+                    });
+                }
             }
-        } else {
-            // OpenCode CLI 不需要 stdin
-            drop(child.stdin.take());
+            CliTool::Claude => {
+                let message = claude_user_message(&full_prompt);
+                if let Some(ref mut recording) = recording {
+                    recording.stdin.push(message.clone());
+                }
+                if let Some(mut stdin) = child.stdin.take() {
+                    pending_stdin = Some(tokio::spawn(async move {
+                        stdin.write_all(message.as_bytes()).await?;
+                        Ok(stdin)
+                    }));
+                }
+            }
+            _ => drop(child.stdin.take()),
         }
 
         // 读取输出
         let stdout = child.stdout.take().ok_or(OmccError::EmptyResult)?;
         let stderr = child.stderr.take();
 
-        let idle_timeout = Duration::from_secs(self.config.get_timeout());
-        let max_duration = Duration::from_secs(self.config.get_max_duration());
-        let start_time = Instant::now();
+        // 超时均以 0 表示不限制
+        let idle_timeout = self.config.get_timeout();
+        let max_duration = self.config.get_max_duration();
+        let start_time = tokio::time::Instant::now();
+        let idle_deadline_after = |now: tokio::time::Instant| {
+            (idle_timeout > 0).then(|| now + Duration::from_secs(idle_timeout))
+        };
+        let hard_deadline =
+            (max_duration > 0).then(|| start_time + Duration::from_secs(max_duration));
+        let soft_deadline = self
+            .config
+            .soft_deadline
+            .filter(|s| *s > 0)
+            .map(|s| start_time + Duration::from_secs(s));
+        let mut idle_deadline = idle_deadline_after(start_time);
+        let mut soft_deadline_reached = false;
+        let mut exit_status: Option<ExitStatus> = None;
 
         let mut reader = BufReader::new(stdout).lines();
         let mut output_lines: Vec<String> = Vec::new();
         let mut session_id: Option<String> = None;
+        let mut usage: Option<TokenUsage> = None;
+        let mut delta_open = false;
+        let output_rules = custom.map(|command| command.output.clone().unwrap_or_default());
 
         loop {
-            // 检查总时长
-            if max_duration.as_secs() > 0 && start_time.elapsed() > max_duration {
-                let _ = child.kill().await;
-                return Err(OmccError::Timeout(max_duration.as_secs()));
-            }
-
-            // 带超时读取
-            match timeout(idle_timeout, reader.next_line()).await {
-                Ok(Ok(Some(line))) => {
-                    // 尝试解析 JSON 响应
-                    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&line) {
-                        // 提取 SESSION_ID
-                        if let Some(sid) = json.get("session_id").and_then(|v| v.as_str()) {
+            tokio::select! {
+                biased;
+                _ = self.cancel.cancelled() => {
+                    terminate_group(&mut child, pgid, TERMINATE_GRACE).await;
+                    return Err(OmccError::Cancelled);
+                }
+                _ = sleep_until_opt(hard_deadline) => {
+                    // 总时长超时：不依赖输出到达，准时终止
+                    terminate_group(&mut child, pgid, TERMINATE_GRACE).await;
+                    return Err(OmccError::Timeout(max_duration));
+                }
+                _ = sleep_until_opt(soft_deadline), if !soft_deadline_reached => {
+                    soft_deadline_reached = true;
+                    let delivered = send_wrap_up(&mut live_stdin).await;
+                    if let (true, Some(recording)) = (delivered, recording.as_mut()) {
+                        recording.stdin.push(claude_user_message(WRAP_UP_MESSAGE));
+                    }
+                    if self.config.log_metrics {
+                        eprintln!(
+                            "[OMCC] 已到软截止时间，收尾消息{}",
+                            if delivered { "已发送" } else { "未发送（底层 CLI 不支持）" }
+                        );
+                    }
+                    self.emit(AgentEvent::SoftDeadline {
+                        elapsed_s: start_time.elapsed().as_secs(),
+                        delivered,
+                    });
+                }
+                _ = sleep_until_opt(idle_deadline) => {
+                    if exit_status.is_some() {
+                        // 底层 CLI 已退出，但遗留的后台进程仍持有 stdout：视为输出结束
+                        break;
+                    }
+                    // 空闲超时：终止整个进程组，避免遗留孙进程占用工作区
+                    terminate_group(&mut child, pgid, TERMINATE_GRACE).await;
+                    return Err(OmccError::IdleTimeout(idle_timeout));
+                }
+                written = join_opt(&mut pending_stdin), if pending_stdin.is_some() => {
+                    pending_stdin = None;
+                    match written {
+                        Ok(stdin) => live_stdin = Some(stdin),
+                        Err(e) => {
+                            terminate_group(&mut child, pgid, TERMINATE_GRACE).await;
+                            return Err(OmccError::IoError(e));
+                        }
+                    }
+                }
+                status = child.wait(), if exit_status.is_none() => {
+                    exit_status = Some(status?);
+                    // 只再等待一小段时间读取剩余输出
+                    idle_deadline = Some(tokio::time::Instant::now() + DRAIN_GRACE);
+                }
+                next_line = reader.next_line() => {
+                    let line = match next_line {
+                        Ok(Some(line)) => line,
+                        // EOF
+                        Ok(None) => break,
+                        Err(e) => {
+                            terminate_group(&mut child, pgid, TERMINATE_GRACE).await;
+                            return Err(OmccError::IoError(e));
+                        }
+                    };
+                    let now = tokio::time::Instant::now();
+                    idle_deadline = if exit_status.is_some() {
+                        Some(now + DRAIN_GRACE)
+                    } else {
+                        idle_deadline_after(now)
+                    };
+
+                    if let Some(ref mut recording) = recording {
+                        recording.push_stdout(&line);
+                    }
+                    self.emit(AgentEvent::Output { line: line.clone() });
+                    if let Some(ref rules) = output_rules {
+                        // 自定义命令：按配置的 JSON 指针提取字段
+                        let parsed = rules.parse(&line);
+                        if let Some(sid) = parsed.session_id {
+                            if session_id.as_deref() != Some(sid.as_str()) {
+                                self.emit(AgentEvent::Session {
+                                    session_id: sid.clone(),
+                                });
+                            }
+                            session_id = Some(sid);
+                        }
+                        output_lines.extend(parsed.text);
+                        if let Some(u) = parsed.usage.as_ref().and_then(openai::parse_usage) {
+                            usage = Some(u);
+                        }
+                        if let Some(error) = parsed.error {
+                            terminate_group(&mut child, pgid, TERMINATE_GRACE).await;
+                            return Err(upstream_error(&error));
+                        }
+                    } else if let Ok(json) = serde_json::from_str::<serde_json::Value>(&line) {
+                        // 解析 JSON 响应
+                        // 提取 SESSION_ID（OpenCode 的字段名为 sessionID）
+                        let sid = json.get("session_id").or_else(|| json.get("sessionID"));
+                        if let Some(sid) = sid.and_then(|v| v.as_str()) {
+                            if session_id.as_deref() != Some(sid) {
+                                self.emit(AgentEvent::Session {
+                                    session_id: sid.to_string(),
+                                });
+                            }
                             session_id = Some(sid.to_string());
                         }
-                        // 提取结果
-                        if let Some(result) = json.get("result").and_then(|v| v.as_str()) {
-                            output_lines.push(result.to_string());
-                        } else if let Some(content) = json.get("content").and_then(|v| v.as_str()) {
-                            output_lines.push(content.to_string());
-                        } else if let Some(message) = json.get("message").and_then(|v| v.as_str()) {
-                            output_lines.push(message.to_string());
+                        // 提取结果（跳过回显的用户消息和 Gemini 的错误事件；
+                        // Gemini 的增量内容拼接到上一段增量之后）
+                        let event_type = json.get("type").and_then(|v| v.as_str());
+                        let severity = json.get("severity").and_then(|v| v.as_str());
+                        let gemini_error = event_type == Some("error") && severity.is_some();
+                        let text = ["result", "content", "message"]
+                            .iter()
+                            .find_map(|key| json.get(*key).and_then(|v| v.as_str()))
+                            .filter(|_| json.get("role").and_then(|v| v.as_str()) != Some("user"));
+                        let is_delta = json.get("delta").and_then(|v| v.as_bool()) == Some(true);
+                        if let Some(text) = text.filter(|_| !gemini_error) {
+                            match output_lines.last_mut() {
+                                Some(last) if is_delta && delta_open => last.push_str(text),
+                                _ => output_lines.push(text.to_string()),
+                            }
+                        }
+                        delta_open = is_delta && text.is_some();
+                        // 提取 token 用量（Gemini 在 `stats` 中报告）
+                        if let Some(u) = ["usage", "stats"]
+                            .iter()
+                            .find_map(|key| json.get(*key).and_then(openai::parse_usage))
+                        {
+                            usage = Some(u);
+                        }
+                        // Claude 输出最终结果后关闭 stdin，让其正常退出
+                        if event_type == Some("result") {
+                            live_stdin = None;
                         }
                         // 检查错误
                         if let Some(error) = json.get("error").and_then(|v| v.as_str()) {
-                            return Err(OmccError::UpstreamError(error.to_string()));
+                            terminate_group(&mut child, pgid, TERMINATE_GRACE).await;
+                            return Err(upstream_error(error));
+                        }
+                        // Gemini 以 error 事件报告错误（severity 为 warning 时继续执行）
+                        if gemini_error && severity == Some("error") {
+                            let message = text.unwrap_or("未知错误").to_string();
+                            terminate_group(&mut child, pgid, TERMINATE_GRACE).await;
+                            return Err(upstream_error(&message));
                         }
                     } else {
                         // 非 JSON 行，直接记录
                         output_lines.push(line);
+                        delta_open = false;
                     }
                 }
-                Ok(Ok(None)) => {
-                    // EOF
-                    break;
-                }
-                Ok(Err(e)) => {
-                    return Err(OmccError::IoError(e));
-                }
-                Err(_) => {
-                    // 空闲超时
-                    let _ = child.kill().await;
-                    return Err(OmccError::IdleTimeout(idle_timeout.as_secs()));
-                }
             }
         }
+        drop(live_stdin);
 
         // 等待进程结束
-        let status = child.wait().await.map_err(OmccError::IoError)?;
+        let status = match exit_status {
+            Some(status) => status,
+            None => tokio::select! {
+                biased;
+                _ = self.cancel.cancelled() => {
+                    terminate_group(&mut child, pgid, TERMINATE_GRACE).await;
+                    return Err(OmccError::Cancelled);
+                }
+                _ = sleep_until_opt(hard_deadline) => {
+                    terminate_group(&mut child, pgid, TERMINATE_GRACE).await;
+                    return Err(OmccError::Timeout(max_duration));
+                }
+                status = child.wait() => status?,
+            },
+        };
+
+        // 底层 CLI 已退出，清理其遗留在进程组中的后台进程
+        cleanup_group(pgid, TERMINATE_GRACE).await;
 
         // 读取 stderr（如果有）
         let mut stderr_output = String::new();
@@ -192,8 +914,22 @@ impl AgentExecutor {
             }
         }
 
+        if let Some(ref mut recording) = recording {
+            recording.stderr = stderr_output.clone();
+            recording.exit_code = status.code();
+        }
+
         // 检查退出码
         if !status.success() {
+            // 限流信息通常出现在 stderr 或最后几行输出中
+            if let Some(line) = stderr_output
+                .lines()
+                .chain(output_lines.iter().rev().take(5).map(String::as_str))
+                .find(|l| is_rate_limit_message(l))
+            {
+                return Err(OmccError::RateLimited(line.trim().to_string()));
+            }
+
             let exit_code = status.code().unwrap_or(-1);
             let last_lines: Vec<String> = output_lines
                 .iter()
@@ -220,16 +956,133 @@ impl AgentExecutor {
         // 生成 SESSION_ID（如果没有从响应中获取）
         let session_id = session_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
 
-        Ok((session_id, result))
+        Ok(AttemptOutput {
+            session_id,
+            result,
+            usage,
+        })
+    }
+
+    /// 通过 OpenAI 兼容 HTTP API 执行一次 Agent 任务
+    ///
+    /// 传入会话 ID 时先加载本地保存的历史消息；成功后将本轮对话追加到会话历史
+    async fn run_http(
+        &self,
+        backend: &BackendSpec,
+        session_id: Option<&str>,
+    ) -> Result<AttemptOutput, OmccError> {
+        let settings = self.config.backend_settings.get(&CliTool::OpenAi);
+        let api = settings.and_then(|s| s.api.clone()).unwrap_or_default();
+        let model = http_model(backend, &api)?;
+        let api_key = openai::api_key(settings)?;
+
+        let mut messages = match session_id {
+            Some(id) => openai::load_history(id)?,
+            None => Vec::new(),
+        };
+        messages.push(Message::user(self.build_full_prompt()));
+
+        if self.cancel.is_cancelled() {
+            return Err(OmccError::Cancelled);
+        }
+
+        // 超时均以 0 表示不限制；空闲超时从请求发出开始计算，每收到一段内容后重新计时
+        let idle_timeout = self.config.get_timeout();
+        let max_duration = self.config.get_max_duration();
+        let start_time = tokio::time::Instant::now();
+        let idle_deadline_after = |now: tokio::time::Instant| {
+            (idle_timeout > 0).then(|| now + Duration::from_secs(idle_timeout))
+        };
+        let hard_deadline =
+            (max_duration > 0).then(|| start_time + Duration::from_secs(max_duration));
+        let mut idle_deadline = idle_deadline_after(start_time);
+
+        let mut stream = tokio::select! {
+            biased;
+            _ = self.cancel.cancelled() => return Err(OmccError::Cancelled),
+            _ = sleep_until_opt(hard_deadline) => return Err(OmccError::Timeout(max_duration)),
+            _ = sleep_until_opt(idle_deadline) => return Err(OmccError::IdleTimeout(idle_timeout)),
+            stream = openai::start(&api, api_key.as_deref(), &model, &messages) => stream?,
+        };
+
+        let session_id = session_id
+            .map(str::to_string)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        self.emit(AgentEvent::Session {
+            session_id: session_id.clone(),
+        });
+
+        // 按完整的行发送输出事件
+        let mut content = String::new();
+        let mut emitted = 0;
+        let mut usage: Option<TokenUsage> = None;
+        loop {
+            let event = tokio::select! {
+                biased;
+                _ = self.cancel.cancelled() => return Err(OmccError::Cancelled),
+                _ = sleep_until_opt(hard_deadline) => return Err(OmccError::Timeout(max_duration)),
+                _ = sleep_until_opt(idle_deadline) => return Err(OmccError::IdleTimeout(idle_timeout)),
+                event = stream.next_event() => event?,
+            };
+            idle_deadline = idle_deadline_after(tokio::time::Instant::now());
+            match event {
+                Some(ChatEvent::Delta(text)) => {
+                    content.push_str(&text);
+                    while let Some(pos) = content[emitted..].find('\n') {
+                        let line = content[emitted..emitted + pos].to_string();
+                        emitted += pos + 1;
+                        self.emit(AgentEvent::Output { line });
+                    }
+                }
+                Some(ChatEvent::Usage(u)) => usage = Some(u),
+                None => break,
+            }
+        }
+        if emitted < content.len() {
+            self.emit(AgentEvent::Output {
+                line: content[emitted..].to_string(),
+            });
+        }
+
+        let result = content.trim().to_string();
+        if result.is_empty() {
+            return Err(OmccError::EmptyResult);
+        }
+
+        messages.push(Message::assistant(content));
+        if let Err(e) = openai::save_history(&session_id, &messages) {
+            eprintln!("[OMCC] 保存会话历史失败：{}", e);
+        }
+
+        Ok(AttemptOutput {
+            session_id,
+            result,
+            usage,
+        })
     }
 
     /// 构建命令
-    fn build_command(&self, cli_tool: CliTool) -> Result<Command, OmccError> {
-        let mut cmd = Command::new(cli_tool.command());
+    ///
+    /// `prompt` 为自定义命令中 `{prompt}` 的值（提示词或提示词文件路径），其他后端忽略
+    fn build_command(
+        &self,
+        backend: &BackendSpec,
+        session_id: Option<&str>,
+        prompt: &str,
+    ) -> Result<Command, OmccError> {
+        let cli_tool = backend.tool;
+        let custom = self.custom_command(backend)?;
+        let mut cmd = match custom {
+            Some(command) => Command::new(&command.program),
+            None => Command::new(cli_tool.program()),
+        };
 
         // 设置工作目录
         cmd.current_dir(&self.config.working_dir);
 
+        // 按后端配置设置环境变量
+        let env_clear = self.apply_env(&mut cmd, cli_tool)?;
+
         // 根据不同的 CLI 工具设置不同的参数格式
         match cli_tool {
             CliTool::Codex => {
@@ -249,12 +1102,16 @@ impl AgentExecutor {
                 if let Some(ref profile) = self.config.profile {
                     cmd.arg("--profile").arg(profile);
                 }
-                // 图片
-                for image in &self.config.images {
+                // 模型
+                if let Some(ref model) = backend.model {
+                    cmd.arg("--model").arg(model);
+                }
+                // 图片（含 Looker 要分析的图片）
+                for image in self.config.attached_images() {
                     cmd.arg("--image").arg(image);
                 }
                 // 会话复用
-                if let Some(ref session_id) = self.config.session_id {
+                if let Some(session_id) = session_id {
                     cmd.arg("resume").arg(session_id);
                 }
             }
@@ -263,39 +1120,258 @@ impl AgentExecutor {
                 cmd.arg("run");
                 cmd.arg("--format").arg("json");
 
+                // OpenCode 没有沙箱参数：通过配置覆盖将沙箱策略转换为权限设置
+                let existing = configured_env(&cmd, OPENCODE_CONFIG_ENV).or_else(|| {
+                    (!env_clear)
+                        .then(|| std::env::var(OPENCODE_CONFIG_ENV).ok())
+                        .flatten()
+                });
+                cmd.env(
+                    OPENCODE_CONFIG_ENV,
+                    opencode_config(self.config.sandbox, existing.as_deref()),
+                );
+
                 // 模型
-                if let Some(ref model) = self.config.model {
+                if let Some(ref model) = backend.model {
                     cmd.arg("--model").arg(model);
                 }
 
-                // Looker 需要分析文件（通过 --image 参数）
-                if self.config.agent_type == AgentType::Looker {
-                    if let Some(ref file_path) = self.config.file_path {
-                        cmd.arg("--image").arg(file_path);
-                    }
+                // 会话复用
+                if let Some(session_id) = session_id {
+                    cmd.arg("--session").arg(session_id);
+                }
+
+                // 图片和其他附件（含 Looker 要分析的文件）统一通过 --file 附加
+                let attachments = self.config.attached_images();
+                for file in attachments.iter().chain(&self.config.attached_files()) {
+                    cmd.arg("--file").arg(file);
                 }
             }
             CliTool::Claude => {
-                // claude -p --output-format stream-json --sandbox xxx
+                // claude -p --input-format stream-json --output-format stream-json --sandbox xxx
                 cmd.arg("-p");
+                cmd.arg("--input-format").arg("stream-json");
                 cmd.arg("--output-format").arg("stream-json");
+                cmd.arg("--verbose");
                 cmd.arg("--sandbox").arg(self.config.sandbox.as_arg());
 
                 // 模型
-                if let Some(ref model) = self.config.model {
+                if let Some(ref model) = backend.model {
                     cmd.arg("--model").arg(model);
                 }
 
                 // 会话复用
-                if let Some(ref session_id) = self.config.session_id {
+                if let Some(session_id) = session_id {
                     cmd.arg("--resume").arg(session_id);
                 }
             }
+            CliTool::Gemini => {
+                // gemini --output-format stream-json --approval-mode xxx（stdin 非终端时为非交互模式）
+                cmd.arg("--output-format").arg("stream-json");
+                cmd.arg("--approval-mode")
+                    .arg(gemini_approval_mode(self.config.sandbox, self.config.yolo));
+
+                // 模型
+                if let Some(ref model) = backend.model {
+                    cmd.arg("--model").arg(model);
+                }
+
+                // 会话复用
+                if let Some(session_id) = session_id {
+                    cmd.arg("--resume").arg(session_id);
+                }
+            }
+            CliTool::Mock => {
+                // omcc mock-cli <script>：模型字段为脚本路径（相对路径基于工作目录）
+                let script = backend.model.as_ref().ok_or_else(|| {
+                    OmccError::ConfigError("mock 后端需要脚本路径，格式为 mock:<脚本>".to_string())
+                })?;
+                cmd.arg("mock-cli").arg(script);
+            }
+            CliTool::Replay => {
+                // omcc replay-cli <recording>：模型字段为记录文件路径（相对路径基于工作目录）
+                let recording = backend.model.as_ref().ok_or_else(|| {
+                    OmccError::ConfigError(
+                        "replay 后端需要记录文件路径，格式为 replay:<记录>".to_string(),
+                    )
+                })?;
+                cmd.arg("replay-cli").arg(recording);
+            }
+            CliTool::OpenAi => {
+                return Err(OmccError::ConfigError(
+                    "openai 后端通过 HTTP API 调用，没有对应的命令".to_string(),
+                ));
+            }
+            CliTool::Custom => {
+                // 按配置的参数模板展开占位符，没有值的项被省略
+                if let Some(command) = custom {
+                    let images: Vec<String> = self
+                        .config
+                        .attached_images()
+                        .into_iter()
+                        .chain(self.config.attached_files())
+                        .map(|file| file.to_string_lossy().into_owned())
+                        .collect();
+                    let prompt = (command.prompt != PromptDelivery::Stdin).then_some(prompt);
+                    let values = [
+                        ("prompt", prompt),
+                        ("model", backend.model.as_deref()),
+                        ("session_id", session_id),
+                        ("sandbox", Some(self.config.sandbox.as_arg())),
+                    ];
+                    cmd.args(command.expand_args(&values, &images));
+                }
+            }
         }
 
         Ok(cmd)
     }
 
+    /// 获取自定义命令后端的定义（其他后端返回 `None`）
+    fn custom_command(&self, backend: &BackendSpec) -> Result<Option<&CustomCommand>, OmccError> {
+        if backend.tool != CliTool::Custom {
+            return Ok(None);
+        }
+        let name = backend.command.as_deref().unwrap_or_default();
+        self.config.commands.get(name).map(Some).ok_or_else(|| {
+            OmccError::ConfigError(format!(
+                "未定义的自定义命令：{}（在配置文件的 commands 段中定义）",
+                name
+            ))
+        })
+    }
+
+    /// 按后端配置的环境变量策略设置子进程环境，返回是否清空了继承的环境变量
+    ///
+    /// 优先级：继承的环境变量 < 环境变量文件 < `set`；omcc 自身设置的变量在此之后写入
+    fn apply_env(&self, cmd: &mut Command, cli_tool: CliTool) -> Result<bool, OmccError> {
+        let Some(policy) = self
+            .config
+            .backend_settings
+            .get(&cli_tool)
+            .and_then(|settings| settings.env.as_ref())
+        else {
+            return Ok(false);
+        };
+        if policy.clear {
+            cmd.env_clear();
+            for (name, value) in std::env::vars_os() {
+                if name.to_str().is_some_and(|name| policy.passes(name)) {
+                    cmd.env(name, value);
+                }
+            }
+        }
+        for (name, value) in policy.file_vars()? {
+            cmd.env(name, value);
+        }
+        cmd.envs(&policy.set);
+        Ok(policy.clear)
+    }
+
+    /// 预览主后端将要执行的命令（不启动进程），环境变量的值已脱敏
+    pub fn preview_command(&self) -> Result<CommandPreview, OmccError> {
+        let backend = self.config.primary_backend();
+        if backend.tool == CliTool::OpenAi {
+            return self.preview_request(&backend);
+        }
+        let delivery = self.custom_command(&backend)?.map(|command| command.prompt);
+        let placeholder = match delivery {
+            Some(PromptDelivery::File) => "<prompt-file>",
+            _ => "<prompt>",
+        };
+        let cmd = self.build_command(&backend, self.config.session_id.as_deref(), placeholder)?;
+        let env_clear = self
+            .config
+            .backend_settings
+            .get(&backend.tool)
+            .and_then(|settings| settings.env.as_ref())
+            .is_some_and(|policy| policy.clear);
+
+        let cmd = cmd.as_std();
+        let mut args: Vec<String> = cmd
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        let prompt_via_stdin = match delivery {
+            Some(delivery) => delivery == PromptDelivery::Stdin,
+            None => backend.tool != CliTool::OpenCode,
+        };
+        if backend.tool == CliTool::OpenCode {
+            args.push("--".to_string());
+            args.push("<prompt>".to_string());
+        }
+        let env = cmd
+            .get_envs()
+            .filter_map(|(name, value)| {
+                let name = name.to_string_lossy().into_owned();
+                let value = redact::redact_env(&name, &value?.to_string_lossy());
+                Some((name, value))
+            })
+            .collect();
+
+        Ok(CommandPreview {
+            backend: backend.to_string(),
+            program: cmd.get_program().to_string_lossy().into_owned(),
+            args,
+            cwd: self.config.working_dir.clone(),
+            env_clear,
+            env,
+            prompt_via_stdin,
+        })
+    }
+
+    /// 以等价的 curl 命令预览 HTTP 后端的请求（API 密钥和提示词以占位符表示）
+    fn preview_request(&self, backend: &BackendSpec) -> Result<CommandPreview, OmccError> {
+        let settings = self.config.backend_settings.get(&CliTool::OpenAi);
+        let api = settings.and_then(|s| s.api.clone()).unwrap_or_default();
+        let model = http_model(backend, &api)?;
+        let mut args = vec![
+            "-N".to_string(),
+            format!("{}/chat/completions", api.base_url.trim_end_matches('/')),
+            "-H".to_string(),
+            "Content-Type: application/json".to_string(),
+        ];
+        if openai::api_key(settings)?.is_some() {
+            args.push("-H".to_string());
+            args.push("Authorization: Bearer [REDACTED]".to_string());
+        }
+        args.push("-d".to_string());
+        args.push(
+            serde_json::json!({
+                "model": model,
+                "messages": [{ "role": "user", "content": "<prompt>" }],
+                "stream": true,
+            })
+            .to_string(),
+        );
+        Ok(CommandPreview {
+            backend: backend.to_string(),
+            program: "curl".to_string(),
+            args,
+            cwd: self.config.working_dir.clone(),
+            env_clear: false,
+            env: Default::default(),
+            prompt_via_stdin: false,
+        })
+    }
+
+    /// 生成试运行报告：解析后的配置、主后端命令和完整提示词（不启动进程）
+    pub fn dry_run(&self) -> Result<DryRunReport, OmccError> {
+        let mut config = self.config.resolved();
+        redact::redact_config(&mut config);
+        let backend = self.config.primary_backend();
+        let warnings = self
+            .config
+            .check_backend(&backend, self.config.session_id.is_some())?;
+        Ok(DryRunReport {
+            config,
+            command: self.preview_command()?,
+            prompt: self.build_full_prompt(),
+            capabilities: self.config.capabilities(&backend),
+            warnings,
+        })
+    }
+
     /// 获取 Agent 的引导提示词（追加到用户 prompt 后面）
     fn get_guidance_prompt(&self) -> &'static str {
         match self.config.agent_type {
@@ -322,62 +1398,259 @@ impl AgentExecutor {
         )
     }
 
-    /// 判断是否应该重试
-    fn should_retry(&self, error: &OmccError) -> bool {
-        matches!(
-            error,
-            OmccError::IdleTimeout(_)
-                | OmccError::Timeout(_)
-                | OmccError::IoError(_)
-                | OmccError::UpstreamError(_)
-        )
-    }
-
     /// 将错误转换为结果
-    fn error_to_result(&self, error: OmccError) -> AgentResult {
+    ///
+    /// `attempts` 为每次尝试的错误记录，`retries` 为 [`retry_count`] 统计的实际重试次数
+    fn error_to_result(
+        &self,
+        error: OmccError,
+        attempts: Vec<AttemptError>,
+        retries: u32,
+    ) -> AgentResult {
         let error_kind = ErrorKind::from(&error);
-        let error_detail = match &error {
+        let mut detail = ErrorDetail {
+            message: error.to_string(),
+            exit_code: None,
+            last_lines: vec![],
+            json_decode_errors: None,
+            idle_timeout_s: None,
+            max_duration_s: None,
+            retries: Some(retries),
+            attempts,
+            violations: Vec::new(),
+            rolled_back: false,
+        };
+        match &error {
             OmccError::SubprocessError {
                 exit_code,
                 last_lines,
-            } => Some(ErrorDetail {
-                message: error.to_string(),
-                exit_code: Some(*exit_code),
-                last_lines: last_lines.clone(),
-                json_decode_errors: None,
-                idle_timeout_s: None,
-                max_duration_s: None,
-                retries: Some(self.config.get_max_retries()),
-            }),
-            OmccError::IdleTimeout(secs) => Some(ErrorDetail {
-                message: error.to_string(),
-                exit_code: None,
-                last_lines: vec![],
-                json_decode_errors: None,
-                idle_timeout_s: Some(*secs),
-                max_duration_s: None,
-                retries: Some(self.config.get_max_retries()),
-            }),
-            OmccError::Timeout(secs) => Some(ErrorDetail {
-                message: error.to_string(),
-                exit_code: None,
-                last_lines: vec![],
-                json_decode_errors: None,
-                idle_timeout_s: None,
-                max_duration_s: Some(*secs),
-                retries: Some(self.config.get_max_retries()),
-            }),
-            _ => Some(ErrorDetail {
-                message: error.to_string(),
-                exit_code: None,
-                last_lines: vec![],
-                json_decode_errors: None,
-                idle_timeout_s: None,
-                max_duration_s: None,
-                retries: Some(self.config.get_max_retries()),
-            }),
-        };
+            } => {
+                detail.exit_code = Some(*exit_code);
+                detail.last_lines = last_lines.clone();
+            }
+            OmccError::IdleTimeout(secs) => detail.idle_timeout_s = Some(*secs),
+            OmccError::Timeout(secs) => detail.max_duration_s = Some(*secs),
+            OmccError::PolicyViolation {
+                violations,
+                rolled_back,
+                ..
+            } => {
+                detail.violations = violations.clone();
+                detail.rolled_back = *rolled_back;
+            }
+            _ => {}
+        }
+
+        AgentResult::failure(
+            self.config.agent_type,
+            error.to_string(),
+            error_kind,
+            Some(detail),
+        )
+    }
+}
+
+/// OpenCode 读取的内联配置环境变量（与用户配置合并，优先级最高）
+const OPENCODE_CONFIG_ENV: &str = "OPENCODE_CONFIG_CONTENT";
+
+/// 将沙箱策略转换为 OpenCode 的权限配置，并合并到已有的内联配置中
+///
+/// 非交互模式下无法审批，因此不允许的操作一律为 `deny`。
+/// 只读策略完全禁用 shell：按命令前缀放行无法阻止重定向、`rg --pre`、`git diff --output` 等写入途径，
+/// 读取和搜索使用 OpenCode 内置的 read / grep / glob / list 工具
+fn opencode_config(sandbox: SandboxPolicy, existing: Option<&str>) -> String {
+    let permission = match sandbox {
+        SandboxPolicy::ReadOnly => serde_json::json!({
+            "edit": "deny",
+            "bash": "deny",
+            "webfetch": "allow",
+            "external_directory": "deny",
+        }),
+        SandboxPolicy::WorkspaceWrite => serde_json::json!({
+            "edit": "allow",
+            "bash": "allow",
+            "webfetch": "allow",
+            "external_directory": "deny",
+        }),
+        SandboxPolicy::DangerFullAccess => serde_json::json!({
+            "edit": "allow",
+            "bash": "allow",
+            "webfetch": "allow",
+            "external_directory": "allow",
+        }),
+    };
+
+    let mut config = existing
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(content).ok())
+        .filter(|value| value.is_object())
+        .unwrap_or_else(|| serde_json::json!({}));
+    config["permission"] = permission;
+    config.to_string()
+}
+
+/// 实际重试次数：所有后端上的尝试总数减一
+///
+/// 切换到备用后端后的首次尝试也计为一次重试，成功结果的执行指标和失败结果的错误详情都按此统计
+fn retry_count(attempted: usize) -> u32 {
+    attempted.saturating_sub(1) as u32
+}
+
+/// HTTP 后端使用的模型：`openai:<model>` 优先，其次为配置文件中的默认模型
+fn http_model(backend: &BackendSpec, api: &ApiSettings) -> Result<String, OmccError> {
+    backend
+        .model
+        .clone()
+        .or_else(|| api.model.clone())
+        .ok_or_else(|| {
+            OmccError::ConfigError(
+                "openai 后端需要模型，格式为 openai:<模型>，或在配置文件 backends.openai.api.model 中设置"
+                    .to_string(),
+            )
+        })
+}
+
+/// 通过文件传递的提示词，执行结束后删除
+struct PromptFile(PathBuf);
+
+impl PromptFile {
+    /// 将提示词写入临时文件（仅当前用户可读写）
+    fn create(prompt: &str) -> Result<Self, OmccError> {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let path = std::env::temp_dir().join(format!("omcc-prompt-{}.md", uuid::Uuid::new_v4()));
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?;
+        let guard = Self(path);
+        file.write_all(prompt.as_bytes())?;
+        Ok(guard)
+    }
+}
+
+impl Drop for PromptFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// 将沙箱策略转换为 Gemini CLI 的审批模式
+///
+/// 非交互模式下需要审批的工具不会执行：只读时不自动批准任何工具，
+/// 可写时自动批准文件编辑，完全访问（或 `yolo`）时自动批准全部工具
+fn gemini_approval_mode(sandbox: SandboxPolicy, yolo: bool) -> &'static str {
+    match sandbox {
+        _ if yolo => "yolo",
+        SandboxPolicy::ReadOnly => "default",
+        SandboxPolicy::WorkspaceWrite => "auto_edit",
+        SandboxPolicy::DangerFullAccess => "yolo",
+    }
+}
+
+/// 读取已在命令上显式设置的环境变量
+fn configured_env(cmd: &Command, name: &str) -> Option<String> {
+    cmd.as_std()
+        .get_envs()
+        .find(|(key, _)| *key == name)
+        .and_then(|(_, value)| value)
+        .map(|value| value.to_string_lossy().into_owned())
+}
+
+/// 根据只读任务的改动报告生成沙箱违规错误
+fn sandbox_violation(changes: &ChangeReport) -> OmccError {
+    let paths: Vec<&str> = changed_paths(changes).collect();
+    OmccError::SandboxViolation(format!("只读任务修改了工作区：{}", list_paths(&paths)))
+}
+
+/// 改动报告中的所有文件路径
+fn changed_paths(changes: &ChangeReport) -> impl Iterator<Item = &str> {
+    changes
+        .added
+        .iter()
+        .chain(&changes.modified)
+        .chain(&changes.deleted)
+        .map(|file| file.path.as_str())
+}
+
+/// 列出文件路径（最多 10 个）
+fn list_paths(paths: &[&str]) -> String {
+    const MAX_LISTED: usize = 10;
+    let mut listed = paths
+        .iter()
+        .take(MAX_LISTED)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if paths.len() > MAX_LISTED {
+        listed.push_str(&format!(" 等 {} 个文件", paths.len()));
+    }
+    listed
+}
+
+/// 将上游返回的错误信息转换为错误类型（识别限流）
+fn upstream_error(message: &str) -> OmccError {
+    if is_rate_limit_message(message) {
+        OmccError::RateLimited(message.to_string())
+    } else {
+        OmccError::UpstreamError(message.to_string())
+    }
+}
+
+/// 判断一段上游输出是否表示限流或额度用尽
+fn is_rate_limit_message(text: &str) -> bool {
+    const PATTERNS: [&str; 6] = [
+        "rate limit",
+        "rate_limit",
+        "ratelimit",
+        "too many requests",
+        "insufficient_quota",
+        "quota exceeded",
+    ];
+    let text = text.to_lowercase();
+    PATTERNS.iter().any(|p| text.contains(p))
+}
+
+/// 等待到指定时间点；未设置时永不完成
+async fn sleep_until_opt(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 等待后台写入 stdin 的任务结束；未设置时永不完成
+async fn join_opt(
+    task: &mut Option<JoinHandle<std::io::Result<ChildStdin>>>,
+) -> std::io::Result<ChildStdin> {
+    match task {
+        Some(task) => task.await.map_err(std::io::Error::other)?,
+        None => std::future::pending().await,
+    }
+}
+
+/// 构造 Claude stream-json 输入格式的用户消息
+fn claude_user_message(text: &str) -> String {
+    let message = serde_json::json!({
+        "type": "user",
+        "message": {
+            "role": "user",
+            "content": [{ "type": "text", "text": text }],
+        },
+    });
+    format!("{}\n", message)
+}
 
-        AgentResult::failure(self.config.agent_type, error.to_string(), error_kind, error_detail)
+/// 通过仍打开的 stdin 发送收尾消息，返回是否送达
+async fn send_wrap_up(stdin: &mut Option<ChildStdin>) -> bool {
+    let Some(handle) = stdin.as_mut() else {
+        return false;
+    };
+    let message = claude_user_message(WRAP_UP_MESSAGE);
+    if handle.write_all(message.as_bytes()).await.is_err() || handle.flush().await.is_err() {
+        *stdin = None;
+        return false;
     }
+    true
 }
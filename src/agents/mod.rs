@@ -3,5 +3,7 @@
 //! 定义和管理所有 AI Agent
 
 pub mod executor;
+pub mod openai;
 
 pub use executor::AgentExecutor;
+pub use tokio_util::sync::CancellationToken;
@@ -0,0 +1,255 @@
+//! OpenAI 兼容 HTTP 后端
+//!
+//! 直接以流式请求调用 `<base_url>/chat/completions`，不依赖第三方 CLI。
+//! 会话历史保存在状态目录 `sessions/` 下，传入 SESSION_ID 时加载历史消息继续对话
+
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::state::{state_subdir, write_atomic};
+use crate::types::{ApiSettings, BackendSettings, OmccError, TokenUsage};
+
+/// 对话消息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    /// 角色：`user` / `assistant`
+    pub role: String,
+
+    /// 内容
+    pub content: String,
+}
+
+impl Message {
+    /// 用户消息
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: content.into(),
+        }
+    }
+
+    /// 助手消息
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: "assistant".to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+/// 流式响应中的事件
+#[derive(Debug)]
+pub enum ChatEvent {
+    /// 增量内容
+    Delta(String),
+    /// token 用量（通常在最后一个数据块中）
+    Usage(TokenUsage),
+}
+
+/// 流式响应
+pub struct ChatStream {
+    body: futures_util::stream::BoxStream<'static, reqwest::Result<Vec<u8>>>,
+    buffer: Vec<u8>,
+    pending: std::collections::VecDeque<ChatEvent>,
+    done: bool,
+}
+
+/// 会话 ID 只能包含字母、数字、`-` 和 `_`（用作文件名）
+fn validate_session_id(id: &str) -> Result<(), OmccError> {
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(OmccError::ConfigError(format!("无效的会话 ID：{}", id)));
+    }
+    Ok(())
+}
+
+/// 读取会话历史
+pub fn load_history(session_id: &str) -> Result<Vec<Message>, OmccError> {
+    validate_session_id(session_id)?;
+    let path = state_subdir("sessions")?.join(format!("{}.json", session_id));
+    let content = std::fs::read_to_string(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => {
+            OmccError::ConfigError(format!("会话 {} 不存在", session_id))
+        }
+        _ => OmccError::IoError(e),
+    })?;
+    serde_json::from_str(&content).map_err(|e| OmccError::JsonDecode(e.to_string()))
+}
+
+/// 保存会话历史
+pub fn save_history(session_id: &str, messages: &[Message]) -> Result<(), OmccError> {
+    validate_session_id(session_id)?;
+    let path = state_subdir("sessions")?.join(format!("{}.json", session_id));
+    let content =
+        serde_json::to_vec_pretty(messages).map_err(|e| OmccError::JsonDecode(e.to_string()))?;
+    write_atomic(&path, &content)?;
+    Ok(())
+}
+
+/// 查找 API 密钥
+///
+/// 优先级：`api.api_key` > `env.set` > `env.file` > omcc 自身的环境变量（清空环境变量时需在 `env.pass` 中）
+pub fn api_key(settings: Option<&BackendSettings>) -> Result<Option<String>, OmccError> {
+    let default_api = ApiSettings::default();
+    let api = settings
+        .and_then(|s| s.api.as_ref())
+        .unwrap_or(&default_api);
+    if let Some(ref key) = api.api_key {
+        return Ok(Some(key.clone()));
+    }
+    let name = api.api_key_env.as_str();
+    let env = settings.and_then(|s| s.env.as_ref());
+    if let Some(env) = env {
+        if let Some(value) = env.set.get(name) {
+            return Ok(Some(value.clone()));
+        }
+        if let Some((_, value)) = env.file_vars()?.into_iter().rev().find(|(k, _)| k == name) {
+            return Ok(Some(value));
+        }
+        if env.clear && !env.passes(name) {
+            return Ok(None);
+        }
+    }
+    Ok(std::env::var(name).ok().filter(|key| !key.is_empty()))
+}
+
+/// 发送流式请求，返回响应流
+pub async fn start(
+    settings: &ApiSettings,
+    api_key: Option<&str>,
+    model: &str,
+    messages: &[Message],
+) -> Result<ChatStream, OmccError> {
+    let url = format!(
+        "{}/chat/completions",
+        settings.base_url.trim_end_matches('/')
+    );
+    let body = serde_json::json!({
+        "model": model,
+        "messages": messages,
+        "stream": true,
+        "stream_options": { "include_usage": true },
+    });
+    let mut request = reqwest::Client::new()
+        .post(&url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string());
+    if let Some(key) = api_key {
+        request = request.bearer_auth(key);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| OmccError::UpstreamError(format!("请求 {} 失败：{}", url, e)))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        let message = error_message(&text).unwrap_or(text);
+        let message = format!("HTTP {}：{}", status.as_u16(), message.trim());
+        return Err(match status.as_u16() {
+            429 => OmccError::RateLimited(message),
+            401 | 403 | 404 => OmccError::ConfigError(message),
+            _ => OmccError::UpstreamError(message),
+        });
+    }
+
+    Ok(ChatStream {
+        body: response
+            .bytes_stream()
+            .map(|chunk| chunk.map(|bytes| bytes.to_vec()))
+            .boxed(),
+        buffer: Vec::new(),
+        pending: Default::default(),
+        done: false,
+    })
+}
+
+impl ChatStream {
+    /// 读取下一个事件，响应结束时返回 `None`
+    pub async fn next_event(&mut self) -> Result<Option<ChatEvent>, OmccError> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+            if self.done {
+                return Ok(None);
+            }
+            // 按行解析 SSE：`data: <JSON>`，以 `data: [DONE]` 结束
+            if let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+                self.parse_line(line.trim())?;
+                continue;
+            }
+            match self.body.next().await {
+                Some(Ok(chunk)) => self.buffer.extend_from_slice(&chunk),
+                Some(Err(e)) => {
+                    return Err(OmccError::UpstreamError(format!("读取响应失败：{}", e)))
+                }
+                None => {
+                    // 处理没有换行结尾的最后一行
+                    let rest = std::mem::take(&mut self.buffer);
+                    self.parse_line(String::from_utf8_lossy(&rest).trim())?;
+                    self.done = true;
+                }
+            }
+        }
+    }
+
+    /// 解析一行 SSE
+    fn parse_line(&mut self, line: &str) -> Result<(), OmccError> {
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            return Ok(());
+        };
+        if data == "[DONE]" {
+            self.done = true;
+            return Ok(());
+        }
+        let chunk: serde_json::Value =
+            serde_json::from_str(data).map_err(|e| OmccError::JsonDecode(e.to_string()))?;
+        if let Some(message) = error_message(data) {
+            return Err(OmccError::UpstreamError(message));
+        }
+        if let Some(content) = chunk
+            .pointer("/choices/0/delta/content")
+            .and_then(|v| v.as_str())
+            .filter(|c| !c.is_empty())
+        {
+            self.pending
+                .push_back(ChatEvent::Delta(content.to_string()));
+        }
+        if let Some(usage) = chunk.get("usage").and_then(parse_usage) {
+            self.pending.push_back(ChatEvent::Usage(usage));
+        }
+        Ok(())
+    }
+}
+
+/// 从 `usage` 对象中提取 token 用量（兼容 `prompt_tokens` / `input_tokens` 两种命名）
+pub fn parse_usage(usage: &serde_json::Value) -> Option<TokenUsage> {
+    let field = |names: [&str; 2]| names.iter().find_map(|n| usage.get(n)?.as_u64());
+    let input_tokens = field(["prompt_tokens", "input_tokens"]);
+    let output_tokens = field(["completion_tokens", "output_tokens"]);
+    if input_tokens.is_none() && output_tokens.is_none() {
+        return None;
+    }
+    Some(TokenUsage {
+        input_tokens: input_tokens.unwrap_or(0),
+        output_tokens: output_tokens.unwrap_or(0),
+    })
+}
+
+/// 提取 `{"error": {"message": ...}}` 或 `{"error": "..."}` 中的错误信息
+fn error_message(body: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    let error = value.get("error")?;
+    error
+        .get("message")
+        .and_then(|m| m.as_str())
+        .or_else(|| error.as_str())
+        .map(str::to_string)
+}
@@ -3,8 +3,11 @@
 //! 使用 clap 定义命令行参数和子命令
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
+use crate::types::{BackendSpec, ErrorKind, RetryPolicy};
+
 /// Oh-My-ClaudeCode CLI - AI 多代理协作命令行工具
 #[derive(Parser, Debug)]
 #[command(name = "omcc")]
@@ -53,6 +56,58 @@ pub struct Cli {
 /// 子命令定义
 #[derive(Subcommand, Debug)]
 pub enum Commands {
+    /// Agent 子命令
+    #[command(flatten)]
+    Agent(AgentCommand),
+
+    /// 提交后台任务，立即返回任务 ID
+    #[command(name = "submit")]
+    Submit(SubmitArgs),
+
+    /// 查询后台任务状态
+    #[command(name = "status")]
+    Status(JobIdArgs),
+
+    /// 等待后台任务结束并输出结果
+    #[command(name = "wait")]
+    Wait(WaitArgs),
+
+    /// 取消后台任务
+    #[command(name = "cancel")]
+    Cancel(JobIdArgs),
+
+    /// 在后台进程中执行任务（由 submit 内部调用）
+    #[command(name = "job-run", hide = true)]
+    JobRun(JobIdArgs),
+
+    /// 按脚本回放事件（mock 后端，由执行器内部调用）
+    #[command(name = "mock-cli", hide = true)]
+    MockCli(MockCliArgs),
+
+    /// 回放调用记录（replay 后端，由执行器内部调用）
+    #[command(name = "replay-cli", hide = true)]
+    ReplayCli(ReplayCliArgs),
+
+    /// 列出所有可用的 Agent
+    #[command(name = "list")]
+    List,
+
+    /// 显示版本和配置信息
+    #[command(name = "info")]
+    Info,
+
+    /// MCP 服务（Model Context Protocol）
+    #[command(name = "mcp")]
+    Mcp(McpArgs),
+
+    /// 启动本地 HTTP/JSON API 服务
+    #[command(name = "serve")]
+    Serve(ServeArgs),
+}
+
+/// Agent 子命令
+#[derive(Subcommand, Debug)]
+pub enum AgentCommand {
     /// 调用 Reviewer 进行代码审核
     #[command(name = "reviewer")]
     Reviewer(ReviewerArgs),
@@ -72,14 +127,103 @@ pub enum Commands {
     /// 调用 Looker 进行多模态分析
     #[command(name = "looker")]
     Looker(LookerArgs),
+}
 
-    /// 列出所有可用的 Agent
-    #[command(name = "list")]
-    List,
+impl AgentCommand {
+    /// 获取子命令对应的 Agent 类型
+    pub fn agent_type(&self) -> crate::types::AgentType {
+        use crate::types::AgentType;
+        match self {
+            AgentCommand::Reviewer(_) => AgentType::Reviewer,
+            AgentCommand::Advisor(_) => AgentType::Advisor,
+            AgentCommand::Chore(_) => AgentType::Chore,
+            AgentCommand::Researcher(_) => AgentType::Researcher,
+            AgentCommand::Looker(_) => AgentType::Looker,
+        }
+    }
 
-    /// 显示版本和配置信息
-    #[command(name = "info")]
-    Info,
+    /// 获取子命令的通用参数
+    pub fn common(&self) -> &CommonAgentArgs {
+        match self {
+            AgentCommand::Reviewer(args) => &args.common,
+            AgentCommand::Advisor(args) => &args.common,
+            AgentCommand::Chore(args) => &args.common,
+            AgentCommand::Researcher(args) => &args.common,
+            AgentCommand::Looker(args) => &args.common,
+        }
+    }
+}
+
+/// 后台任务提交参数
+#[derive(Args, Debug)]
+pub struct SubmitArgs {
+    /// 要在后台执行的 Agent
+    #[command(subcommand)]
+    pub agent: AgentCommand,
+}
+
+/// mock 后端参数
+#[derive(Args, Debug)]
+pub struct MockCliArgs {
+    /// 脚本路径
+    #[arg(value_name = "SCRIPT")]
+    pub script: PathBuf,
+}
+
+/// replay 后端参数
+#[derive(Args, Debug)]
+pub struct ReplayCliArgs {
+    /// 记录文件路径
+    #[arg(value_name = "RECORDING")]
+    pub recording: PathBuf,
+}
+
+/// 后台任务 ID 参数
+#[derive(Args, Debug)]
+pub struct JobIdArgs {
+    /// 任务 ID
+    #[arg(value_name = "JOB_ID")]
+    pub id: String,
+}
+
+/// 后台任务等待参数
+#[derive(Args, Debug)]
+pub struct WaitArgs {
+    /// 任务 ID
+    #[arg(value_name = "JOB_ID")]
+    pub id: String,
+
+    /// 最长等待时间（秒），超时后以退出码 2 返回
+    #[arg(long = "timeout", short = 't')]
+    pub timeout: Option<u64>,
+}
+
+/// HTTP 服务参数
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// 监听地址
+    #[arg(long = "listen", short = 'l', default_value = crate::server::DEFAULT_LISTEN)]
+    pub listen: SocketAddr,
+
+    /// 访问令牌：请求需携带 `Authorization: Bearer <令牌>`（监听非回环地址时必填）
+    #[arg(long = "token", env = "OMCC_SERVE_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+}
+
+/// MCP 子命令参数
+#[derive(Args, Debug)]
+pub struct McpArgs {
+    /// MCP 操作
+    #[command(subcommand)]
+    pub command: McpCommands,
+}
+
+/// MCP 子命令
+#[derive(Subcommand, Debug)]
+pub enum McpCommands {
+    /// 通过 stdio JSON-RPC 提供 MCP 服务，将各 Agent 暴露为工具
+    #[command(name = "serve")]
+    Serve,
 }
 
 /// 沙箱策略枚举
@@ -106,6 +250,70 @@ impl From<SandboxArg> for crate::types::SandboxPolicy {
     }
 }
 
+/// 只读任务工作区检查方式枚举
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SandboxCheckArg {
+    /// 不检查
+    #[value(name = "off")]
+    Off,
+    /// 发现改动时输出警告
+    #[value(name = "warn")]
+    Warn,
+    /// 发现改动时执行失败
+    #[value(name = "fail")]
+    Fail,
+}
+
+impl From<SandboxCheckArg> for crate::types::SandboxCheck {
+    fn from(arg: SandboxCheckArg) -> Self {
+        match arg {
+            SandboxCheckArg::Off => crate::types::SandboxCheck::Off,
+            SandboxCheckArg::Warn => crate::types::SandboxCheck::Warn,
+            SandboxCheckArg::Fail => crate::types::SandboxCheck::Fail,
+        }
+    }
+}
+
+/// 工作区隔离方式枚举
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum IsolateArg {
+    /// 在临时 git worktree 中执行
+    #[value(name = "worktree")]
+    Worktree,
+}
+
+impl From<IsolateArg> for crate::types::IsolationMode {
+    fn from(arg: IsolateArg) -> Self {
+        match arg {
+            IsolateArg::Worktree => crate::types::IsolationMode::Worktree,
+        }
+    }
+}
+
+/// 隔离工作树改动处理方式枚举
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum WorktreeActionArg {
+    /// 应用到主工作区
+    #[value(name = "apply")]
+    Apply,
+    /// 保存为新分支
+    #[value(name = "branch")]
+    Branch,
+    /// 丢弃（只返回 diff）
+    #[value(name = "discard")]
+    Discard,
+}
+
+impl From<WorktreeActionArg> for crate::types::WorktreeAction {
+    fn from(arg: WorktreeActionArg) -> Self {
+        match arg {
+            WorktreeActionArg::Apply => crate::types::WorktreeAction::Apply,
+            WorktreeActionArg::Branch => crate::types::WorktreeAction::Branch,
+            WorktreeActionArg::Discard => crate::types::WorktreeAction::Discard,
+        }
+    }
+}
+
 /// 通用 Agent 参数
 #[derive(Args, Debug, Clone)]
 pub struct CommonAgentArgs {
@@ -117,10 +325,18 @@ pub struct CommonAgentArgs {
     #[arg(long = "sandbox", short = 's')]
     pub sandbox: Option<SandboxArg>,
 
+    /// 只读任务结束后检查工作区是否被修改（默认 fail）
+    #[arg(long = "sandbox-check", value_name = "MODE")]
+    pub sandbox_check: Option<SandboxCheckArg>,
+
     /// 会话 ID（用于多轮对话）
     #[arg(long = "session-id", short = 'S', env = "OMCC_SESSION_ID")]
     pub session_id: Option<String>,
 
+    /// 附加图片文件（后端不支持图片时报错）
+    #[arg(long = "image", short = 'I')]
+    pub images: Vec<PathBuf>,
+
     /// 空闲超时（秒）
     #[arg(long = "timeout", short = 't')]
     pub timeout: Option<u64>,
@@ -129,10 +345,74 @@ pub struct CommonAgentArgs {
     #[arg(long = "max-duration", short = 'd')]
     pub max_duration: Option<u64>,
 
+    /// 软截止时间（秒）：到达后提醒 Agent 收尾（仅 Claude 支持）
+    #[arg(long = "soft-deadline")]
+    pub soft_deadline: Option<u64>,
+
     /// 最大重试次数
     #[arg(long = "max-retries", short = 'r')]
     pub max_retries: Option<u32>,
 
+    /// 重试策略
+    #[command(flatten)]
+    pub retry: RetryArgs,
+
+    /// 主后端，格式为 `<cli>[:<model>]`（如 `claude:sonnet`），自定义命令为 `custom:<name>[:<model>]`
+    #[arg(long = "backend", value_name = "SPEC")]
+    pub backend: Option<BackendSpec>,
+
+    /// 备用后端（可重复或逗号分隔，按顺序尝试）
+    #[arg(long = "fallback", value_name = "SPEC", value_delimiter = ',')]
+    pub fallbacks: Vec<BackendSpec>,
+
+    /// 触发切换备用后端的错误类型（逗号分隔，默认 command_not_found,upstream_error,rate_limited,circuit_open,throttled）
+    #[arg(long = "fallback-on", value_name = "KINDS", value_delimiter = ',')]
+    pub fallback_on: Option<Vec<ErrorKind>>,
+
+    /// 工作区写锁被占用时最多等待的秒数（默认立即失败，仅对可写沙箱策略生效）
+    #[arg(long = "wait-lock", value_name = "SECS")]
+    pub wait_lock: Option<u64>,
+
+    /// 每次尝试前创建 Git 检查点，失败重试前恢复工作区（仅对可写沙箱策略生效）
+    #[arg(long = "checkpoint")]
+    pub checkpoint: bool,
+
+    /// 发送前对提示词中的密钥和令牌脱敏
+    #[arg(long = "redact-prompt")]
+    pub redact_prompt: bool,
+
+    /// 在改动报告中附带统一 diff（仅对可写沙箱策略生效）
+    #[arg(long = "diff")]
+    pub diff: bool,
+
+    /// 打印将要执行的底层 CLI 命令和环境变量（已脱敏）后退出，不执行任务
+    #[arg(long = "print-command")]
+    pub print_command: bool,
+
+    /// 打印解析后的配置、底层 CLI 命令和完整提示词后退出，不执行任务
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// 将每次尝试的命令行、提示词、输出和退出状态保存到目录（可用 replay 后端回放）
+    #[arg(long = "record", value_name = "DIR")]
+    pub record: Option<PathBuf>,
+
+    /// 调用记录保留未脱敏的原始内容（默认与结果一样脱敏）
+    #[arg(long = "record-raw", requires = "record")]
+    pub record_raw: bool,
+
+    /// 在隔离的 git worktree 中执行，主工作区保持不变（仅对可写沙箱策略生效）
+    #[arg(long = "isolate", value_name = "MODE")]
+    pub isolate: Option<IsolateArg>,
+
+    /// 隔离工作树的基准引用（默认 HEAD）
+    #[arg(long = "isolate-base", value_name = "REF", requires = "isolate")]
+    pub isolate_base: Option<String>,
+
+    /// 隔离工作树中改动的处理方式（默认 branch）
+    #[arg(long = "on-finish", value_name = "ACTION", requires = "isolate")]
+    pub on_finish: Option<WorktreeActionArg>,
+
     /// 返回完整消息
     #[arg(long = "return-all-messages")]
     pub return_all_messages: bool,
@@ -150,6 +430,79 @@ pub struct CommonAgentArgs {
     pub model: Option<String>,
 }
 
+/// 重试策略参数（覆盖配置文件中的对应字段）
+#[derive(Args, Debug, Clone, Default)]
+#[command(next_help_heading = "重试策略")]
+pub struct RetryArgs {
+    /// 首次重试前的等待时间（毫秒）
+    #[arg(long = "retry-delay", value_name = "MS")]
+    pub initial_delay_ms: Option<u64>,
+
+    /// 退避倍数
+    #[arg(long = "retry-multiplier", value_name = "FACTOR")]
+    pub multiplier: Option<f64>,
+
+    /// 单次等待时间上限（毫秒）
+    #[arg(long = "retry-max-delay", value_name = "MS")]
+    pub max_delay_ms: Option<u64>,
+
+    /// 抖动比例（0.0 ~ 1.0）
+    #[arg(long = "retry-jitter", value_name = "RATIO", value_parser = parse_ratio)]
+    pub jitter: Option<f64>,
+
+    /// 可重试的错误类型（逗号分隔，如 idle_timeout,upstream_error）
+    #[arg(long = "retry-on", value_name = "KINDS", value_delimiter = ',')]
+    pub retry_on: Option<Vec<ErrorKind>>,
+
+    /// 总重试时间预算（秒）
+    #[arg(long = "retry-budget", value_name = "SECS")]
+    pub budget_s: Option<u64>,
+}
+
+impl RetryArgs {
+    /// 是否设置了任何重试参数
+    pub fn is_set(&self) -> bool {
+        self.initial_delay_ms.is_some()
+            || self.multiplier.is_some()
+            || self.max_delay_ms.is_some()
+            || self.jitter.is_some()
+            || self.retry_on.is_some()
+            || self.budget_s.is_some()
+    }
+
+    /// 将参数覆盖到重试策略上
+    pub fn apply(&self, policy: &mut RetryPolicy) {
+        if let Some(delay) = self.initial_delay_ms {
+            policy.initial_delay_ms = delay;
+        }
+        if let Some(multiplier) = self.multiplier {
+            policy.multiplier = multiplier;
+        }
+        if let Some(max_delay) = self.max_delay_ms {
+            policy.max_delay_ms = max_delay;
+        }
+        if let Some(jitter) = self.jitter {
+            policy.jitter = jitter;
+        }
+        if let Some(ref retry_on) = self.retry_on {
+            policy.retry_on = retry_on.iter().copied().collect();
+        }
+        if let Some(budget) = self.budget_s {
+            policy.budget_s = Some(budget);
+        }
+    }
+}
+
+/// 解析 `[0, 1]` 区间的比例（拒绝 NaN 和无穷大）
+fn parse_ratio(value: &str) -> Result<f64, String> {
+    let ratio: f64 = value.parse().map_err(|e| format!("{}", e))?;
+    if (0.0..=1.0).contains(&ratio) {
+        Ok(ratio)
+    } else {
+        Err(format!("必须在 0.0 到 1.0 之间：{}", value))
+    }
+}
+
 /// Reviewer Agent 参数
 #[derive(Args, Debug)]
 pub struct ReviewerArgs {
@@ -165,10 +518,6 @@ pub struct ReviewerArgs {
     #[arg(long = "file", short = 'f')]
     pub from_file: Option<PathBuf>,
 
-    /// 附加图片文件
-    #[arg(long = "image", short = 'I')]
-    pub images: Vec<PathBuf>,
-
     /// 跳过 Git 仓库检查
     #[arg(long = "skip-git-check")]
     pub skip_git_repo_check: bool,
@@ -0,0 +1,339 @@
+//! 后端熔断与限流
+//!
+//! 每个底层 CLI 的状态保存在状态目录 `backends/<cli>.json` 中，读写时持有 `backends/<cli>.lock`
+//! 文件锁，因此同一台机器上的多个 omcc 进程共享同一个熔断器和限流器。
+//!
+//! 熔断器在冷却时间结束后进入半开状态：只放行一次试探调用，试探成功后关闭，失败则重新熔断
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::process;
+use crate::state::{state_subdir, write_atomic, FileLock};
+use crate::types::{
+    BackendSettings, CircuitBreakerPolicy, CliTool, ErrorKind, OmccError, RateLimitPolicy,
+};
+
+/// 等待限流时的最长轮询间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// 后端的持久化状态
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct BackendState {
+    /// 连续失败次数
+    consecutive_failures: u32,
+
+    /// 熔断截止时间（Unix 毫秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    open_until_ms: Option<u64>,
+
+    /// 令牌桶中剩余的令牌（未初始化时视为满）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tokens: Option<f64>,
+
+    /// 令牌桶上次补充时间（Unix 毫秒）
+    refilled_at_ms: u64,
+
+    /// 正在运行的调用
+    active: Vec<ActiveSlot>,
+
+    /// 半开状态下正在进行的试探调用
+    #[serde(skip_serializing_if = "Option::is_none")]
+    probe: Option<ActiveSlot>,
+}
+
+impl BackendState {
+    /// 释放由 `id` 持有的试探名额
+    fn release_probe(&mut self, id: Option<&str>) {
+        if id.is_some() && self.probe.as_ref().map(|p| p.id.as_str()) == id {
+            self.probe = None;
+        }
+    }
+}
+
+/// 正在运行的调用
+#[derive(Debug, Serialize, Deserialize)]
+struct ActiveSlot {
+    /// 调用 ID
+    id: String,
+
+    /// 所属 omcc 进程
+    pid: u32,
+
+    /// 所属进程的启动时间（用于识别 PID 复用）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start: Option<u64>,
+}
+
+impl ActiveSlot {
+    /// 为当前进程登记一个新名额
+    fn new() -> Self {
+        let pid = std::process::id();
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            pid,
+            start: process::start_time(pid),
+        }
+    }
+
+    /// 所属进程是否仍在运行（PID 已被其他进程复用时视为已退出）
+    fn holder_alive(&self) -> bool {
+        process::is_running(self.pid, self.start)
+    }
+}
+
+/// 获取许可的判定结果
+enum Admission {
+    /// 允许调用，附带并发名额和试探调用 ID
+    Granted(Option<String>, Option<String>),
+    /// 熔断中，附带剩余冷却时间和连续失败次数
+    Open(Duration, u32),
+    /// 半开状态下已有试探调用在进行，附带连续失败次数
+    Probing(u32),
+    /// 达到限流上限，附带建议等待时间和原因
+    Wait(Duration, &'static str),
+}
+
+/// 后端调用许可
+///
+/// 持有期间占用一个并发名额（半开状态下还占用唯一的试探名额），离开作用域时释放；
+/// 调用结束后通过 `record` 更新熔断器
+pub struct BackendPermit {
+    tool: CliTool,
+    slot: Option<String>,
+    probe: Option<String>,
+    breaker: Option<CircuitBreakerPolicy>,
+}
+
+impl BackendPermit {
+    /// 记录本次调用结果（`None` 表示成功）
+    pub fn record(&self, error: Option<ErrorKind>) {
+        let Some(ref breaker) = self.breaker else {
+            return;
+        };
+        let result = with_state(self.tool, |state| {
+            state.release_probe(self.probe.as_deref());
+            match error {
+                None => {
+                    state.consecutive_failures = 0;
+                    state.open_until_ms = None;
+                }
+                Some(kind) if counts_as_failure(kind) => {
+                    state.consecutive_failures += 1;
+                    if state.consecutive_failures >= breaker.failure_threshold.max(1) {
+                        state.open_until_ms = Some(unix_ms() + breaker.cooldown_s * 1000);
+                    }
+                }
+                Some(_) => {}
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("[OMCC] 更新 {} 熔断状态失败：{}", self.tool.command(), e);
+        }
+    }
+}
+
+impl Drop for BackendPermit {
+    fn drop(&mut self) {
+        let (slot, probe) = (self.slot.take(), self.probe.take());
+        if slot.is_none() && probe.is_none() {
+            return;
+        }
+        let _ = with_state(self.tool, |state| {
+            state.active.retain(|s| Some(&s.id) != slot.as_ref());
+            // 未记录结果的试探调用（如被取消）释放试探名额，由下一次调用继续试探
+            state.release_probe(probe.as_deref());
+        });
+    }
+}
+
+/// 获取后端调用许可
+///
+/// 未配置熔断和限流时返回 `None`；熔断中（包括半开状态下已有试探调用时）立即返回 `CircuitOpen`；
+/// 达到限流上限时最多等待 `max_wait_s` 秒，仍未获得许可则返回 `Throttled`
+pub async fn acquire(
+    tool: CliTool,
+    settings: Option<&BackendSettings>,
+    cancel: &CancellationToken,
+) -> Result<Option<BackendPermit>, OmccError> {
+    let Some(settings) = settings else {
+        return Ok(None);
+    };
+    if settings.circuit_breaker.is_none() && settings.rate_limit.is_none() {
+        return Ok(None);
+    }
+
+    let max_wait = Duration::from_secs(
+        settings
+            .rate_limit
+            .as_ref()
+            .map(|r| r.max_wait_s)
+            .unwrap_or(0),
+    );
+    let start = Instant::now();
+    loop {
+        let admission = with_state(tool, |state| admit(state, settings))?;
+        match admission {
+            Admission::Granted(slot, probe) => {
+                return Ok(Some(BackendPermit {
+                    tool,
+                    slot,
+                    probe,
+                    breaker: settings.circuit_breaker.clone(),
+                }));
+            }
+            Admission::Open(remaining, failures) => {
+                return Err(OmccError::CircuitOpen(format!(
+                    "{} 连续失败 {} 次，{} 秒后恢复",
+                    tool.command(),
+                    failures,
+                    remaining.as_secs().max(1)
+                )));
+            }
+            Admission::Probing(failures) => {
+                return Err(OmccError::CircuitOpen(format!(
+                    "{} 连续失败 {} 次，正在试探是否恢复",
+                    tool.command(),
+                    failures
+                )));
+            }
+            Admission::Wait(hint, reason) => {
+                if start.elapsed() + hint > max_wait {
+                    return Err(OmccError::Throttled(format!(
+                        "{} 已达到{}上限",
+                        tool.command(),
+                        reason
+                    )));
+                }
+                tokio::select! {
+                    _ = cancel.cancelled() => return Err(OmccError::Cancelled),
+                    _ = tokio::time::sleep(hint.min(POLL_INTERVAL)) => {}
+                }
+            }
+        }
+    }
+}
+
+/// 判定能否发起调用，允许时更新令牌桶并登记并发名额（半开状态下登记试探调用）
+fn admit(state: &mut BackendState, settings: &BackendSettings) -> Admission {
+    let now = unix_ms();
+
+    // 清理已退出进程遗留的并发名额和试探名额
+    state.active.retain(ActiveSlot::holder_alive);
+    if state.probe.as_ref().is_some_and(|p| !p.holder_alive()) {
+        state.probe = None;
+    }
+
+    // 冷却结束后熔断截止时间仍然保留，直到试探调用成功：期间为半开状态
+    let open_until = state
+        .open_until_ms
+        .filter(|_| settings.circuit_breaker.is_some());
+    let half_open = match open_until {
+        Some(until) if now < until => {
+            return Admission::Open(
+                Duration::from_millis(until - now),
+                state.consecutive_failures,
+            );
+        }
+        Some(_) => {
+            if state.probe.is_some() {
+                return Admission::Probing(state.consecutive_failures);
+            }
+            true
+        }
+        None => false,
+    };
+
+    let slot = match settings.rate_limit {
+        Some(ref limit) => match take_slot(state, limit, now) {
+            Ok(slot) => slot,
+            Err(admission) => return admission,
+        },
+        None => None,
+    };
+    let probe = half_open.then(|| {
+        let probe = ActiveSlot::new();
+        let id = probe.id.clone();
+        state.probe = Some(probe);
+        id
+    });
+    Admission::Granted(slot, probe)
+}
+
+/// 按限流策略检查并发数和令牌桶，允许时扣除令牌并登记并发名额
+fn take_slot(
+    state: &mut BackendState,
+    limit: &RateLimitPolicy,
+    now: u64,
+) -> Result<Option<String>, Admission> {
+    if let Some(max) = limit.max_concurrent {
+        if state.active.len() >= max as usize {
+            return Err(Admission::Wait(POLL_INTERVAL, "并发数"));
+        }
+    }
+
+    if let Some(per_minute) = limit.per_minute.filter(|p| *p > 0) {
+        let capacity = per_minute as f64;
+        let rate_per_ms = capacity / 60_000.0;
+        let elapsed = now.saturating_sub(state.refilled_at_ms) as f64;
+        let tokens = (state.tokens.unwrap_or(capacity) + elapsed * rate_per_ms).min(capacity);
+        state.refilled_at_ms = now;
+        if tokens < 1.0 {
+            state.tokens = Some(tokens);
+            let wait_ms = ((1.0 - tokens) / rate_per_ms).ceil() as u64;
+            return Err(Admission::Wait(
+                Duration::from_millis(wait_ms),
+                "每分钟调用次数",
+            ));
+        }
+        state.tokens = Some(tokens - 1.0);
+    }
+
+    Ok(limit.max_concurrent.map(|_| {
+        let slot = ActiveSlot::new();
+        let id = slot.id.clone();
+        state.active.push(slot);
+        id
+    }))
+}
+
+/// 该错误是否计入熔断器的连续失败次数（只统计上游问题，不统计取消和配置错误）
+fn counts_as_failure(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::UpstreamError
+            | ErrorKind::RateLimited
+            | ErrorKind::SubprocessError
+            | ErrorKind::IdleTimeout
+            | ErrorKind::Timeout
+            | ErrorKind::EmptyResult
+    )
+}
+
+/// 在文件锁保护下读取、修改并写回后端状态
+fn with_state<T>(tool: CliTool, f: impl FnOnce(&mut BackendState) -> T) -> Result<T, OmccError> {
+    let dir = state_subdir("backends")?;
+    let _lock = FileLock::acquire(&dir.join(format!("{}.lock", tool.command())))?;
+    let path: PathBuf = dir.join(format!("{}.json", tool.command()));
+    let mut state: BackendState = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    let result = f(&mut state);
+    let content =
+        serde_json::to_vec_pretty(&state).map_err(|e| OmccError::JsonDecode(e.to_string()))?;
+    write_atomic(&path, &content)?;
+    Ok(result)
+}
+
+/// 当前 Unix 时间（毫秒）
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,412 @@
+//! 后台任务模块
+//!
+//! `omcc submit` 将 Agent 任务交给后台进程执行，任务记录、日志和最终结果持久化在状态目录中：
+//!
+//! ```text
+//! <state_dir>/jobs/<id>/job.json     任务记录
+//! <state_dir>/jobs/<id>/job.lock     记录文件锁
+//! <state_dir>/jobs/<id>/output.log   后台进程日志
+//! <state_dir>/jobs/<id>/result.json  最终 AgentResult
+//! ```
+//!
+//! 任务目录仅当前用户可访问。任务记录中的配置已对后端凭据脱敏，
+//! 后台进程执行前从配置文件重新读取后端设置
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::agents::{AgentExecutor, CancellationToken};
+use crate::process;
+use crate::redact;
+use crate::settings::Settings;
+use crate::state::{self, FileLock};
+use crate::types::{AgentConfig, AgentResult, ErrorKind, OmccError};
+
+/// 后台执行任务的隐藏子命令
+pub const RUN_SUBCOMMAND: &str = "job-run";
+
+/// 轮询任务状态的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 提交后等待记录后台进程 PID 的最长时间，超过后仍没有 PID 的排队任务视为启动失败
+const SPAWN_GRACE: Duration = Duration::from_secs(60);
+
+/// 任务状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// 已提交，后台进程尚未启动
+    Queued,
+    /// 执行中
+    Running,
+    /// 执行成功
+    Succeeded,
+    /// 执行失败
+    Failed,
+    /// 已取消
+    Cancelled,
+}
+
+impl JobState {
+    /// 获取状态名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Succeeded => "succeeded",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+        }
+    }
+
+    /// 是否已结束
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, JobState::Queued | JobState::Running)
+    }
+}
+
+/// 任务记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    /// 任务 ID
+    pub id: String,
+
+    /// Agent 名称
+    pub agent: String,
+
+    /// 任务状态
+    pub status: JobState,
+
+    /// 后台进程 PID（同时也是其进程组 ID）
+    #[serde(default)]
+    pub pid: Option<u32>,
+
+    /// 后台进程启动时间（用于识别 PID 复用）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid_start: Option<u64>,
+
+    /// 日志文件路径
+    pub log_file: PathBuf,
+
+    /// 结果文件路径
+    pub result_file: PathBuf,
+
+    /// 创建时间（Unix 秒）
+    pub created_at: u64,
+
+    /// 开始执行时间（Unix 秒）
+    #[serde(default)]
+    pub started_at: Option<u64>,
+
+    /// 结束时间（Unix 秒）
+    #[serde(default)]
+    pub finished_at: Option<u64>,
+
+    /// 会话 ID
+    #[serde(rename = "SESSION_ID", default)]
+    pub session_id: Option<String>,
+
+    /// 异常结束原因（结果文件之外的错误）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// Agent 配置（后端凭据已脱敏）
+    pub config: AgentConfig,
+}
+
+impl JobRecord {
+    /// 后台进程是否仍在运行（PID 已被其他进程复用时视为已退出）
+    pub fn runner_alive(&self) -> bool {
+        self.pid
+            .is_some_and(|pid| process::is_running(pid, self.pid_start))
+    }
+
+    /// 未结束的任务是否已失去后台进程
+    fn is_orphaned(&self) -> bool {
+        if self.status.is_terminal() {
+            return false;
+        }
+        match self.pid {
+            Some(_) => !self.runner_alive(),
+            // 提交进程在记录 PID 之前退出
+            None => unix_now().saturating_sub(self.created_at) > SPAWN_GRACE.as_secs(),
+        }
+    }
+}
+
+/// 任务存储
+pub struct JobStore {
+    dir: PathBuf,
+}
+
+impl JobStore {
+    /// 打开状态目录下的任务存储
+    pub fn open() -> Result<Self, OmccError> {
+        Ok(Self {
+            dir: state::state_subdir("jobs")?,
+        })
+    }
+
+    /// 任务目录
+    fn job_dir(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+
+    /// 创建任务记录（配置中的后端凭据脱敏后保存）
+    pub fn create(&self, mut config: AgentConfig) -> Result<JobRecord, OmccError> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let dir = self.job_dir(&id);
+        state::create_private_dir(&dir)?;
+        redact::redact_config(&mut config);
+        let record = JobRecord {
+            id,
+            agent: config.agent_type.name().to_string(),
+            status: JobState::Queued,
+            pid: None,
+            pid_start: None,
+            log_file: dir.join("output.log"),
+            result_file: dir.join("result.json"),
+            created_at: unix_now(),
+            started_at: None,
+            finished_at: None,
+            session_id: config.session_id.clone(),
+            error: None,
+            config,
+        };
+        self.save(&record)?;
+        Ok(record)
+    }
+
+    /// 读取任务记录
+    pub fn load(&self, id: &str) -> Result<JobRecord, OmccError> {
+        let path = self.job_dir(id).join("job.json");
+        if !is_valid_id(id) || !path.exists() {
+            return Err(OmccError::FileNotFound(format!("任务不存在：{}", id)));
+        }
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content).map_err(|e| OmccError::JsonDecode(e.to_string()))
+    }
+
+    /// 在文件锁保护下更新任务记录
+    pub fn update(&self, id: &str, f: impl FnOnce(&mut JobRecord)) -> Result<JobRecord, OmccError> {
+        if !is_valid_id(id) {
+            return Err(OmccError::FileNotFound(format!("任务不存在：{}", id)));
+        }
+        let _lock = FileLock::acquire(&self.job_dir(id).join("job.lock"))?;
+        let mut record = self.load(id)?;
+        f(&mut record);
+        self.save(&record)?;
+        Ok(record)
+    }
+
+    /// 写入任务记录
+    fn save(&self, record: &JobRecord) -> Result<(), OmccError> {
+        let content =
+            serde_json::to_vec_pretty(record).map_err(|e| OmccError::JsonDecode(e.to_string()))?;
+        state::write_atomic(&self.job_dir(&record.id).join("job.json"), &content)?;
+        Ok(())
+    }
+
+    /// 读取任务结果（尚未写入时返回 `None`）
+    pub fn read_result(&self, record: &JobRecord) -> Option<AgentResult> {
+        let content = fs::read_to_string(&record.result_file).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// 读取任务记录并校正状态：排队或执行中的任务失去后台进程时，按结果文件更新，没有结果则标记为失败
+    pub fn refresh(&self, id: &str) -> Result<JobRecord, OmccError> {
+        let record = self.load(id)?;
+        if !record.is_orphaned() {
+            return Ok(record);
+        }
+        let result = self.read_result(&record);
+        self.update(id, |r| {
+            // 在锁内重新检查：后台进程可能刚刚记录了 PID 或写入了状态
+            if !r.is_orphaned() {
+                return;
+            }
+            r.finished_at = Some(unix_now());
+            match result {
+                Some(ref result) => r.status = state_of(result),
+                None => {
+                    r.error = Some(match r.status {
+                        JobState::Queued => "后台进程未能启动".to_string(),
+                        _ => "后台进程已退出，但未写入结果".to_string(),
+                    });
+                    r.status = JobState::Failed;
+                }
+            }
+        })
+    }
+}
+
+/// 提交后台任务：创建记录并启动后台进程，立即返回
+pub fn submit(config: AgentConfig) -> Result<JobRecord, OmccError> {
+    use std::os::unix::process::CommandExt;
+
+    let store = JobStore::open()?;
+    let record = store.create(config)?;
+
+    let spawned = (|| {
+        let exe = std::env::current_exe()?;
+        let log = state::create_private_file(&record.log_file)?;
+        std::process::Command::new(exe)
+            .arg(RUN_SUBCOMMAND)
+            .arg(&record.id)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            // 独立进程组：不受提交方终端信号影响，取消时可整组终止
+            .process_group(0)
+            .spawn()
+    })();
+    let child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            store.update(&record.id, |r| {
+                r.status = JobState::Failed;
+                r.finished_at = Some(unix_now());
+                r.error = Some(format!("启动后台进程失败：{}", e));
+            })?;
+            return Err(e.into());
+        }
+    };
+
+    let pid = child.id();
+    let pid_start = process::start_time(pid);
+    store.update(&record.id, |r| {
+        if r.pid.is_none() {
+            r.pid = Some(pid);
+            r.pid_start = pid_start;
+        }
+    })
+}
+
+/// 在后台进程中执行任务（`omcc job-run <id>`）
+pub async fn run(id: &str) -> Result<AgentResult, OmccError> {
+    let store = JobStore::open()?;
+    let record = store.update(id, |r| {
+        // 排队期间已被取消（或判定为启动失败）的任务保持原状态
+        if r.status.is_terminal() {
+            return;
+        }
+        r.status = JobState::Running;
+        r.pid = Some(std::process::id());
+        r.pid_start = process::start_time(std::process::id());
+        r.started_at = Some(unix_now());
+    })?;
+    if record.status.is_terminal() {
+        return Ok(AgentResult::failure(
+            record.config.agent_type,
+            format!("任务在开始执行前已结束（{}）", record.status.as_str()),
+            ErrorKind::Cancelled,
+            None,
+        ));
+    }
+
+    // 任务记录中的后端凭据已脱敏，从配置文件重新读取后端设置
+    let mut config = record.config.clone();
+    match Settings::load() {
+        Ok(settings) => config.backend_settings = settings.backends,
+        Err(e) => {
+            store.update(id, |r| {
+                r.status = JobState::Failed;
+                r.finished_at = Some(unix_now());
+                r.error = Some(e.to_string());
+            })?;
+            return Err(e);
+        }
+    }
+
+    // `omcc cancel` 向进程组发送 SIGTERM：终止底层 CLI 后仍写入 Cancelled 结果
+    let cancel = CancellationToken::new();
+    process::cancel_on_signals(cancel.clone())?;
+    let result = AgentExecutor::new(config)
+        .with_cancellation(cancel)
+        .execute()
+        .await;
+
+    let content =
+        serde_json::to_vec_pretty(&result).map_err(|e| OmccError::JsonDecode(e.to_string()))?;
+    state::write_atomic(&record.result_file, &content)?;
+
+    store.update(id, |r| {
+        if r.status.is_terminal() {
+            return;
+        }
+        r.status = state_of(&result);
+        r.finished_at = Some(unix_now());
+        if let AgentResult::Success(ref success) = result {
+            r.session_id = Some(success.session_id.clone());
+        }
+    })?;
+    Ok(result)
+}
+
+/// 取消任务：向后台进程所在的进程组发送 SIGTERM
+///
+/// 后台进程收到信号后终止底层 CLI，并写入 `Cancelled` 结果。
+/// 记录的 PID 已被其他进程复用时不发送信号
+pub fn cancel(id: &str) -> Result<JobRecord, OmccError> {
+    let store = JobStore::open()?;
+    let record = store.refresh(id)?;
+    if record.status.is_terminal() {
+        return Ok(record);
+    }
+    if let Some(pid) = record.pid.filter(|_| record.runner_alive()) {
+        process::signal_group(pid, libc::SIGTERM)?;
+    }
+    store.update(id, |r| {
+        if r.status.is_terminal() {
+            return;
+        }
+        r.status = JobState::Cancelled;
+        r.finished_at = Some(unix_now());
+    })
+}
+
+/// 等待任务结束；超时返回 `None`
+pub async fn wait(id: &str, timeout: Option<Duration>) -> Result<Option<JobRecord>, OmccError> {
+    let store = JobStore::open()?;
+    let start = Instant::now();
+    loop {
+        let record = store.refresh(id)?;
+        // 已取消的任务还需等待后台进程终止底层 CLI 并写入结果
+        let runner_exited = !record.runner_alive() || record.result_file.exists();
+        if record.status.is_terminal() && runner_exited {
+            return Ok(Some(record));
+        }
+        if timeout.is_some_and(|t| start.elapsed() >= t) {
+            return Ok(None);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// 结果对应的任务状态
+fn state_of(result: &AgentResult) -> JobState {
+    match result {
+        AgentResult::Success(_) => JobState::Succeeded,
+        AgentResult::Failure(failure) if failure.error_kind == ErrorKind::Cancelled => {
+            JobState::Cancelled
+        }
+        AgentResult::Failure(_) => JobState::Failed,
+    }
+}
+
+/// 任务 ID 只能由字母、数字和 `-` 组成（防止路径穿越）
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// 当前 Unix 时间（秒）
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
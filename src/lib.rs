@@ -21,12 +21,33 @@
 //! # 获取使用指南
 //! omcc --reviewer-instructions
 //! omcc --workflow
+//!
+//! # 以 MCP 服务器方式运行
+//! omcc mcp serve
+//!
+//! # 后台执行长任务
+//! omcc submit reviewer -C /path/to/project "全面审核"
+//! omcc wait <JOB_ID> --timeout 600
+//!
+//! # 启动本地 HTTP API
+//! omcc serve --listen 127.0.0.1:8765
 //! ```
 
 pub mod agents;
 pub mod cli;
+pub mod guard;
 pub mod instructions;
+pub mod jobs;
+pub mod mcp;
+pub mod mock;
+pub mod process;
+pub mod record;
+pub mod redact;
+pub mod server;
+pub mod settings;
+pub mod state;
 pub mod types;
+pub mod workspace;
 
 pub use agents::AgentExecutor;
 pub use cli::{Cli, Commands};
@@ -4,17 +4,25 @@
 
 use std::io::{self, Read};
 use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::Result;
 use clap::Parser;
 
-use omcc::agents::AgentExecutor;
+use omcc::agents::{AgentExecutor, CancellationToken};
 use omcc::cli::{
-    AdvisorArgs, ChoreArgs, Cli, Commands, CommonAgentArgs, LookerArgs, ResearcherArgs,
-    ReviewerArgs,
+    AdvisorArgs, AgentCommand, ChoreArgs, Cli, Commands, CommonAgentArgs, LookerArgs, McpCommands,
+    ResearcherArgs, ReviewerArgs,
 };
 use omcc::instructions::{get_agent_skill, get_global_prompt, get_workflow_instructions};
-use omcc::types::{AgentConfig, AgentResult, AgentType};
+use omcc::jobs::{self, JobRecord, JobStore};
+use omcc::mcp::McpServer;
+use omcc::process;
+use omcc::server::ServeOptions;
+use omcc::settings::Settings;
+use omcc::types::{
+    AgentConfig, AgentResult, AgentType, ChangeReport, CommandPreview, WorktreeAction,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -52,40 +60,73 @@ async fn main() -> Result<()> {
 
     // 处理子命令
     match cli.command {
-        Some(Commands::Reviewer(args)) => {
-            execute_agent(
-                AgentType::Reviewer,
-                build_reviewer_config(args)?,
-                cli.json_output,
-            )
-            .await
+        Some(Commands::Agent(command)) => {
+            let (print_command, dry_run) =
+                (command.common().print_command, command.common().dry_run);
+            let config = build_agent_config(command)?;
+            if dry_run {
+                print_dry_run(config, cli.json_output)
+            } else if print_command {
+                print_command_preview(config, cli.json_output)
+            } else {
+                execute_agent(config, cli.json_output).await
+            }
+        }
+        Some(Commands::Submit(args)) => {
+            let record = jobs::submit(build_agent_config(args.agent)?)?;
+            print_job(&record, None, cli.json_output);
+            Ok(())
+        }
+        Some(Commands::Status(args)) => {
+            let store = JobStore::open()?;
+            let record = store.refresh(&args.id)?;
+            let result = store.read_result(&record);
+            print_job(&record, result.as_ref(), cli.json_output);
+            Ok(())
+        }
+        Some(Commands::Wait(args)) => {
+            let timeout = args.timeout.map(Duration::from_secs);
+            match jobs::wait(&args.id, timeout).await? {
+                Some(record) => {
+                    let result = JobStore::open()?.read_result(&record);
+                    match result {
+                        Some(result) => {
+                            output_result(&result, cli.json_output);
+                            if !result.is_success() {
+                                std::process::exit(1);
+                            }
+                        }
+                        None => {
+                            print_job(&record, None, cli.json_output);
+                            std::process::exit(1);
+                        }
+                    }
+                    Ok(())
+                }
+                None => {
+                    let record = JobStore::open()?.refresh(&args.id)?;
+                    print_job(&record, None, cli.json_output);
+                    std::process::exit(2);
+                }
+            }
         }
-        Some(Commands::Advisor(args)) => {
-            execute_agent(
-                AgentType::Advisor,
-                build_advisor_config(args)?,
-                cli.json_output,
-            )
-            .await
+        Some(Commands::Cancel(args)) => {
+            let record = jobs::cancel(&args.id)?;
+            print_job(&record, None, cli.json_output);
+            Ok(())
         }
-        Some(Commands::Chore(args)) => {
-            execute_agent(AgentType::Chore, build_chore_config(args)?, cli.json_output).await
+        Some(Commands::JobRun(args)) => {
+            let result = jobs::run(&args.id).await?;
+            output_result(&result, true);
+            Ok(())
         }
-        Some(Commands::Researcher(args)) => {
-            execute_agent(
-                AgentType::Researcher,
-                build_researcher_config(args)?,
-                cli.json_output,
-            )
-            .await
+        Some(Commands::MockCli(args)) => {
+            let code = omcc::mock::play(&args.script)?;
+            std::process::exit(code);
         }
-        Some(Commands::Looker(args)) => {
-            execute_agent(
-                AgentType::Looker,
-                build_looker_config(args)?,
-                cli.json_output,
-            )
-            .await
+        Some(Commands::ReplayCli(args)) => {
+            let code = omcc::record::replay(&args.recording)?;
+            std::process::exit(code);
         }
         Some(Commands::List) => {
             print_agent_list(cli.json_output);
@@ -95,6 +136,29 @@ async fn main() -> Result<()> {
             print_info(cli.json_output);
             Ok(())
         }
+        Some(Commands::Mcp(args)) => match args.command {
+            McpCommands::Serve => {
+                // SIGINT/SIGTERM 时取消进行中的工具调用，终止底层 CLI 后退出
+                let cancel = CancellationToken::new();
+                process::cancel_on_signals(cancel.clone())?;
+                McpServer::new()
+                    .with_cancellation(cancel)
+                    .serve_stdio()
+                    .await?;
+                Ok(())
+            }
+        },
+        Some(Commands::Serve(args)) => {
+            // SIGINT/SIGTERM 时停止接受请求并取消进行中的任务，终止底层 CLI 后退出
+            let cancel = CancellationToken::new();
+            process::cancel_on_signals(cancel.clone())?;
+            let options = ServeOptions {
+                listen: args.listen,
+                token: args.token,
+            };
+            omcc::server::serve(options, cancel).await?;
+            Ok(())
+        }
         None => {
             // 没有子命令时显示帮助
             println!("{}", get_global_prompt());
@@ -104,12 +168,12 @@ async fn main() -> Result<()> {
 }
 
 /// 执行 Agent 任务
-async fn execute_agent(
-    _agent_type: AgentType,
-    config: AgentConfig,
-    json_output: bool,
-) -> Result<()> {
-    let executor = AgentExecutor::new(config);
+async fn execute_agent(config: AgentConfig, json_output: bool) -> Result<()> {
+    // SIGINT/SIGTERM 时终止底层 CLI 并输出结构化的 Cancelled 结果
+    let cancel = CancellationToken::new();
+    process::cancel_on_signals(cancel.clone())?;
+
+    let executor = AgentExecutor::new(config).with_cancellation(cancel);
     let result = executor.execute().await;
 
     output_result(&result, json_output);
@@ -121,6 +185,81 @@ async fn execute_agent(
     }
 }
 
+/// 打印将要执行的底层 CLI 命令（不执行任务）
+fn print_command_preview(config: AgentConfig, json_output: bool) -> Result<()> {
+    let preview = AgentExecutor::new(config).preview_command()?;
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&preview).unwrap());
+    } else {
+        for line in format_command(&preview) {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+/// 打印试运行报告（不执行任务）
+fn print_dry_run(config: AgentConfig, json_output: bool) -> Result<()> {
+    let report = AgentExecutor::new(config).dry_run()?;
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return Ok(());
+    }
+    println!("# 配置");
+    println!("{}", serde_json::to_string_pretty(&report.config).unwrap());
+    println!();
+    println!("# 命令");
+    for line in format_command(&report.command) {
+        println!("{}", line);
+    }
+    println!();
+    println!("# 提示词");
+    println!("{}", report.prompt);
+    if !report.warnings.is_empty() {
+        println!();
+        println!("# 警告");
+        for warning in &report.warnings {
+            println!("{}", warning);
+        }
+    }
+    Ok(())
+}
+
+/// 将命令预览格式化为可复制到 shell 的形式
+fn format_command(preview: &CommandPreview) -> Vec<String> {
+    let mut lines = vec![
+        format!("# 后端: {}", preview.backend),
+        format!("# 工作目录: {}", preview.cwd.display()),
+    ];
+    if preview.prompt_via_stdin {
+        lines.push("# 提示词通过 stdin 传递".to_string());
+    }
+    let mut words = Vec::new();
+    if preview.env_clear {
+        words.push("env -i".to_string());
+    }
+    for (name, value) in &preview.env {
+        words.push(format!("{}={}", name, shell_quote(value)));
+    }
+    words.push(shell_quote(&preview.program));
+    words.extend(preview.args.iter().map(|arg| shell_quote(arg)));
+    lines.push(words.join(" \\\n  "));
+    lines
+}
+
+/// 按 POSIX shell 规则为参数加引号（只含安全字符时原样返回）
+fn shell_quote(word: &str) -> String {
+    let safe = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+%".contains(c));
+    if safe {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
 /// 输出结果
 fn output_result(result: &AgentResult, json_output: bool) {
     if json_output {
@@ -128,10 +267,46 @@ fn output_result(result: &AgentResult, json_output: bool) {
     } else {
         match result {
             AgentResult::Success(success) => {
-                println!("[{}] 执行成功 ({})", success.agent, success.duration);
+                match (&success.backend, &success.model) {
+                    (Some(backend), Some(model)) => println!(
+                        "[{}] 执行成功 ({}, {}:{})",
+                        success.agent, success.duration, backend, model
+                    ),
+                    (Some(backend), None) => println!(
+                        "[{}] 执行成功 ({}, {})",
+                        success.agent, success.duration, backend
+                    ),
+                    _ => println!("[{}] 执行成功 ({})", success.agent, success.duration),
+                }
                 println!("SESSION_ID: {}", success.session_id);
+                if let Some(isolation) = &success.isolation {
+                    match (isolation.action, &isolation.branch) {
+                        (_, _) if isolation.diff.is_empty() => println!("隔离工作树: 无改动"),
+                        (WorktreeAction::Apply, _) => println!("隔离工作树: 改动已应用到工作区"),
+                        (WorktreeAction::Branch, Some(branch)) => {
+                            println!("隔离工作树: 改动已保存到分支 {}", branch)
+                        }
+                        _ => println!("隔离工作树: 改动已丢弃"),
+                    }
+                    if let Some(error) = &isolation.apply_error {
+                        println!("应用改动失败: {}", error);
+                    }
+                }
                 println!();
                 println!("{}", success.result);
+                if let Some(changes) = &success.changes {
+                    println!();
+                    for line in format_changes(changes) {
+                        println!("{}", line);
+                    }
+                }
+                // 丢弃的改动只保留在 diff 中
+                if let Some(isolation) = &success.isolation {
+                    if isolation.action == WorktreeAction::Discard && !isolation.diff.is_empty() {
+                        println!();
+                        print!("{}", isolation.diff);
+                    }
+                }
             }
             AgentResult::Failure(failure) => {
                 eprintln!("[{}] 执行失败", failure.agent);
@@ -147,12 +322,88 @@ fn output_result(result: &AgentResult, json_output: bool) {
                             eprintln!("  {}", line);
                         }
                     }
+                    if detail.attempts.len() > 1 {
+                        eprintln!("尝试记录:");
+                        for attempt in &detail.attempts {
+                            eprintln!(
+                                "  #{} {:?} ({}ms): {}",
+                                attempt.attempt,
+                                attempt.error_kind,
+                                attempt.duration_ms,
+                                attempt.error
+                            );
+                        }
+                    }
+                }
+                if let Some(changes) = &failure.changes {
+                    for line in format_changes(changes) {
+                        eprintln!("{}", line);
+                    }
                 }
             }
         }
     }
 }
 
+/// 格式化工作区改动报告（每个文件一行，附带 diff 时追加在末尾）
+fn format_changes(changes: &ChangeReport) -> Vec<String> {
+    if changes.is_empty() {
+        return vec!["工作区改动: 无".to_string()];
+    }
+    let mut lines = vec!["工作区改动:".to_string()];
+    let groups = [
+        ("A", &changes.added),
+        ("M", &changes.modified),
+        ("D", &changes.deleted),
+    ];
+    for (status, files) in groups {
+        for file in files {
+            match (file.additions, file.deletions) {
+                (Some(additions), Some(deletions)) => lines.push(format!(
+                    "  {} {} (+{} -{})",
+                    status, file.path, additions, deletions
+                )),
+                _ => lines.push(format!("  {} {} (二进制)", status, file.path)),
+            }
+        }
+    }
+    if let Some(diff) = &changes.diff {
+        lines.push(String::new());
+        lines.extend(diff.lines().map(str::to_string));
+    }
+    lines
+}
+
+/// 输出后台任务记录
+fn print_job(record: &JobRecord, result: Option<&AgentResult>, json_output: bool) {
+    if json_output {
+        let mut value = serde_json::to_value(record).unwrap();
+        if let (Some(object), Some(result)) = (value.as_object_mut(), result) {
+            object.insert("result".to_string(), serde_json::to_value(result).unwrap());
+        }
+        println!("{}", serde_json::to_string_pretty(&value).unwrap());
+    } else {
+        println!("JOB_ID: {}", record.id);
+        println!("Agent: {}", record.agent);
+        println!("状态: {}", record.status.as_str());
+        if let Some(pid) = record.pid {
+            println!("PID: {}", pid);
+        }
+        if let Some(ref session_id) = record.session_id {
+            println!("SESSION_ID: {}", session_id);
+        }
+        println!("日志: {}", record.log_file.display());
+        println!("结果: {}", record.result_file.display());
+        if let Some(ref error) = record.error {
+            println!("错误: {}", error);
+        }
+        if let Some(result) = result {
+            println!();
+            output_result(result, false);
+        }
+    }
+}
+
 /// 打印 Agent 列表
 fn print_agent_list(json_output: bool) {
     let agents = vec![
@@ -221,6 +472,20 @@ fn print_info(json_output: bool) {
     }
 }
 
+/// 根据 Agent 子命令构建配置
+///
+/// 命令行参数优先于配置文件中的 Agent 默认设置
+fn build_agent_config(command: AgentCommand) -> Result<AgentConfig> {
+    let settings = Settings::load()?;
+    match command {
+        AgentCommand::Reviewer(args) => build_reviewer_config(args, &settings),
+        AgentCommand::Advisor(args) => build_advisor_config(args, &settings),
+        AgentCommand::Chore(args) => build_chore_config(args, &settings),
+        AgentCommand::Researcher(args) => build_researcher_config(args, &settings),
+        AgentCommand::Looker(args) => build_looker_config(args, &settings),
+    }
+}
+
 /// 读取提示词
 fn read_prompt(
     prompt: Option<String>,
@@ -242,17 +507,15 @@ fn read_prompt(
 
 /// 构建 Reviewer 配置
 /// 注意：Reviewer 的超时时间固定为 300s（空闲）和 7200s（总时长），不允许外部修改
-fn build_reviewer_config(args: ReviewerArgs) -> Result<AgentConfig> {
+fn build_reviewer_config(args: ReviewerArgs, settings: &Settings) -> Result<AgentConfig> {
     let prompt = read_prompt(args.prompt, args.from_stdin, args.from_file)?;
     let working_dir = args.common.working_dir.clone();
     let mut config = AgentConfig::new(AgentType::Reviewer, prompt, working_dir);
-    apply_common_args(&mut config, &args.common);
-    
+    apply_common_args(&mut config, &args.common, settings);
+
     // Reviewer 超时时间锁死，忽略用户传入的值（与原项目一致）
-    config.timeout = None;      // 使用默认值 300s
-    config.max_duration = None; // 使用默认值 7200s
-    
-    config.images = args.images;
+    config.enforce_agent_rules();
+
     config.skip_git_repo_check = args.skip_git_repo_check;
     config.yolo = args.yolo;
     config.profile = args.profile;
@@ -260,34 +523,34 @@ fn build_reviewer_config(args: ReviewerArgs) -> Result<AgentConfig> {
 }
 
 /// 构建 Advisor 配置
-fn build_advisor_config(args: AdvisorArgs) -> Result<AgentConfig> {
+fn build_advisor_config(args: AdvisorArgs, settings: &Settings) -> Result<AgentConfig> {
     let prompt = read_prompt(args.prompt, args.from_stdin, args.from_file)?;
     let working_dir = args.common.working_dir.clone();
     let mut config = AgentConfig::new(AgentType::Advisor, prompt, working_dir);
-    apply_common_args(&mut config, &args.common);
+    apply_common_args(&mut config, &args.common, settings);
     Ok(config)
 }
 
 /// 构建 Chore 配置
-fn build_chore_config(args: ChoreArgs) -> Result<AgentConfig> {
+fn build_chore_config(args: ChoreArgs, settings: &Settings) -> Result<AgentConfig> {
     let prompt = read_prompt(args.prompt, args.from_stdin, args.from_file)?;
     let working_dir = args.common.working_dir.clone();
     let mut config = AgentConfig::new(AgentType::Chore, prompt, working_dir);
-    apply_common_args(&mut config, &args.common);
+    apply_common_args(&mut config, &args.common, settings);
     Ok(config)
 }
 
 /// 构建 Researcher 配置
-fn build_researcher_config(args: ResearcherArgs) -> Result<AgentConfig> {
+fn build_researcher_config(args: ResearcherArgs, settings: &Settings) -> Result<AgentConfig> {
     let prompt = read_prompt(args.prompt, args.from_stdin, args.from_file)?;
     let working_dir = args.common.working_dir.clone();
     let mut config = AgentConfig::new(AgentType::Researcher, prompt, working_dir);
-    apply_common_args(&mut config, &args.common);
+    apply_common_args(&mut config, &args.common, settings);
     Ok(config)
 }
 
 /// 构建 Looker 配置
-fn build_looker_config(args: LookerArgs) -> Result<AgentConfig> {
+fn build_looker_config(args: LookerArgs, settings: &Settings) -> Result<AgentConfig> {
     let goal = if args.from_stdin {
         let mut buffer = String::new();
         io::stdin().read_to_string(&mut buffer)?;
@@ -302,23 +565,54 @@ fn build_looker_config(args: LookerArgs) -> Result<AgentConfig> {
 
     let working_dir = args.common.working_dir.clone();
     let mut config = AgentConfig::new(AgentType::Looker, goal.clone(), working_dir);
-    apply_common_args(&mut config, &args.common);
+    apply_common_args(&mut config, &args.common, settings);
     config.file_path = Some(args.file_path);
     config.goal = Some(goal);
     Ok(config)
 }
 
 /// 应用通用参数
-fn apply_common_args(config: &mut AgentConfig, args: &CommonAgentArgs) {
+fn apply_common_args(config: &mut AgentConfig, args: &CommonAgentArgs, settings: &Settings) {
     if let Some(sandbox) = args.sandbox {
         config.sandbox = sandbox.into();
     }
+    if let Some(sandbox_check) = args.sandbox_check {
+        config.sandbox_check = sandbox_check.into();
+    }
     config.session_id = args.session_id.clone();
+    config.images = args.images.clone();
     config.timeout = args.timeout;
     config.max_duration = args.max_duration;
+    config.soft_deadline = args.soft_deadline;
     config.max_retries = args.max_retries;
+    config.checkpoint = args.checkpoint;
+    config.lock_wait = args.wait_lock;
+    config.redact_prompt = args.redact_prompt;
+    config.report_diff = args.diff;
+    config.record = args.record.clone();
+    config.record_raw = args.record_raw;
+    if let Some(isolate) = args.isolate {
+        config.isolate = isolate.into();
+    }
+    config.isolate_base = args.isolate_base.clone();
+    if let Some(on_finish) = args.on_finish {
+        config.on_finish = on_finish.into();
+    }
+    config.backend = args.backend.clone();
+    config.fallbacks = args.fallbacks.clone();
+    config.fallback_on = args
+        .fallback_on
+        .as_ref()
+        .map(|kinds| kinds.iter().copied().collect());
     config.return_all_messages = args.return_all_messages;
     config.return_metrics = args.return_metrics;
     config.log_metrics = args.log_metrics;
     config.model = args.model.clone();
+
+    settings.apply(config);
+    if args.retry.is_set() {
+        let mut policy = config.get_retry_policy();
+        args.retry.apply(&mut policy);
+        config.retry_policy = Some(policy);
+    }
 }
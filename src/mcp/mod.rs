@@ -0,0 +1,8 @@
+//! MCP 模块
+//!
+//! 通过 stdio JSON-RPC 实现 Model Context Protocol，将各 Agent 暴露为 MCP 工具
+
+pub mod schema;
+pub mod server;
+
+pub use server::McpServer;
@@ -0,0 +1,483 @@
+//! MCP 工具定义
+//!
+//! 定义每个 Agent 工具的输入 schema（字段与 `ToolArguments` 一致，由测试校验），并将调用参数转换为配置
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::settings::Settings;
+use crate::types::{
+    AgentConfig, AgentType, BackendSpec, ErrorKind, IsolationMode, OmccError, RetryPolicy,
+    SandboxCheck, SandboxPolicy, WorktreeAction,
+};
+
+/// 所有以工具形式暴露的 Agent
+pub const TOOL_AGENTS: [AgentType; 5] = [
+    AgentType::Reviewer,
+    AgentType::Advisor,
+    AgentType::Chore,
+    AgentType::Researcher,
+    AgentType::Looker,
+];
+
+/// 工具调用参数（与 `AgentConfig` 的可配置字段一一对应）
+///
+/// 输入 schema 由 [`tool_input_schema`] 手写，序列化结果的字段名用于校验两者一致
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolArguments {
+    /// 任务提示词（Looker 为分析目标）
+    #[serde(default)]
+    pub prompt: Option<String>,
+
+    /// 工作目录
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+
+    /// 沙箱策略
+    #[serde(default)]
+    pub sandbox: Option<SandboxPolicy>,
+
+    /// 会话 ID
+    #[serde(default, rename = "SESSION_ID", alias = "session_id")]
+    pub session_id: Option<String>,
+
+    /// 空闲超时（秒）
+    #[serde(default)]
+    pub timeout: Option<u64>,
+
+    /// 最大执行时长（秒）
+    #[serde(default)]
+    pub max_duration: Option<u64>,
+
+    /// 软截止时间（秒）
+    #[serde(default)]
+    pub soft_deadline: Option<u64>,
+
+    /// 最大重试次数
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+
+    /// 重试策略
+    #[serde(default)]
+    pub retry_policy: Option<RetryPolicy>,
+
+    /// 是否创建工作区检查点
+    #[serde(default)]
+    pub checkpoint: bool,
+
+    /// 工作区写锁的最长等待时间（秒）
+    #[serde(default)]
+    pub wait_lock: Option<u64>,
+
+    /// 只读任务结束后对工作区的检查方式
+    #[serde(default)]
+    pub sandbox_check: SandboxCheck,
+
+    /// 是否在发送前对提示词脱敏
+    #[serde(default)]
+    pub redact_prompt: bool,
+
+    /// 改动报告中是否附带统一 diff
+    #[serde(default)]
+    pub diff: bool,
+
+    /// 工作区隔离方式
+    #[serde(default)]
+    pub isolate: IsolationMode,
+
+    /// 隔离工作树的基准引用
+    #[serde(default)]
+    pub isolate_base: Option<String>,
+
+    /// 隔离工作树中改动的处理方式
+    #[serde(default)]
+    pub on_finish: WorktreeAction,
+
+    /// 主后端
+    #[serde(default)]
+    pub backend: Option<BackendSpec>,
+
+    /// 备用后端
+    #[serde(default)]
+    pub fallbacks: Vec<BackendSpec>,
+
+    /// 触发切换备用后端的错误类型
+    #[serde(default)]
+    pub fallback_on: Option<HashSet<ErrorKind>>,
+
+    /// 是否返回指标数据
+    #[serde(default)]
+    pub return_metrics: bool,
+
+    /// 指定模型
+    #[serde(default)]
+    pub model: Option<String>,
+
+    /// 附加图片
+    #[serde(default)]
+    pub images: Vec<PathBuf>,
+
+    /// 要分析的文件路径（Looker）
+    #[serde(default)]
+    pub file_path: Option<PathBuf>,
+
+    /// 是否跳过 Git 仓库检查（Reviewer）
+    #[serde(default)]
+    pub skip_git_repo_check: Option<bool>,
+
+    /// YOLO 模式（Reviewer）
+    #[serde(default)]
+    pub yolo: bool,
+
+    /// 配置文件名称（Reviewer）
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+impl ToolArguments {
+    /// 从 `tools/call` 的 arguments 解析
+    pub fn from_value(value: Option<Value>) -> Result<Self, OmccError> {
+        match value {
+            None | Some(Value::Null) => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value)
+                .map_err(|e| OmccError::ConfigError(format!("工具参数无效：{}", e))),
+        }
+    }
+
+    /// 转换为 Agent 配置（规则与命令行子命令保持一致），并填入配置文件中的默认值
+    pub fn into_config(
+        self,
+        agent_type: AgentType,
+        settings: &Settings,
+    ) -> Result<AgentConfig, OmccError> {
+        let prompt = self
+            .prompt
+            .filter(|p| !p.trim().is_empty())
+            .ok_or_else(|| OmccError::ConfigError("缺少必填参数 prompt".to_string()))?;
+        let working_dir = self.working_dir.unwrap_or_else(|| PathBuf::from("."));
+
+        let mut config = AgentConfig::new(agent_type, prompt.clone(), working_dir);
+        if let Some(sandbox) = self.sandbox {
+            config.sandbox = sandbox;
+        }
+        config.session_id = self.session_id;
+        config.soft_deadline = self.soft_deadline;
+        config.max_retries = self.max_retries;
+        config.retry_policy = self.retry_policy;
+        config.checkpoint = self.checkpoint;
+        config.lock_wait = self.wait_lock;
+        config.sandbox_check = self.sandbox_check;
+        config.redact_prompt = self.redact_prompt;
+        config.report_diff = self.diff;
+        config.isolate = self.isolate;
+        config.isolate_base = self.isolate_base;
+        config.on_finish = self.on_finish;
+        config.backend = self.backend;
+        config.fallbacks = self.fallbacks;
+        config.fallback_on = self.fallback_on;
+        config.return_metrics = self.return_metrics;
+        config.model = self.model;
+        config.images = self.images;
+
+        match agent_type {
+            AgentType::Reviewer => {
+                if let Some(skip) = self.skip_git_repo_check {
+                    config.skip_git_repo_check = skip;
+                }
+                config.yolo = self.yolo;
+                config.profile = self.profile;
+            }
+            AgentType::Looker => {
+                let file_path = self
+                    .file_path
+                    .ok_or_else(|| OmccError::ConfigError("缺少必填参数 file_path".to_string()))?;
+                config.timeout = self.timeout;
+                config.max_duration = self.max_duration;
+                config.file_path = Some(file_path);
+                config.goal = Some(prompt);
+            }
+            _ => {
+                config.timeout = self.timeout;
+                config.max_duration = self.max_duration;
+            }
+        }
+
+        settings.apply(&mut config);
+        config.enforce_agent_rules();
+        Ok(config)
+    }
+}
+
+/// 生成 Agent 工具的描述
+pub fn tool_description(agent_type: AgentType) -> String {
+    format!(
+        "调用 {}（{}）。底层 CLI：{}，默认沙箱：{}。使用指南见 prompt `{}` 或资源 `{}`。",
+        agent_type.name(),
+        agent_type.display_name(),
+        agent_type.cli_tool().command(),
+        agent_type.default_sandbox().as_arg(),
+        agent_type.name(),
+        skill_uri(agent_type),
+    )
+}
+
+/// 生成 Agent 工具的输入 schema
+pub fn tool_input_schema(agent_type: AgentType) -> Value {
+    let mut properties = Map::new();
+
+    let prompt_description = if agent_type == AgentType::Looker {
+        "分析目标描述"
+    } else {
+        "任务提示词"
+    };
+    properties.insert(
+        "prompt".to_string(),
+        json!({ "type": "string", "description": prompt_description }),
+    );
+    properties.insert(
+        "working_dir".to_string(),
+        json!({ "type": "string", "description": "工作目录", "default": "." }),
+    );
+    properties.insert(
+        "sandbox".to_string(),
+        json!({
+            "type": "string",
+            "description": "沙箱策略",
+            "enum": [
+                SandboxPolicy::ReadOnly.as_arg(),
+                SandboxPolicy::WorkspaceWrite.as_arg(),
+                SandboxPolicy::DangerFullAccess.as_arg(),
+            ],
+            "default": agent_type.default_sandbox().as_arg(),
+        }),
+    );
+    properties.insert(
+        "SESSION_ID".to_string(),
+        json!({ "type": "string", "description": "会话 ID（用于多轮对话）" }),
+    );
+    if agent_type != AgentType::Reviewer {
+        properties.insert(
+            "timeout".to_string(),
+            json!({
+                "type": "integer",
+                "minimum": 0,
+                "description": "空闲超时（秒）",
+                "default": agent_type.default_timeout(),
+            }),
+        );
+        properties.insert(
+            "max_duration".to_string(),
+            json!({
+                "type": "integer",
+                "minimum": 0,
+                "description": "最大执行时长（秒）",
+                "default": agent_type.default_max_duration(),
+            }),
+        );
+    }
+    properties.insert(
+        "soft_deadline".to_string(),
+        json!({
+            "type": "integer",
+            "minimum": 0,
+            "description": "软截止时间（秒），到达后提醒 Agent 收尾（仅 Claude 底层支持）",
+        }),
+    );
+    properties.insert(
+        "max_retries".to_string(),
+        json!({
+            "type": "integer",
+            "minimum": 0,
+            "description": "最大重试次数",
+            "default": agent_type.default_max_retries(),
+        }),
+    );
+    properties.insert(
+        "retry_policy".to_string(),
+        json!({
+            "type": "object",
+            "description": "重试策略（未指定的字段使用默认值）",
+            "properties": {
+                "initial_delay_ms": { "type": "integer", "minimum": 0, "description": "首次重试前的等待时间（毫秒）" },
+                "multiplier": { "type": "number", "minimum": 0, "description": "退避倍数" },
+                "max_delay_ms": { "type": "integer", "minimum": 0, "description": "单次等待时间上限（毫秒）" },
+                "jitter": { "type": "number", "minimum": 0, "maximum": 1, "description": "抖动比例" },
+                "retry_on": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "可重试的错误类型（如 idle_timeout、upstream_error）",
+                },
+                "budget_s": { "type": "integer", "minimum": 0, "description": "总重试时间预算（秒）" },
+            },
+            "additionalProperties": false,
+        }),
+    );
+    properties.insert(
+        "checkpoint".to_string(),
+        json!({
+            "type": "boolean",
+            "description": "创建 Git 检查点，失败重试前恢复工作区（仅对可写沙箱策略生效）",
+            "default": false,
+        }),
+    );
+    properties.insert(
+        "sandbox_check".to_string(),
+        json!({
+            "type": "string",
+            "enum": ["off", "warn", "fail"],
+            "description": "只读任务结束后检查工作区是否被修改：不检查、警告或以 sandbox_violation 失败",
+            "default": "fail",
+        }),
+    );
+    properties.insert(
+        "redact_prompt".to_string(),
+        json!({
+            "type": "boolean",
+            "description": "发送前对提示词中的密钥和令牌脱敏",
+            "default": false,
+        }),
+    );
+    properties.insert(
+        "diff".to_string(),
+        json!({
+            "type": "boolean",
+            "description": "在改动报告 changes 中附带统一 diff（仅对可写沙箱策略生效）",
+            "default": false,
+        }),
+    );
+    properties.insert(
+        "isolate".to_string(),
+        json!({
+            "type": "string",
+            "enum": ["none", "worktree"],
+            "description": "worktree：在临时 git worktree 中执行，主工作区保持不变（仅对可写沙箱策略生效）",
+            "default": "none",
+        }),
+    );
+    properties.insert(
+        "isolate_base".to_string(),
+        json!({
+            "type": "string",
+            "description": "隔离工作树的基准引用（默认 HEAD）",
+        }),
+    );
+    properties.insert(
+        "on_finish".to_string(),
+        json!({
+            "type": "string",
+            "enum": ["apply", "branch", "discard"],
+            "description": "隔离工作树中改动的处理方式：应用到主工作区、保存为新分支或丢弃（只返回 diff）",
+            "default": "branch",
+        }),
+    );
+    properties.insert(
+        "wait_lock".to_string(),
+        json!({
+            "type": "integer",
+            "minimum": 0,
+            "description": "工作区写锁被占用时最多等待的秒数（默认立即失败，仅对可写沙箱策略生效）",
+        }),
+    );
+    properties.insert(
+        "backend".to_string(),
+        json!({
+            "type": "string",
+            "description": "主后端，格式为 `<cli>[:<model>]`（如 `claude:sonnet`），自定义命令为 `custom:<name>[:<model>]`",
+            "default": agent_type.cli_tool().command(),
+        }),
+    );
+    properties.insert(
+        "fallbacks".to_string(),
+        json!({
+            "type": "array",
+            "items": { "type": "string" },
+            "description": "备用后端（按顺序尝试），格式同 backend",
+        }),
+    );
+    properties.insert(
+        "fallback_on".to_string(),
+        json!({
+            "type": "array",
+            "items": { "type": "string" },
+            "description": "触发切换备用后端的错误类型（默认 command_not_found、upstream_error、rate_limited、circuit_open、throttled）",
+        }),
+    );
+    properties.insert(
+        "return_metrics".to_string(),
+        json!({ "type": "boolean", "description": "返回指标数据", "default": false }),
+    );
+    properties.insert(
+        "model".to_string(),
+        json!({ "type": "string", "description": "指定模型" }),
+    );
+
+    properties.insert(
+        "images".to_string(),
+        json!({
+            "type": "array",
+            "items": { "type": "string" },
+            "description": "附加图片文件（后端不支持图片时报错）",
+        }),
+    );
+
+    let mut required = vec!["prompt"];
+    match agent_type {
+        AgentType::Reviewer => {
+            properties.insert(
+                "skip_git_repo_check".to_string(),
+                json!({ "type": "boolean", "description": "跳过 Git 仓库检查", "default": true }),
+            );
+            properties.insert(
+                "yolo".to_string(),
+                json!({ "type": "boolean", "description": "YOLO 模式（跳过审批）", "default": false }),
+            );
+            properties.insert(
+                "profile".to_string(),
+                json!({ "type": "string", "description": "配置文件名称" }),
+            );
+        }
+        AgentType::Looker => {
+            properties.insert(
+                "file_path".to_string(),
+                json!({ "type": "string", "description": "要分析的文件路径" }),
+            );
+            required.push("file_path");
+        }
+        _ => {}
+    }
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// 生成 Agent 工具的完整定义（`tools/list` 条目）
+pub fn tool_definition(agent_type: AgentType) -> Value {
+    json!({
+        "name": agent_type.name(),
+        "description": tool_description(agent_type),
+        "inputSchema": tool_input_schema(agent_type),
+    })
+}
+
+/// Agent skill 文档的资源 URI
+pub fn skill_uri(agent_type: AgentType) -> String {
+    format!("omcc://skills/{}", agent_type.name())
+}
+
+/// 工作流指南的资源 URI
+pub const WORKFLOW_URI: &str = "omcc://workflow";
+
+/// 全局提示词的资源 URI
+pub const GLOBAL_PROMPT_URI: &str = "omcc://global-prompt";
+
+/// 按名称查找 Agent 类型
+pub fn agent_by_name(name: &str) -> Option<AgentType> {
+    TOOL_AGENTS.iter().copied().find(|a| a.name() == name)
+}
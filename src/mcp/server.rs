@@ -0,0 +1,376 @@
+//! MCP 服务器
+//!
+//! 基于换行分隔的 JSON-RPC 2.0，可通过 stdio 运行，也可在进程内直接调用 `handle`
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+use super::schema::{
+    agent_by_name, skill_uri, tool_definition, ToolArguments, GLOBAL_PROMPT_URI, TOOL_AGENTS,
+    WORKFLOW_URI,
+};
+use crate::agents::{AgentExecutor, CancellationToken};
+use crate::instructions::{get_agent_skill, get_global_prompt, get_workflow_instructions};
+use crate::settings::Settings;
+use crate::types::AgentResult;
+
+/// 默认协议版本（客户端未声明或请求了不支持的版本时使用）
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// 支持的协议版本（客户端请求其中之一时按原样应答）
+pub const SUPPORTED_PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", PROTOCOL_VERSION];
+
+/// JSON-RPC 错误码
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// 工作流 prompt 名称
+const WORKFLOW_PROMPT: &str = "workflow";
+
+/// 全局提示词 prompt 名称
+const GLOBAL_PROMPT: &str = "global-prompt";
+
+/// MCP 服务器
+///
+/// 根取消令牌触发时取消所有进行中的工具调用并停止读取请求；
+/// `notifications/cancelled` 只取消对应请求的工具调用
+#[derive(Debug, Default, Clone)]
+pub struct McpServer {
+    cancel: CancellationToken,
+    in_flight: Arc<Mutex<HashMap<String, CancellationToken>>>,
+}
+
+impl McpServer {
+    /// 创建新的服务器
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 设置根取消令牌
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// 在 stdio 上运行服务器，直到 stdin 关闭
+    pub async fn serve_stdio(self) -> std::io::Result<()> {
+        let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+        let stdout = tokio::io::stdout();
+        self.serve(stdin, stdout).await
+    }
+
+    /// 在任意读写流上运行服务器
+    ///
+    /// 每条请求在独立任务中处理，长时间运行的工具调用不会阻塞其它请求。
+    /// 输入关闭或根取消令牌触发后，等待进行中的请求结束（已取消的工具调用会终止底层 CLI）
+    pub async fn serve<R, W>(self, reader: R, mut writer: W) -> std::io::Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let server = Arc::new(self);
+        let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+
+        let writer_task = tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                let mut line = message.to_string();
+                line.push('\n');
+                writer.write_all(line.as_bytes()).await?;
+                writer.flush().await?;
+            }
+            Ok::<_, std::io::Error>(())
+        });
+
+        let mut lines = reader.lines();
+        let mut tasks = JoinSet::new();
+        loop {
+            let line = tokio::select! {
+                line = lines.next_line() => line?,
+                _ = server.cancel.cancelled() => break,
+                // 回收已结束的请求任务，避免长时间运行时任务句柄不断累积
+                Some(_) = tasks.join_next(), if !tasks.is_empty() => continue,
+            };
+            let Some(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message = match serde_json::from_str::<Value>(&line) {
+                Ok(message) => message,
+                Err(e) => {
+                    let _ = tx.send(error_response(
+                        Value::Null,
+                        PARSE_ERROR,
+                        &format!("JSON 解析失败：{}", e),
+                    ));
+                    continue;
+                }
+            };
+
+            let server = Arc::clone(&server);
+            let tx = tx.clone();
+            tasks.spawn(async move {
+                if let Some(response) = server.handle(message).await {
+                    let _ = tx.send(response);
+                }
+            });
+        }
+
+        // stdin 关闭后等待进行中的请求完成
+        while tasks.join_next().await.is_some() {}
+        drop(tx);
+        writer_task
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?
+    }
+
+    /// 处理一条 JSON-RPC 消息，通知类消息返回 `None`
+    pub async fn handle(&self, message: Value) -> Option<Value> {
+        if let Value::Array(batch) = message {
+            let mut responses = Vec::new();
+            for item in batch {
+                if let Some(response) = Box::pin(self.handle(item)).await {
+                    responses.push(response);
+                }
+            }
+            return (!responses.is_empty()).then_some(Value::Array(responses));
+        }
+
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(|m| m.as_str()) else {
+            // 客户端发来的响应（无 method）直接忽略
+            return id.map(|id| error_response(id, INVALID_REQUEST, "缺少 method 字段"));
+        };
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        let result = self.dispatch(method, id.as_ref(), params).await;
+
+        // 通知不需要响应
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error_response(id, code, &message),
+        })
+    }
+
+    /// 分发方法调用
+    async fn dispatch(
+        &self,
+        method: &str,
+        id: Option<&Value>,
+        params: Value,
+    ) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => Ok(self.initialize(&params)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({
+                "tools": TOOL_AGENTS.iter().map(|a| tool_definition(*a)).collect::<Vec<_>>(),
+            })),
+            "tools/call" => self.call_tool(id, params).await,
+            "prompts/list" => Ok(self.list_prompts()),
+            "prompts/get" => self.get_prompt(&params),
+            "resources/list" => Ok(self.list_resources()),
+            "resources/read" => self.read_resource(&params),
+            "notifications/cancelled" => {
+                self.cancel_request(&params);
+                Ok(Value::Null)
+            }
+            _ if method.starts_with("notifications/") => Ok(Value::Null),
+            _ => Err((METHOD_NOT_FOUND, format!("未知方法：{}", method))),
+        }
+    }
+
+    /// 处理 initialize：协商协议版本
+    fn initialize(&self, params: &Value) -> Value {
+        let protocol_version = params
+            .get("protocolVersion")
+            .and_then(|v| v.as_str())
+            .and_then(|v| SUPPORTED_PROTOCOL_VERSIONS.into_iter().find(|s| *s == v))
+            .unwrap_or(PROTOCOL_VERSION);
+        json!({
+            "protocolVersion": protocol_version,
+            "capabilities": {
+                "tools": {},
+                "prompts": {},
+                "resources": {},
+            },
+            "serverInfo": {
+                "name": "omcc",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "instructions": get_global_prompt(),
+        })
+    }
+
+    /// 处理 tools/call：通过 `AgentExecutor` 执行对应 Agent
+    ///
+    /// 执行期间以请求 ID 登记取消令牌，供 `notifications/cancelled` 取消
+    async fn call_tool(&self, id: Option<&Value>, params: Value) -> Result<Value, (i64, String)> {
+        let name = params
+            .get("name")
+            .and_then(|n| n.as_str())
+            .ok_or((INVALID_PARAMS, "缺少工具名称".to_string()))?;
+        let agent_type =
+            agent_by_name(name).ok_or((INVALID_PARAMS, format!("未知工具：{}", name)))?;
+
+        // 配置文件无效是服务端的问题，不是调用参数错误
+        let settings = Settings::load().map_err(|e| (INTERNAL_ERROR, e.to_string()))?;
+        let config = ToolArguments::from_value(params.get("arguments").cloned())
+            .and_then(|args| args.into_config(agent_type, &settings))
+            .map_err(|e| (INVALID_PARAMS, e.to_string()))?;
+
+        let cancel = self.cancel.child_token();
+        let key = id.map(Value::to_string);
+        if let Some(ref key) = key {
+            self.lock_in_flight().insert(key.clone(), cancel.clone());
+        }
+        let result = AgentExecutor::new(config)
+            .with_cancellation(cancel)
+            .execute()
+            .await;
+        if let Some(ref key) = key {
+            self.lock_in_flight().remove(key);
+        }
+        Ok(tool_result(&result))
+    }
+
+    /// 处理 notifications/cancelled：取消 `requestId` 对应的工具调用（未知请求忽略）
+    fn cancel_request(&self, params: &Value) {
+        let Some(request_id) = params.get("requestId") else {
+            return;
+        };
+        if let Some(cancel) = self.lock_in_flight().get(&request_id.to_string()) {
+            cancel.cancel();
+        }
+    }
+
+    fn lock_in_flight(&self) -> std::sync::MutexGuard<'_, HashMap<String, CancellationToken>> {
+        self.in_flight.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 处理 prompts/list
+    fn list_prompts(&self) -> Value {
+        let mut prompts: Vec<Value> = TOOL_AGENTS
+            .iter()
+            .map(|a| {
+                json!({
+                    "name": a.name(),
+                    "description": format!("{}（{}）使用指南", a.name(), a.display_name()),
+                })
+            })
+            .collect();
+        prompts.push(json!({ "name": WORKFLOW_PROMPT, "description": "完整工作流指南" }));
+        prompts.push(json!({ "name": GLOBAL_PROMPT, "description": "全局提示词模板" }));
+        json!({ "prompts": prompts })
+    }
+
+    /// 处理 prompts/get
+    fn get_prompt(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let name = params
+            .get("name")
+            .and_then(|n| n.as_str())
+            .ok_or((INVALID_PARAMS, "缺少 prompt 名称".to_string()))?;
+        let (description, text) = match name {
+            WORKFLOW_PROMPT => ("完整工作流指南".to_string(), get_workflow_instructions()),
+            GLOBAL_PROMPT => ("全局提示词模板".to_string(), get_global_prompt()),
+            _ => {
+                let agent = agent_by_name(name)
+                    .ok_or((INVALID_PARAMS, format!("未知 prompt：{}", name)))?;
+                (
+                    format!("{}（{}）使用指南", agent.name(), agent.display_name()),
+                    get_agent_skill(agent),
+                )
+            }
+        };
+        Ok(json!({
+            "description": description,
+            "messages": [{
+                "role": "user",
+                "content": { "type": "text", "text": text },
+            }],
+        }))
+    }
+
+    /// 处理 resources/list
+    fn list_resources(&self) -> Value {
+        let mut resources: Vec<Value> = TOOL_AGENTS
+            .iter()
+            .map(|a| {
+                json!({
+                    "uri": skill_uri(*a),
+                    "name": format!("{} skill", a.name()),
+                    "description": format!("{}（{}）使用指南", a.name(), a.display_name()),
+                    "mimeType": "text/markdown",
+                })
+            })
+            .collect();
+        resources.push(json!({
+            "uri": WORKFLOW_URI,
+            "name": "workflow",
+            "description": "完整工作流指南",
+            "mimeType": "text/markdown",
+        }));
+        resources.push(json!({
+            "uri": GLOBAL_PROMPT_URI,
+            "name": "global-prompt",
+            "description": "全局提示词模板",
+            "mimeType": "text/markdown",
+        }));
+        json!({ "resources": resources })
+    }
+
+    /// 处理 resources/read
+    fn read_resource(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let uri = params
+            .get("uri")
+            .and_then(|u| u.as_str())
+            .ok_or((INVALID_PARAMS, "缺少资源 URI".to_string()))?;
+        let text = match uri {
+            WORKFLOW_URI => get_workflow_instructions(),
+            GLOBAL_PROMPT_URI => get_global_prompt(),
+            _ => {
+                let agent = uri
+                    .strip_prefix("omcc://skills/")
+                    .and_then(agent_by_name)
+                    .ok_or((INVALID_PARAMS, format!("未知资源：{}", uri)))?;
+                get_agent_skill(agent)
+            }
+        };
+        Ok(json!({
+            "contents": [{ "uri": uri, "mimeType": "text/markdown", "text": text }],
+        }))
+    }
+}
+
+/// 将 Agent 结果转换为 `tools/call` 响应
+///
+/// 文本内容与 `--json` 输出相同，便于现有客户端复用解析逻辑
+fn tool_result(result: &AgentResult) -> Value {
+    let structured = serde_json::to_value(result).unwrap_or(Value::Null);
+    json!({
+        "content": [{
+            "type": "text",
+            "text": serde_json::to_string_pretty(result).unwrap_or_default(),
+        }],
+        "structuredContent": structured,
+        "isError": !result.is_success(),
+    })
+}
+
+/// 构建 JSON-RPC 错误响应
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
@@ -0,0 +1,127 @@
+//! 模拟后端
+//!
+//! `mock` 后端不访问网络：omcc 以隐藏的 `mock-cli` 子命令启动自身，按脚本逐步输出 NDJSON 事件、
+//! 写入 stderr、等待、挂起或以指定退出码退出，用于离线测试执行器的超时、重试、解析和错误处理
+//!
+//! 脚本每行一个步骤（JSON 对象），空行和 `#` 开头的行被忽略：
+//!
+//! ```text
+//! {"stdout": {"session_id": "s1", "content": "hello"}}  向 stdout 输出一行（对象序列化为 JSON，字符串原样输出）
+//! {"stderr": "warning"}                                   向 stderr 输出一行
+//! {"sleep_ms": 500}                                       等待
+//! {"hang": true}                                          挂起直到被终止
+//! {"exit": 2}                                             以指定退出码退出
+//! ```
+//!
+//! 步骤可带 `"attempt": N`，只在第 N 次（从 0 开始）启动时执行；启动次数记录在脚本旁的
+//! `<脚本>.attempts` 文件中，删除该文件即可重置。脚本执行完毕且没有 `exit` 步骤时以 0 退出
+
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::OmccError;
+
+/// 脚本步骤（同一步骤中的多个动作按字段顺序执行）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MockStep {
+    /// 只在第几次启动时执行（为空时每次都执行）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attempt: Option<u32>,
+
+    /// 输出到 stdout 的一行
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<serde_json::Value>,
+
+    /// 输出到 stderr 的一行
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+
+    /// 等待时间（毫秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sleep_ms: Option<u64>,
+
+    /// 挂起直到被终止
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub hang: bool,
+
+    /// 退出码
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit: Option<i32>,
+}
+
+/// 解析脚本内容
+pub fn parse_script(content: &str) -> Result<Vec<MockStep>, OmccError> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                OmccError::ConfigError(format!("mock 脚本第 {} 行无效：{}", index + 1, e))
+            })
+        })
+        .collect()
+}
+
+/// 执行脚本，返回退出码
+pub fn play(script: &Path) -> Result<i32, OmccError> {
+    let content = std::fs::read_to_string(script).map_err(|e| {
+        OmccError::ConfigError(format!("无法读取 mock 脚本 {}：{}", script.display(), e))
+    })?;
+    let attempt = next_attempt(script)?;
+    let steps: Vec<MockStep> = parse_script(&content)?
+        .into_iter()
+        .filter(|step| step.attempt.is_none_or(|a| a == attempt))
+        .collect();
+    play_steps(&steps)
+}
+
+/// 依次执行步骤（忽略 `attempt`），返回退出码
+pub fn play_steps(steps: &[MockStep]) -> Result<i32, OmccError> {
+    let mut stdout = std::io::stdout().lock();
+    for step in steps {
+        if let Some(ref value) = step.stdout {
+            match value {
+                serde_json::Value::String(line) => writeln!(stdout, "{}", line)?,
+                value => writeln!(stdout, "{}", value)?,
+            }
+            stdout.flush()?;
+        }
+        if let Some(ref line) = step.stderr {
+            eprintln!("{}", line);
+        }
+        if let Some(ms) = step.sleep_ms {
+            std::thread::sleep(Duration::from_millis(ms));
+        }
+        if step.hang {
+            loop {
+                std::thread::sleep(Duration::from_secs(3600));
+            }
+        }
+        if let Some(code) = step.exit {
+            return Ok(code);
+        }
+    }
+    Ok(0)
+}
+
+/// 读取并递增脚本的启动次数，返回本次的序号
+fn next_attempt(script: &Path) -> Result<u32, OmccError> {
+    let mut path = OsString::from(script.as_os_str());
+    path.push(".attempts");
+    let path = PathBuf::from(path);
+    let attempt = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(0);
+    std::fs::write(&path, (attempt + 1).to_string())?;
+    Ok(attempt)
+}
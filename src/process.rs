@@ -0,0 +1,134 @@
+//! 进程管理工具
+//!
+//! 进程存活检测、信号发送和进程组终止
+
+use std::time::Duration;
+
+use tokio::process::Child;
+use tokio_util::sync::CancellationToken;
+
+/// SIGTERM 之后等待进程退出的宽限期，超时后发送 SIGKILL
+pub const TERMINATE_GRACE: Duration = Duration::from_secs(5);
+
+/// 轮询进程组是否退出的间隔
+const GROUP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 检查进程是否存活（僵尸进程视为已退出）
+pub fn is_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: 信号 0 只做存在性和权限检查，不会真正发送信号
+    let exists = unsafe { libc::kill(pid, 0) } == 0
+        || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    exists && proc_stat(pid as u32).is_none_or(|(state, _)| state != 'Z')
+}
+
+/// 检查 `pid` 是否仍是启动时间为 `start` 的那个进程（未记录启动时间时只检查存活）
+///
+/// PID 已被其他进程复用时视为已退出
+pub fn is_running(pid: u32, start: Option<u64>) -> bool {
+    is_alive(pid) && start.is_none_or(|start| start_time(pid) == Some(start))
+}
+
+/// 检查进程组中是否仍有存活（非僵尸）进程
+pub fn group_alive(pgid: u32) -> bool {
+    let Ok(raw) = libc::pid_t::try_from(pgid) else {
+        return false;
+    };
+    // SAFETY: 信号 0 只做存在性和权限检查，不会真正发送信号
+    let exists = unsafe { libc::kill(-raw, 0) } == 0
+        || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    if !exists {
+        return false;
+    }
+    // 没有 init 回收孤儿进程时，已退出的成员会以僵尸形式残留在组内
+    match std::fs::read_dir("/proc") {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok()?.file_name().to_str()?.parse::<u32>().ok())
+            .filter_map(proc_stat)
+            .any(|(state, group)| group == pgid && state != 'Z'),
+        Err(_) => true,
+    }
+}
+
+/// 读取 `/proc/<pid>/stat` 中进程名之后的字段（第一个为进程状态）
+fn proc_stat_fields(pid: u32) -> Option<Vec<String>> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // 进程名可能包含空格和括号，从最后一个 ')' 之后开始解析
+    let fields = stat.get(stat.rfind(')')? + 1..)?.split_whitespace();
+    Some(fields.map(str::to_string).collect())
+}
+
+/// 读取 `/proc/<pid>/stat` 中的进程状态和进程组 ID
+fn proc_stat(pid: u32) -> Option<(char, u32)> {
+    let fields = proc_stat_fields(pid)?;
+    let state = fields.first()?.chars().next()?;
+    let pgrp = fields.get(2)?.parse().ok()?;
+    Some((state, pgrp))
+}
+
+/// 进程启动时间（系统启动后的时钟滴答数），与 PID 一起唯一标识进程，用于识别 PID 复用
+pub fn start_time(pid: u32) -> Option<u64> {
+    // stat 的第 22 个字段，进程名之后的第 20 个
+    proc_stat_fields(pid)?.get(19)?.parse().ok()
+}
+
+/// 向整个进程组发送信号
+pub fn signal_group(pgid: u32, signal: libc::c_int) -> std::io::Result<()> {
+    let pgid = libc::pid_t::try_from(pgid)
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+    // SAFETY: 负 pid 表示向进程组发送信号
+    if unsafe { libc::kill(-pgid, signal) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 终止子进程所在的整个进程组
+///
+/// 先发送 SIGTERM，子进程在宽限期内未退出则发送 SIGKILL，
+/// 随后继续清理组内残留的孙进程（测试运行器、语言服务器等）。
+/// 要求子进程以 `process_group(0)` 启动，`pgid` 为启动时记录的子进程 PID：
+/// 子进程已退出并被回收后 `Child::id` 不再可用，但组内的孙进程仍需清理
+pub async fn terminate_group(child: &mut Child, pgid: u32, grace: Duration) {
+    let _ = signal_group(pgid, libc::SIGTERM);
+    if tokio::time::timeout(grace, child.wait()).await.is_err() {
+        let _ = signal_group(pgid, libc::SIGKILL);
+        let _ = child.wait().await;
+    }
+    cleanup_group(pgid, grace).await;
+}
+
+/// 清理进程组中残留的进程：SIGTERM，宽限期后仍存活则 SIGKILL
+pub async fn cleanup_group(pgid: u32, grace: Duration) {
+    if !group_alive(pgid) {
+        return;
+    }
+    let _ = signal_group(pgid, libc::SIGTERM);
+    let deadline = tokio::time::Instant::now() + grace;
+    while group_alive(pgid) {
+        if tokio::time::Instant::now() >= deadline {
+            let _ = signal_group(pgid, libc::SIGKILL);
+            return;
+        }
+        tokio::time::sleep(GROUP_POLL_INTERVAL).await;
+    }
+}
+
+/// 收到 SIGINT 或 SIGTERM 时触发取消令牌
+pub fn cancel_on_signals(token: CancellationToken) -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = interrupt.recv() => {}
+            _ = terminate.recv() => {}
+        }
+        eprintln!("[OMCC] 收到终止信号，正在取消执行...");
+        token.cancel();
+    });
+    Ok(())
+}
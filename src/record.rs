@@ -0,0 +1,218 @@
+//! 上游调用记录与回放
+//!
+//! `--record <dir>` 为每次尝试在目录中保存一份记录：命令行、完整提示词、写入 stdin 的内容、
+//! 带时间戳的 stdout 行、stderr 和退出状态。`replay` 后端以隐藏的 `replay-cli` 子命令启动 omcc 自身，
+//! 按记录原样输出 stdout 和 stderr 并以相同的退出码退出，让输出重新经过执行器的解析逻辑。
+//!
+//! 记录默认与结果一样脱敏，`--record-raw` 保留原始内容；记录文件只对当前用户可读写
+
+use std::fs::{DirBuilder, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::mock::{self, MockStep};
+use crate::redact::Redactor;
+use crate::types::{AgentType, ErrorKind, OmccError};
+
+/// 单次尝试的记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    /// Agent 类型
+    pub agent: AgentType,
+
+    /// 后端
+    pub backend: String,
+
+    /// 可执行文件
+    pub program: String,
+
+    /// 命令行参数
+    pub args: Vec<String>,
+
+    /// 工作目录
+    pub cwd: PathBuf,
+
+    /// 完整提示词（含引导提示词）
+    pub prompt: String,
+
+    /// 按顺序写入 stdin 的内容
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stdin: Vec<String>,
+
+    /// 开始时间（Unix 毫秒）
+    pub started_at: u64,
+
+    /// stdout 行
+    #[serde(default)]
+    pub stdout: Vec<RecordedLine>,
+
+    /// stderr
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+
+    /// 退出码（被 omcc 终止或未能启动时为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+
+    /// 耗时（毫秒）
+    #[serde(default)]
+    pub duration_ms: u64,
+
+    /// 本次尝试的错误类型
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<ErrorKind>,
+
+    /// 本次尝试的错误信息
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// 计时起点
+    #[serde(skip)]
+    start: Option<Instant>,
+}
+
+/// 带时间戳的输出行
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedLine {
+    /// 相对开始时间的毫秒数
+    pub t_ms: u64,
+
+    /// 行内容（不含换行符）
+    pub line: String,
+}
+
+impl Recording {
+    /// 根据即将执行的命令开始记录
+    pub fn start(agent: AgentType, backend: String, cmd: &Command, prompt: &str) -> Self {
+        let cmd = cmd.as_std();
+        Self {
+            agent,
+            backend,
+            program: cmd.get_program().to_string_lossy().into_owned(),
+            args: cmd
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            cwd: cmd
+                .get_current_dir()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            prompt: prompt.to_string(),
+            stdin: Vec::new(),
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            stdout: Vec::new(),
+            stderr: String::new(),
+            exit_code: None,
+            duration_ms: 0,
+            error_kind: None,
+            error: None,
+            start: Some(Instant::now()),
+        }
+    }
+
+    /// 记录一行 stdout
+    pub fn push_stdout(&mut self, line: &str) {
+        self.stdout.push(RecordedLine {
+            t_ms: self.elapsed_ms(),
+            line: line.to_string(),
+        });
+    }
+
+    /// 记录本次尝试的结果
+    pub fn finish<T>(&mut self, outcome: &Result<T, OmccError>) {
+        self.duration_ms = self.elapsed_ms();
+        if let Err(e) = outcome {
+            self.error_kind = Some(ErrorKind::from(e));
+            self.error = Some(e.to_string());
+        }
+    }
+
+    /// 对记录中的文本脱敏：命令行参数、提示词、stdin、输出和错误信息
+    pub fn redact(&mut self, redactor: &Redactor) {
+        for text in self
+            .args
+            .iter_mut()
+            .chain(std::iter::once(&mut self.prompt))
+            .chain(self.stdin.iter_mut())
+            .chain(self.stdout.iter_mut().map(|line| &mut line.line))
+            .chain(std::iter::once(&mut self.stderr))
+            .chain(self.error.iter_mut())
+        {
+            *text = redactor.redact(text);
+        }
+    }
+
+    /// 保存到目录（新建的目录权限为 0700，文件为 0600），返回文件路径
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, OmccError> {
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let path = dir.join(format!(
+            "{}-{}-{}.json",
+            self.agent.name(),
+            self.started_at,
+            &id[..8]
+        ));
+        let content =
+            serde_json::to_vec_pretty(self).map_err(|e| OmccError::JsonDecode(e.to_string()))?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?;
+        file.write_all(&content)?;
+        Ok(path)
+    }
+
+    /// 读取记录文件
+    pub fn load(path: &Path) -> Result<Self, OmccError> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            OmccError::ConfigError(format!("无法读取记录 {}：{}", path.display(), e))
+        })?;
+        serde_json::from_str(&content)
+            .map_err(|e| OmccError::ConfigError(format!("记录 {} 无效：{}", path.display(), e)))
+    }
+
+    /// 转换为回放步骤：原样输出 stdout 和 stderr（不保留时间间隔），
+    /// 以记录的退出码退出；没有退出码（被 omcc 终止）时挂起，以便复现超时
+    pub fn steps(&self) -> Vec<MockStep> {
+        let mut steps: Vec<MockStep> = self
+            .stdout
+            .iter()
+            .map(|line| MockStep {
+                stdout: Some(serde_json::Value::String(line.line.clone())),
+                ..MockStep::default()
+            })
+            .collect();
+        steps.extend(self.stderr.lines().map(|line| MockStep {
+            stderr: Some(line.to_string()),
+            ..MockStep::default()
+        }));
+        steps.push(MockStep {
+            hang: self.exit_code.is_none(),
+            exit: self.exit_code,
+            ..MockStep::default()
+        });
+        steps
+    }
+
+    /// 距开始的毫秒数
+    fn elapsed_ms(&self) -> u64 {
+        self.start
+            .map(|start| start.elapsed().as_millis() as u64)
+            .unwrap_or(0)
+    }
+}
+
+/// 回放记录文件，返回退出码
+pub fn replay(path: &Path) -> Result<i32, OmccError> {
+    let recording = Recording::load(path)?;
+    mock::play_steps(&recording.steps())
+}
@@ -0,0 +1,280 @@
+//! 敏感信息脱敏
+//!
+//! 在结果、事件流和日志离开 omcc 之前替换其中的密钥和令牌；可选地在发送前对提示词脱敏。
+//! 内置规则覆盖常见的云服务密钥、GitHub 令牌、私钥、Bearer 令牌、环境变量赋值和高熵字符串，
+//! 配置文件的 `redact.patterns` 可追加自定义正则
+
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+use crate::types::{AgentConfig, AgentEvent, AgentResult, OmccError};
+
+/// 内置规则：名称和正则（含名为 `secret` 的分组时只替换该分组）
+const BUILTIN_RULES: [(&str, &str); 8] = [
+    (
+        "private_key",
+        r"-----BEGIN [A-Z ]*PRIVATE KEY-----(?s:.*?-----END [A-Z ]*PRIVATE KEY-----)?",
+    ),
+    ("aws_access_key", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+    (
+        "aws_secret_key",
+        r#"(?i)aws_?secret_?access_?key["']?\s*[:=]\s*["']?(?P<secret>[A-Za-z0-9/+=]{40})"#,
+    ),
+    (
+        "github_token",
+        r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{22,})",
+    ),
+    (
+        "api_key",
+        r"\b(?:sk-(?:ant-)?[A-Za-z0-9_\-]{20,}|xox[abprs]-[A-Za-z0-9\-]{10,})",
+    ),
+    (
+        "bearer_token",
+        r"(?i)\bbearer\s+(?P<secret>[A-Za-z0-9\-._~+/]{8,}=*)",
+    ),
+    (
+        "env_secret",
+        r#"(?m)^\s*(?:export\s+)?[A-Z0-9_]*(?:SECRET|TOKEN|PASSWORD|PASSWD|API_KEY|APIKEY|ACCESS_KEY)[A-Z0-9_]*\s*=\s*["']?(?P<secret>[^\s"']{4,})"#,
+    ),
+    ("high_entropy", r"[A-Za-z0-9+/=_\-]{32,}"),
+];
+
+/// 高熵字符串的最低香农熵（比特/字符），高于十六进制字符串的上限 4.0
+const MIN_ENTROPY: f64 = 4.5;
+
+/// 脱敏配置（配置文件的 `redact` 段）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedactSettings {
+    /// 是否启用脱敏
+    pub enabled: bool,
+
+    /// 是否默认对发送给底层 CLI 的提示词脱敏
+    pub prompts: bool,
+
+    /// 是否对调用记录脱敏（关闭后记录保留原始内容）
+    pub recordings: bool,
+
+    /// 自定义正则（含名为 `secret` 的分组时只替换该分组）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<String>,
+}
+
+impl Default for RedactSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prompts: false,
+            recordings: true,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+/// 脱敏规则
+#[derive(Debug)]
+struct Rule {
+    name: String,
+    regex: Regex,
+}
+
+/// 脱敏器
+#[derive(Debug)]
+pub struct Redactor {
+    rules: Vec<Rule>,
+}
+
+impl Redactor {
+    /// 按配置创建脱敏器（自定义正则无效时返回 `ConfigError`）
+    pub fn new(settings: &RedactSettings) -> Result<Self, OmccError> {
+        if !settings.enabled {
+            return Ok(Self { rules: Vec::new() });
+        }
+        let mut rules: Vec<Rule> = BUILTIN_RULES
+            .iter()
+            .map(|(name, pattern)| Rule {
+                name: name.to_string(),
+                regex: Regex::new(pattern).expect("内置脱敏规则无效"),
+            })
+            .collect();
+        // 自定义规则排在高熵检测之前
+        let custom = settings
+            .patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .map(|regex| Rule {
+                        name: "custom".to_string(),
+                        regex,
+                    })
+                    .map_err(|e| {
+                        OmccError::ConfigError(format!("无效的脱敏正则 {}：{}", pattern, e))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let entropy = rules.pop();
+        rules.extend(custom);
+        rules.extend(entropy);
+        Ok(Self { rules })
+    }
+
+    /// 替换文本中的敏感信息，返回替换后的文本和替换次数
+    pub fn redact_count(&self, text: &str) -> (String, usize) {
+        let mut text = text.to_string();
+        let mut count = 0;
+        for rule in &self.rules {
+            let replaced = rule.regex.replace_all(&text, |caps: &Captures| {
+                let whole = caps.get(0).expect("整体匹配总是存在");
+                if rule.name == "high_entropy" && !is_high_entropy(whole.as_str()) {
+                    return whole.as_str().to_string();
+                }
+                count += 1;
+                let marker = format!("[REDACTED:{}]", rule.name);
+                match caps.name("secret") {
+                    Some(secret) => {
+                        let start = secret.start() - whole.start();
+                        let end = secret.end() - whole.start();
+                        format!(
+                            "{}{}{}",
+                            &whole.as_str()[..start],
+                            marker,
+                            &whole.as_str()[end..]
+                        )
+                    }
+                    None => marker,
+                }
+            });
+            text = replaced.into_owned();
+        }
+        (text, count)
+    }
+
+    /// 替换文本中的敏感信息
+    pub fn redact(&self, text: &str) -> String {
+        self.redact_count(text).0
+    }
+}
+
+/// 获取进程级脱敏器（首次使用时按配置文件创建，配置无效时只使用内置规则）
+pub fn redactor() -> &'static Redactor {
+    static REDACTOR: OnceLock<Redactor> = OnceLock::new();
+    REDACTOR.get_or_init(|| {
+        let settings = Settings::load().map(|s| s.redact).unwrap_or_default();
+        Redactor::new(&settings).unwrap_or_else(|e| {
+            eprintln!("[OMCC] {}，只使用内置脱敏规则", e);
+            Redactor::new(&RedactSettings::default()).expect("内置脱敏规则无效")
+        })
+    })
+}
+
+/// 使用进程级脱敏器替换文本中的敏感信息
+pub fn redact(text: &str) -> String {
+    redactor().redact(text)
+}
+
+/// 对环境变量的值脱敏：名称表明其为凭据时整体隐藏，否则按规则替换
+pub fn redact_env(name: &str, value: &str) -> String {
+    let upper = name.to_ascii_uppercase();
+    let secret = [
+        "KEY",
+        "TOKEN",
+        "SECRET",
+        "PASSWORD",
+        "PASSWD",
+        "CREDENTIAL",
+        "AUTH",
+    ]
+    .iter()
+    .any(|marker| upper.contains(marker));
+    if secret {
+        "[REDACTED]".to_string()
+    } else {
+        redact(value)
+    }
+}
+
+/// 对 Agent 配置中的后端凭据脱敏（试运行报告和后台任务记录共用）
+///
+/// `api.api_key` 整体隐藏，`env.set` 中的值按 [`redact_env`] 处理
+pub fn redact_config(config: &mut AgentConfig) {
+    for settings in config.backend_settings.values_mut() {
+        if let Some(ref mut api) = settings.api {
+            if api.api_key.is_some() {
+                api.api_key = Some("[REDACTED]".to_string());
+            }
+        }
+        if let Some(ref mut env) = settings.env {
+            for (name, value) in env.set.iter_mut() {
+                *value = redact_env(name, value);
+            }
+        }
+    }
+}
+
+/// 对执行结果中的文本字段脱敏
+pub fn redact_result(result: &mut AgentResult) {
+    match result {
+        AgentResult::Success(success) => {
+            success.result = redact(&success.result);
+            if let Some(ref mut isolation) = success.isolation {
+                isolation.diff = redact(&isolation.diff);
+            }
+            if let Some(ref mut changes) = success.changes {
+                changes.diff = changes.diff.as_deref().map(redact);
+            }
+        }
+        AgentResult::Failure(failure) => {
+            failure.error = redact(&failure.error);
+            if let Some(ref mut detail) = failure.error_detail {
+                detail.message = redact(&detail.message);
+                for line in &mut detail.last_lines {
+                    *line = redact(line);
+                }
+                for attempt in &mut detail.attempts {
+                    attempt.error = redact(&attempt.error);
+                }
+            }
+            if let Some(ref mut changes) = failure.changes {
+                changes.diff = changes.diff.as_deref().map(redact);
+            }
+        }
+    }
+}
+
+/// 对执行事件中的文本字段脱敏
+pub fn redact_event(event: &mut AgentEvent) {
+    match event {
+        AgentEvent::Output { line } => *line = redact(line),
+        AgentEvent::Retrying { error, .. } | AgentEvent::Fallback { error, .. } => {
+            *error = redact(error)
+        }
+        // Finished 中的结果在执行器返回前已脱敏
+        _ => {}
+    }
+}
+
+/// 判断候选字符串是否像随机生成的密钥：同时包含字母和数字，且香农熵足够高
+fn is_high_entropy(candidate: &str) -> bool {
+    let has_digit = candidate.bytes().any(|b| b.is_ascii_digit());
+    let has_alpha = candidate.bytes().any(|b| b.is_ascii_alphabetic());
+    if !has_digit || !has_alpha {
+        return false;
+    }
+    let mut counts = [0usize; 256];
+    for byte in candidate.bytes() {
+        counts[byte as usize] += 1;
+    }
+    let len = candidate.len() as f64;
+    let entropy: f64 = counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum();
+    entropy >= MIN_ENTROPY
+}
@@ -0,0 +1,388 @@
+//! 任务注册表
+//!
+//! 管理 HTTP 服务中提交的 Agent 任务及其事件流
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+use crate::agents::{AgentExecutor, CancellationToken};
+use crate::types::{AgentConfig, AgentEvent, AgentResult, ErrorKind, OmccError};
+
+/// 事件广播缓冲区大小
+const EVENT_BUFFER: usize = 1024;
+
+/// 默认保留的已结束任务数
+const DEFAULT_MAX_FINISHED: usize = 256;
+
+/// 默认保留的每个任务事件数（超出后丢弃最早的事件）
+const DEFAULT_MAX_EVENTS: usize = 1000;
+
+/// 已结束任务的默认保留时间
+const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+
+/// 任务保留策略
+#[derive(Debug, Clone, Copy)]
+pub struct Retention {
+    /// 最多保留的已结束任务数
+    pub max_finished: usize,
+
+    /// 每个任务最多保留的事件数（SSE 回放只包含这些事件）
+    pub max_events: usize,
+
+    /// 已结束任务的保留时间
+    pub ttl: Duration,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            max_finished: DEFAULT_MAX_FINISHED,
+            max_events: DEFAULT_MAX_EVENTS,
+            ttl: DEFAULT_TTL,
+        }
+    }
+}
+
+/// 任务状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// 执行中
+    Running,
+    /// 执行成功
+    Succeeded,
+    /// 执行失败
+    Failed,
+    /// 已取消
+    Cancelled,
+}
+
+impl JobStatus {
+    /// 是否已结束
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, JobStatus::Running)
+    }
+}
+
+/// 任务快照（HTTP 响应体）
+#[derive(Debug, Clone, Serialize)]
+pub struct JobSnapshot {
+    /// 任务 ID
+    pub id: String,
+
+    /// Agent 名称
+    pub agent: String,
+
+    /// 工作目录
+    pub working_dir: PathBuf,
+
+    /// 任务状态
+    pub status: JobStatus,
+
+    /// 创建时间（Unix 秒）
+    pub created_at: u64,
+
+    /// 结束时间（Unix 秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+
+    /// 会话 ID
+    #[serde(rename = "SESSION_ID", skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+
+    /// 执行结果（与 `--json` 输出格式一致）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<AgentResult>,
+}
+
+/// 会话摘要
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    /// 会话 ID
+    #[serde(rename = "SESSION_ID")]
+    pub session_id: String,
+
+    /// Agent 名称
+    pub agent: String,
+
+    /// 工作目录
+    pub working_dir: PathBuf,
+
+    /// 最近一次使用该会话的任务 ID
+    pub last_job_id: String,
+
+    /// 最近更新时间（Unix 秒）
+    pub updated_at: u64,
+
+    /// 使用该会话的任务数
+    pub jobs: usize,
+}
+
+/// 任务记录
+struct Job {
+    snapshot: JobSnapshot,
+    events: VecDeque<AgentEvent>,
+    broadcast: Option<broadcast::Sender<AgentEvent>>,
+    cancel: CancellationToken,
+    finished: Option<Instant>,
+    task: Option<JoinHandle<()>>,
+}
+
+/// 任务注册表
+///
+/// 已结束的任务按保留策略清理，执行中的任务始终保留。
+/// 每个任务的取消令牌都是注册表根令牌的子令牌
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    jobs: Arc<Mutex<HashMap<String, Job>>>,
+    retention: Retention,
+    cancel: CancellationToken,
+}
+
+impl JobRegistry {
+    /// 创建新的注册表
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 使用指定保留策略创建注册表
+    pub fn with_retention(retention: Retention) -> Self {
+        Self {
+            retention,
+            ..Self::default()
+        }
+    }
+
+    /// 设置根取消令牌：触发时取消所有任务
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// 取消所有任务，并等待执行器终止底层 CLI
+    pub async fn shutdown(&self) {
+        self.cancel.cancel();
+        let tasks: Vec<_> = self
+            .lock()
+            .values_mut()
+            .filter_map(|job| job.task.take())
+            .collect();
+        for task in tasks {
+            let _ = task.await;
+        }
+    }
+
+    /// 提交任务并立即返回
+    pub fn submit(&self, config: AgentConfig) -> JobSnapshot {
+        self.prune();
+        let id = uuid::Uuid::new_v4().to_string();
+        let snapshot = JobSnapshot {
+            id: id.clone(),
+            agent: config.agent_type.name().to_string(),
+            working_dir: config.working_dir.clone(),
+            status: JobStatus::Running,
+            created_at: unix_now(),
+            finished_at: None,
+            session_id: config.session_id.clone(),
+            result: None,
+        };
+        let (broadcast_tx, _) = broadcast::channel(EVENT_BUFFER);
+        let cancel = self.cancel.child_token();
+        self.lock().insert(
+            id.clone(),
+            Job {
+                snapshot: snapshot.clone(),
+                events: VecDeque::new(),
+                broadcast: Some(broadcast_tx),
+                cancel: cancel.clone(),
+                finished: None,
+                task: None,
+            },
+        );
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let agent_type = config.agent_type;
+        let executor = AgentExecutor::new(config)
+            .with_events(tx)
+            .with_cancellation(cancel);
+        let execution = tokio::spawn(async move {
+            executor.execute().await;
+        });
+
+        // 转发执行事件：记录历史、广播给订阅者并更新任务状态。
+        // 执行器结束后事件流关闭，转发任务随之结束，`shutdown` 等待的就是它
+        let registry = self.clone();
+        let job_id = id.clone();
+        let task = tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                registry.record_event(&job_id, event);
+            }
+            // 执行器 panic 时不会产生 Finished 事件，补记失败结果（已结束的任务不受影响）
+            if let Err(e) = execution.await {
+                let error = OmccError::UnexpectedException(format!("执行器异常退出：{}", e));
+                let result = AgentResult::failure(
+                    agent_type,
+                    error.to_string(),
+                    ErrorKind::from(&error),
+                    None,
+                );
+                registry.record_event(&job_id, AgentEvent::Finished { result });
+            }
+            registry.close(&job_id);
+        });
+        if let Some(job) = self.lock().get_mut(&id) {
+            job.task = Some(task);
+        }
+
+        snapshot
+    }
+
+    /// 查询任务
+    pub fn get(&self, id: &str) -> Option<JobSnapshot> {
+        self.lock().get(id).map(|job| job.snapshot.clone())
+    }
+
+    /// 列出所有任务（按创建时间排序）
+    pub fn list(&self) -> Vec<JobSnapshot> {
+        let mut jobs: Vec<_> = self.lock().values().map(|j| j.snapshot.clone()).collect();
+        jobs.sort_by_key(|j| j.created_at);
+        jobs
+    }
+
+    /// 取消任务
+    ///
+    /// 触发取消令牌后立即返回；执行器终止底层 CLI 后会产生 `Cancelled` 结果并结束事件流
+    pub fn cancel(&self, id: &str) -> Option<JobSnapshot> {
+        let jobs = self.lock();
+        let job = jobs.get(id)?;
+        if !job.snapshot.status.is_terminal() {
+            job.cancel.cancel();
+        }
+        Some(job.snapshot.clone())
+    }
+
+    /// 订阅任务事件：返回历史事件和后续事件接收端（任务已结束时接收端为空）
+    pub fn subscribe(
+        &self,
+        id: &str,
+    ) -> Option<(Vec<AgentEvent>, Option<broadcast::Receiver<AgentEvent>>)> {
+        let jobs = self.lock();
+        let job = jobs.get(id)?;
+        Some((
+            job.events.iter().cloned().collect(),
+            job.broadcast.as_ref().map(|b| b.subscribe()),
+        ))
+    }
+
+    /// 列出任务中出现过的会话
+    pub fn sessions(&self) -> Vec<SessionSummary> {
+        let mut sessions: HashMap<String, SessionSummary> = HashMap::new();
+        for job in self.list() {
+            let Some(session_id) = job.session_id.clone() else {
+                continue;
+            };
+            let updated_at = job.finished_at.unwrap_or(job.created_at);
+            let entry = sessions
+                .entry(session_id.clone())
+                .or_insert_with(|| SessionSummary {
+                    session_id,
+                    agent: job.agent.clone(),
+                    working_dir: job.working_dir.clone(),
+                    last_job_id: job.id.clone(),
+                    updated_at,
+                    jobs: 0,
+                });
+            entry.jobs += 1;
+            if updated_at >= entry.updated_at {
+                entry.last_job_id = job.id.clone();
+                entry.updated_at = updated_at;
+            }
+        }
+        let mut sessions: Vec<_> = sessions.into_values().collect();
+        sessions.sort_by_key(|s| s.updated_at);
+        sessions
+    }
+
+    /// 记录一条执行事件
+    fn record_event(&self, id: &str, event: AgentEvent) {
+        let mut jobs = self.lock();
+        let Some(job) = jobs.get_mut(id) else {
+            return;
+        };
+        if job.snapshot.status.is_terminal() {
+            return;
+        }
+        match &event {
+            AgentEvent::Session { session_id } => {
+                job.snapshot.session_id = Some(session_id.clone());
+            }
+            AgentEvent::Finished { result } => {
+                job.snapshot.status = match result {
+                    AgentResult::Success(_) => JobStatus::Succeeded,
+                    AgentResult::Failure(failure) if failure.error_kind == ErrorKind::Cancelled => {
+                        JobStatus::Cancelled
+                    }
+                    AgentResult::Failure(_) => JobStatus::Failed,
+                };
+                if let AgentResult::Success(success) = result {
+                    job.snapshot.session_id = Some(success.session_id.clone());
+                }
+                job.snapshot.finished_at = Some(unix_now());
+                job.finished = Some(Instant::now());
+                job.snapshot.result = Some(result.clone());
+            }
+            _ => {}
+        }
+        if job.events.len() >= self.retention.max_events {
+            job.events.pop_front();
+        }
+        job.events.push_back(event.clone());
+        if let Some(ref broadcast) = job.broadcast {
+            let _ = broadcast.send(event);
+        }
+    }
+
+    /// 事件流结束：关闭广播
+    fn close(&self, id: &str) {
+        if let Some(job) = self.lock().get_mut(id) {
+            job.broadcast = None;
+        }
+    }
+
+    /// 清理超过保留时间或超出数量上限的已结束任务（先清理最早结束的）
+    fn prune(&self) {
+        let mut jobs = self.lock();
+        let ttl = self.retention.ttl;
+        jobs.retain(|_, job| job.finished.is_none_or(|at| at.elapsed() < ttl));
+
+        let mut finished: Vec<(Instant, String)> = jobs
+            .iter()
+            .filter_map(|(id, job)| job.finished.map(|at| (at, id.clone())))
+            .collect();
+        if finished.len() > self.retention.max_finished {
+            finished.sort();
+            let excess = finished.len() - self.retention.max_finished;
+            for (_, id) in finished.into_iter().take(excess) {
+                jobs.remove(&id);
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Job>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 当前 Unix 时间（秒）
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,317 @@
+//! HTTP 服务模块
+//!
+//! 提供本地 HTTP/JSON API：提交 Agent 任务、查询状态、SSE 事件流、取消任务和列出会话
+
+pub mod jobs;
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::{Path, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures_util::stream::{self, Stream};
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+
+pub use jobs::{JobRegistry, JobSnapshot, JobStatus, Retention, SessionSummary};
+
+use crate::agents::CancellationToken;
+use crate::mcp::schema::ToolArguments;
+use crate::settings::Settings;
+use crate::types::{AgentEvent, AgentType, OmccError, SandboxPolicy};
+
+/// 默认监听地址
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8765";
+
+/// 调用参数白名单中不允许远程调用方设置的字段（提交任务时拒绝）
+///
+/// `commands`、`backend_settings`、`path_policy` 和 `record` 不在调用参数白名单中，只能在配置文件中设置；
+/// `sandbox` 允许设置，但不能是 `danger-full-access`（见 `submit_job`）
+const CONFIG_FILE_ONLY: [&str; 2] = ["yolo", "sandbox_check"];
+
+/// 未配置访问令牌时允许的 Host（防止 DNS 重绑定）
+const LOOPBACK_HOSTS: [&str; 3] = ["localhost", "127.0.0.1", "[::1]"];
+
+/// 服务选项
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    /// 监听地址
+    pub listen: SocketAddr,
+
+    /// 访问令牌（请求需携带 `Authorization: Bearer <令牌>`）
+    pub token: Option<String>,
+}
+
+impl ServeOptions {
+    /// 校验选项：监听非回环地址时必须配置访问令牌
+    pub fn validate(&self) -> Result<(), OmccError> {
+        if self.token.as_deref().is_some_and(str::is_empty) {
+            return Err(OmccError::ConfigError("访问令牌不能为空".to_string()));
+        }
+        if !self.listen.ip().is_loopback() && self.token.is_none() {
+            return Err(OmccError::ConfigError(format!(
+                "监听非回环地址 {} 时必须通过 --token 或 OMCC_SERVE_TOKEN 设置访问令牌",
+                self.listen
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// 构建 API 路由
+///
+/// | 方法 | 路径 | 说明 |
+/// |------|------|------|
+/// | GET | `/health` | 健康检查 |
+/// | POST | `/jobs` | 提交任务（`agent` + 工具调用参数） |
+/// | GET | `/jobs` | 列出任务 |
+/// | GET | `/jobs/{id}` | 查询任务状态和结果 |
+/// | GET | `/jobs/{id}/events` | SSE 事件流 |
+/// | POST / DELETE | `/jobs/{id}/cancel`、`/jobs/{id}` | 取消任务 |
+/// | GET | `/sessions` | 列出会话 |
+///
+/// 配置了访问令牌时，除 `/health` 外的请求都需要携带令牌；
+/// 未配置时只接受 Host 为回环地址的请求
+pub fn router(registry: JobRegistry, token: Option<String>) -> Router {
+    let token = Arc::new(token);
+    Router::new()
+        .route("/jobs", post(submit_job).get(list_jobs))
+        .route("/jobs/{id}", get(get_job).delete(cancel_job))
+        .route("/jobs/{id}/cancel", post(cancel_job))
+        .route("/jobs/{id}/events", get(job_events))
+        .route("/sessions", get(list_sessions))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&token),
+            require_token,
+        ))
+        .route("/health", get(health))
+        .layer(middleware::from_fn_with_state(token, check_host))
+        .with_state(registry)
+}
+
+/// 启动 HTTP 服务
+///
+/// `cancel` 触发后停止接受请求，取消所有进行中的任务，并等待它们终止底层 CLI
+pub async fn serve(options: ServeOptions, cancel: CancellationToken) -> Result<(), OmccError> {
+    options.validate()?;
+    let listener = tokio::net::TcpListener::bind(options.listen).await?;
+    eprintln!("[OMCC] HTTP 服务已启动：http://{}", listener.local_addr()?);
+    let registry = JobRegistry::new().with_cancellation(cancel.clone());
+    axum::serve(listener, router(registry.clone(), options.token))
+        .with_graceful_shutdown(cancel.cancelled_owned())
+        .await?;
+    registry.shutdown().await;
+    Ok(())
+}
+
+/// 未配置访问令牌时拒绝 Host 不是回环地址的请求
+async fn check_host(
+    State(token): State<Arc<Option<String>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if token.is_none() {
+        let host = request
+            .headers()
+            .get(header::HOST)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        if !is_loopback_host(host) {
+            return ApiError(StatusCode::FORBIDDEN, format!("不允许的 Host：{}", host))
+                .into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// 配置了访问令牌时校验 `Authorization: Bearer <令牌>`
+async fn require_token(
+    State(token): State<Arc<Option<String>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(ref expected) = *token {
+        let provided = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if !constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+            return ApiError(StatusCode::UNAUTHORIZED, "访问令牌无效".to_string()).into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// Host 头（去掉端口）是否为回环地址
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.rsplit_once(':') {
+        // IPv6 地址的端口在方括号之后
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+    LOOPBACK_HOSTS
+        .iter()
+        .any(|allowed| name.eq_ignore_ascii_case(allowed))
+}
+
+/// 比较令牌（耗时与不匹配的位置无关）
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// API 错误响应
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// 任务不存在
+fn job_not_found(id: &str) -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, format!("任务不存在：{}", id))
+}
+
+async fn health() -> Json<Value> {
+    Json(json!({ "status": "ok", "version": env!("CARGO_PKG_VERSION") }))
+}
+
+/// 提交任务
+///
+/// 请求体为 `agent` 字段加上 MCP 工具调用参数（同一白名单，未知字段返回 400）；
+/// 后端命令、环境变量、路径策略和调用记录等只能在配置文件中设置
+async fn submit_job(
+    State(registry): State<JobRegistry>,
+    Json(mut body): Json<Value>,
+) -> Result<(StatusCode, Json<JobSnapshot>), ApiError> {
+    let bad_request = |message: String| ApiError(StatusCode::BAD_REQUEST, message);
+    let object = body
+        .as_object_mut()
+        .ok_or_else(|| bad_request("请求体必须是 JSON 对象".to_string()))?;
+    let agent_type = object
+        .remove("agent")
+        .ok_or_else(|| bad_request("缺少必填字段 agent".to_string()))
+        .and_then(|agent| {
+            serde_json::from_value::<AgentType>(agent)
+                .map_err(|e| bad_request(format!("agent 无效：{}", e)))
+        })?;
+    if let Some(field) = CONFIG_FILE_ONLY.iter().find(|f| object.contains_key(**f)) {
+        return Err(bad_request(format!("HTTP 接口不允许设置 {}", field)));
+    }
+    // 配置文件无效是服务端的问题，不是请求错误
+    let settings =
+        Settings::load().map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let config = ToolArguments::from_value(Some(body))
+        .and_then(|args| args.into_config(agent_type, &settings))
+        .map_err(|e| bad_request(e.to_string()))?;
+    // 完全访问等同于 YOLO（OpenCode 放行全部权限、Gemini 自动批准全部工具）
+    if config.sandbox == SandboxPolicy::DangerFullAccess {
+        return Err(bad_request(format!(
+            "HTTP 接口不允许使用 {} 沙箱策略",
+            SandboxPolicy::DangerFullAccess.as_arg()
+        )));
+    }
+
+    Ok((StatusCode::ACCEPTED, Json(registry.submit(config))))
+}
+
+async fn list_jobs(State(registry): State<JobRegistry>) -> Json<Vec<JobSnapshot>> {
+    Json(registry.list())
+}
+
+async fn get_job(
+    State(registry): State<JobRegistry>,
+    Path(id): Path<String>,
+) -> Result<Json<JobSnapshot>, ApiError> {
+    registry
+        .get(&id)
+        .map(Json)
+        .ok_or_else(|| job_not_found(&id))
+}
+
+async fn cancel_job(
+    State(registry): State<JobRegistry>,
+    Path(id): Path<String>,
+) -> Result<Json<JobSnapshot>, ApiError> {
+    registry
+        .cancel(&id)
+        .map(Json)
+        .ok_or_else(|| job_not_found(&id))
+}
+
+async fn list_sessions(State(registry): State<JobRegistry>) -> Json<Vec<SessionSummary>> {
+    Json(registry.sessions())
+}
+
+/// SSE 事件流
+///
+/// 先回放历史事件，再推送后续事件；任务结束后发送 `end` 事件（数据为任务快照）并关闭
+async fn job_events(
+    State(registry): State<JobRegistry>,
+    Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let (history, receiver) = registry.subscribe(&id).ok_or_else(|| job_not_found(&id))?;
+
+    struct StreamState {
+        registry: JobRegistry,
+        id: String,
+        history: VecDeque<AgentEvent>,
+        receiver: Option<broadcast::Receiver<AgentEvent>>,
+        done: bool,
+    }
+
+    let state = StreamState {
+        registry,
+        id,
+        history: history.into(),
+        receiver,
+        done: false,
+    };
+
+    let stream = stream::unfold(state, |mut state| async move {
+        if state.done {
+            return None;
+        }
+        if let Some(event) = state.history.pop_front() {
+            return Some((Ok(agent_event(&event)), state));
+        }
+        if let Some(receiver) = state.receiver.as_mut() {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((Ok(agent_event(&event)), state)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }
+        state.done = true;
+        let snapshot = state.registry.get(&state.id);
+        let event = Event::default()
+            .event("end")
+            .data(serde_json::to_string(&snapshot).unwrap_or_default());
+        Some((Ok(event), state))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// 将执行事件转换为 SSE 事件（事件名即 `event` 字段）
+fn agent_event(event: &AgentEvent) -> Event {
+    let data = serde_json::to_value(event).unwrap_or(Value::Null);
+    let name = data
+        .get("event")
+        .and_then(|e| e.as_str())
+        .unwrap_or("message")
+        .to_string();
+    Event::default().event(name).data(data.to_string())
+}
@@ -0,0 +1,224 @@
+//! 用户配置文件
+//!
+//! 从 JSON 配置文件读取按 Agent 区分的默认设置，命令行参数和调用参数优先于配置文件
+//!
+//! ```json
+//! {
+//!   "agents": {
+//!     "reviewer": {
+//!       "max_retries": 2,
+//!       "fallbacks": ["claude:sonnet", "opencode"],
+//!       "retry": { "initial_delay_ms": 1000, "retry_on": ["upstream_error"], "budget_s": 600 }
+//!     },
+//!     "chore": {
+//!       "paths": { "allow": ["src/**", "tests/**"], "deny": [".env*", "Cargo.lock"], "rollback": true }
+//!     }
+//!   },
+//!   "redact": { "prompts": true, "patterns": ["corp_[a-z0-9]{24}"] },
+//!   "commands": {
+//!     "aider": {
+//!       "program": "aider",
+//!       "args": ["--message-file", "{prompt}", ["--model", "{model}"], "--yes-always", "--no-stream"],
+//!       "prompt": "file"
+//!     }
+//!   },
+//!   "backends": {
+//!     "codex": {
+//!       "env": { "clear": true, "pass": ["OPENAI_*"], "set": { "HTTPS_PROXY": "http://127.0.0.1:7890" }, "file": "codex.env" },
+//!       "circuit_breaker": { "failure_threshold": 5, "cooldown_s": 120 },
+//!       "rate_limit": { "max_concurrent": 2, "per_minute": 10, "max_wait_s": 30 }
+//!     },
+//!     "openai": {
+//!       "api": { "base_url": "http://127.0.0.1:8000/v1", "api_key_env": "LOCAL_LLM_KEY", "model": "qwen2.5-coder" }
+//!     }
+//!   }
+//! }
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::redact::{RedactSettings, Redactor};
+use crate::types::{
+    AgentConfig, AgentType, BackendSettings, BackendSpec, CliTool, CustomCommand, ErrorKind,
+    OmccError, PathPolicy, RetryPolicy,
+};
+
+/// 单个 Agent 的配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentSettings {
+    /// 最大重试次数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
+
+    /// 重试策略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+
+    /// 是否创建工作区检查点
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checkpoint: Option<bool>,
+
+    /// 工作区写锁的最长等待时间（秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wait_lock: Option<u64>,
+
+    /// 路径策略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub paths: Option<PathPolicy>,
+
+    /// 保存调用记录的目录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record: Option<PathBuf>,
+
+    /// 主后端
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<BackendSpec>,
+
+    /// 备用后端（按顺序尝试）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallbacks: Option<Vec<BackendSpec>>,
+
+    /// 触发切换备用后端的错误类型
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_on: Option<HashSet<ErrorKind>>,
+}
+
+/// 配置文件内容
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// 按 Agent 名称区分的配置
+    pub agents: HashMap<AgentType, AgentSettings>,
+
+    /// 按底层 CLI 区分的熔断、限流和环境变量设置
+    pub backends: HashMap<CliTool, BackendSettings>,
+
+    /// 自定义命令后端（以 `custom:<名称>` 引用）
+    pub commands: BTreeMap<String, CustomCommand>,
+
+    /// 敏感信息脱敏
+    pub redact: RedactSettings,
+}
+
+impl Settings {
+    /// 获取配置文件路径
+    ///
+    /// 优先级：`OMCC_CONFIG` > `$XDG_CONFIG_HOME/omcc/config.json` > `~/.config/omcc/config.json`
+    pub fn path() -> PathBuf {
+        if let Some(path) = std::env::var_os("OMCC_CONFIG").filter(|p| !p.is_empty()) {
+            return PathBuf::from(path);
+        }
+        if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+            return PathBuf::from(dir).join("omcc").join("config.json");
+        }
+        let home = std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        home.join(".config").join("omcc").join("config.json")
+    }
+
+    /// 读取配置文件，文件不存在时返回默认配置
+    pub fn load() -> Result<Self, OmccError> {
+        let path = Self::path();
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(OmccError::IoError(e)),
+        };
+        let mut settings: Self = serde_json::from_str(&content).map_err(|e| {
+            OmccError::ConfigError(format!("配置文件 {} 无效：{}", path.display(), e))
+        })?;
+        // 环境变量文件和调用记录目录的相对路径基于配置文件所在目录
+        let base = path.parent().unwrap_or(Path::new("."));
+        for backend in settings.backends.values_mut() {
+            if let Some(file) = backend.env.as_mut().and_then(|env| env.file.as_mut()) {
+                *file = resolve_path(base, file);
+            }
+        }
+        for agent in settings.agents.values_mut() {
+            if let Some(record) = agent.record.as_mut() {
+                *record = resolve_path(base, record);
+            }
+        }
+        // 提前校验自定义脱敏正则
+        Redactor::new(&settings.redact)?;
+        Ok(settings)
+    }
+
+    /// 获取指定 Agent 的配置
+    pub fn agent(&self, agent_type: AgentType) -> Option<&AgentSettings> {
+        self.agents.get(&agent_type)
+    }
+
+    /// 将配置文件中的默认值填入 Agent 配置（只填充未设置的字段）
+    pub fn apply(&self, config: &mut AgentConfig) {
+        for (tool, backend) in &self.backends {
+            config
+                .backend_settings
+                .entry(*tool)
+                .or_insert_with(|| backend.clone());
+        }
+        for (name, command) in &self.commands {
+            config
+                .commands
+                .entry(name.clone())
+                .or_insert_with(|| command.clone());
+        }
+
+        if self.redact.enabled && self.redact.prompts {
+            config.redact_prompt = true;
+        }
+        if !self.redact.recordings {
+            config.record_raw = true;
+        }
+
+        let Some(agent) = self.agent(config.agent_type) else {
+            return;
+        };
+        if config.max_retries.is_none() {
+            config.max_retries = agent.max_retries;
+        }
+        if config.retry_policy.is_none() {
+            config.retry_policy = agent.retry.clone();
+        }
+        if !config.checkpoint {
+            config.checkpoint = agent.checkpoint.unwrap_or(false);
+        }
+        if config.lock_wait.is_none() {
+            config.lock_wait = agent.wait_lock;
+        }
+        if config.path_policy.is_none() {
+            config.path_policy = agent.paths.clone();
+        }
+        if config.record.is_none() {
+            config.record = agent.record.clone();
+        }
+        if config.backend.is_none() {
+            config.backend = agent.backend.clone();
+        }
+        if config.fallbacks.is_empty() {
+            config.fallbacks = agent.fallbacks.clone().unwrap_or_default();
+        }
+        if config.fallback_on.is_none() {
+            config.fallback_on = agent.fallback_on.clone();
+        }
+    }
+}
+
+/// 展开 `~/` 前缀，并将相对路径解析到 `base` 下
+fn resolve_path(base: &Path, path: &Path) -> PathBuf {
+    if let Ok(rest) = path.strip_prefix("~") {
+        if let Some(home) = std::env::var_os("HOME") {
+            return PathBuf::from(home).join(rest);
+        }
+    }
+    if path.is_relative() {
+        base.join(path)
+    } else {
+        path.to_path_buf()
+    }
+}
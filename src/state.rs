@@ -0,0 +1,89 @@
+//! 本地状态目录
+//!
+//! omcc 在状态目录中持久化后台任务等跨进程数据，并通过文件锁保证并发安全。
+//! 目录以 0700、文件以 0600 权限创建，只有当前用户可以读取
+
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// 获取状态目录
+///
+/// 优先级：`OMCC_STATE_DIR` > `$XDG_STATE_HOME/omcc` > `~/.local/state/omcc`
+pub fn state_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("OMCC_STATE_DIR").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    if let Some(dir) = std::env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir).join("omcc");
+    }
+    let home = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    home.join(".local").join("state").join("omcc")
+}
+
+/// 获取（并创建）状态目录下的子目录
+pub fn state_subdir(name: &str) -> io::Result<PathBuf> {
+    let dir = state_dir().join(name);
+    create_private_dir(&dir)?;
+    Ok(dir)
+}
+
+/// 创建仅当前用户可访问的目录（含缺失的上级目录）
+pub fn create_private_dir(dir: &Path) -> io::Result<()> {
+    DirBuilder::new().recursive(true).mode(0o700).create(dir)
+}
+
+/// 创建（或截断）仅当前用户可读写的文件
+pub fn create_private_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+}
+
+/// 原子写入文件（先写临时文件再重命名）
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+    // 删除上次异常退出遗留的临时文件，保证新文件按 0600 创建
+    let _ = fs::remove_file(&tmp);
+    create_private_file(&tmp)?.write_all(contents)?;
+    fs::rename(&tmp, path)
+}
+
+/// 排他文件锁（`flock`），离开作用域时自动释放
+pub struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    /// 阻塞获取锁文件上的排他锁
+    pub fn acquire(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .mode(0o600)
+            .open(path)?;
+        // SAFETY: 文件描述符在 `file` 生命周期内有效
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { file })
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // SAFETY: 文件描述符在 `self.file` 生命周期内有效
+        unsafe {
+            libc::flock(self.file.as_raw_fd(), libc::LOCK_UN);
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//! 后端配置定义
+//!
+//! 按底层 CLI 区分的熔断、限流和环境变量设置，由配置文件的 `backends` 段提供
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::error::OmccError;
+
+/// 清空环境变量时始终保留的变量（底层 CLI 运行所必需）
+const ALWAYS_PASS: [&str; 6] = ["PATH", "HOME", "USER", "LANG", "LC_*", "TMPDIR"];
+
+/// 单个后端的配置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackendSettings {
+    /// 熔断器
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub circuit_breaker: Option<CircuitBreakerPolicy>,
+
+    /// 限流
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitPolicy>,
+
+    /// 环境变量
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<EnvPolicy>,
+
+    /// HTTP API（`openai` 后端）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api: Option<ApiSettings>,
+}
+
+/// 熔断策略：连续失败达到阈值后，在冷却时间内直接拒绝调用
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CircuitBreakerPolicy {
+    /// 触发熔断的连续失败次数
+    pub failure_threshold: u32,
+
+    /// 熔断冷却时间（秒），之后允许再次尝试
+    pub cooldown_s: u64,
+}
+
+impl Default for CircuitBreakerPolicy {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown_s: 60,
+        }
+    }
+}
+
+/// 限流策略：并发数上限和每分钟调用次数（令牌桶）
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitPolicy {
+    /// 同时运行的调用数上限
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_concurrent: Option<u32>,
+
+    /// 每分钟调用次数上限（令牌桶容量，按速率匀速补充）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_minute: Option<u32>,
+
+    /// 达到上限时的最长等待时间（秒），0 表示立即失败
+    pub max_wait_s: u64,
+}
+
+/// 底层 CLI 的环境变量策略
+///
+/// 优先级：继承的环境变量 < `file` < `set`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvPolicy {
+    /// 是否清空从 omcc 继承的环境变量（`PATH`、`HOME`、`USER`、`LANG`、`LC_*`、`TMPDIR` 始终保留）
+    pub clear: bool,
+
+    /// 清空时额外保留的变量（支持 `*` 后缀通配，如 `OPENAI_*`）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pass: Vec<String>,
+
+    /// 额外设置的变量（如 API 地址、代理）
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<String, String>,
+
+    /// 从 `.env` 格式的文件加载变量（相对路径基于配置文件所在目录）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+}
+
+impl EnvPolicy {
+    /// 清空环境变量时是否保留该变量
+    pub fn passes(&self, name: &str) -> bool {
+        ALWAYS_PASS
+            .iter()
+            .copied()
+            .chain(self.pass.iter().map(String::as_str))
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+
+    /// 读取 `file` 中的变量
+    ///
+    /// 每行一个 `KEY=VALUE`，支持 `export` 前缀、`#` 注释和成对的引号，不做变量展开；
+    /// 变量名不是合法标识符的行被忽略
+    pub fn file_vars(&self) -> Result<Vec<(String, String)>, OmccError> {
+        let Some(ref file) = self.file else {
+            return Ok(Vec::new());
+        };
+        let content = std::fs::read_to_string(file).map_err(|e| {
+            OmccError::ConfigError(format!("无法读取环境变量文件 {}：{}", file.display(), e))
+        })?;
+        let mut vars = Vec::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim();
+            if !is_env_name(key) {
+                continue;
+            }
+            let value = value.trim();
+            let value = ['"', '\'']
+                .iter()
+                .find_map(|q| value.strip_prefix(*q).and_then(|v| v.strip_suffix(*q)))
+                .unwrap_or(value);
+            vars.push((key.to_string(), value.to_string()));
+        }
+        Ok(vars)
+    }
+}
+
+/// 是否为合法的环境变量名（字母或下划线开头，只含字母、数字和下划线）
+fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// OpenAI 兼容 HTTP API 的连接设置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiSettings {
+    /// API 地址（请求发送到 `<base_url>/chat/completions`）
+    pub base_url: String,
+
+    /// API 密钥（建议改用 `api_key_env`）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+
+    /// 读取 API 密钥的环境变量（依次查找 `env.set` 和 omcc 自身的环境变量）
+    pub api_key_env: String,
+
+    /// 未通过 `openai:<model>` 指定模型时使用的默认模型
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        Self {
+            base_url: "https://api.openai.com/v1".to_string(),
+            api_key: None,
+            api_key_env: "OPENAI_API_KEY".to_string(),
+            model: None,
+        }
+    }
+}
+
+/// 配置文件定义的自定义命令后端，以 `custom:<名称>[:<模型>]` 引用
+///
+/// 接入新的 CLI 只需描述如何构造命令行和如何解析输出，不需要修改代码
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomCommand {
+    /// 可执行文件
+    pub program: String,
+
+    /// 参数模板，支持 `{prompt}`、`{model}`、`{session_id}`、`{sandbox}`、`{image}` 占位符
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<ArgTemplate>,
+
+    /// 提示词传递方式
+    #[serde(default)]
+    pub prompt: PromptDelivery,
+
+    /// 输出解析规则（未设置时每行输出都作为结果文本）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputRules>,
+}
+
+/// 参数模板：单个参数或需要一起保留、一起省略的一组参数（如 `["--model", "{model}"]`）
+///
+/// 占位符没有值时整项省略；含 `{image}` 的项按图片逐个重复
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ArgTemplate {
+    /// 单个参数
+    Arg(String),
+    /// 一组参数
+    Group(Vec<String>),
+}
+
+/// 提示词传递方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptDelivery {
+    /// 写入 stdin 后关闭
+    #[default]
+    Stdin,
+    /// 作为参数（`{prompt}` 展开为提示词）
+    Arg,
+    /// 写入临时文件（`{prompt}` 展开为文件路径，执行结束后删除）
+    File,
+}
+
+/// 输出解析规则：对每行 JSON 输出按 JSON 指针（如 `/message/content`）提取字段
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputRules {
+    /// 会话 ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+
+    /// 结果文本（未设置时每行输出都作为结果文本；非 JSON 行始终作为结果文本）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+
+    /// 错误信息（提取到非空值时终止执行并返回上游错误）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// token 用量对象（识别 `input_tokens` / `prompt_tokens` 和 `output_tokens` / `completion_tokens`）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<String>,
+}
+
+impl CustomCommand {
+    /// 展开参数模板
+    ///
+    /// `values` 为占位符名称及其值（`{image}` 除外），`images` 为按顺序展开 `{image}` 的图片路径
+    pub fn expand_args(&self, values: &[(&str, Option<&str>)], images: &[String]) -> Vec<String> {
+        let mut args = Vec::new();
+        for template in &self.args {
+            let parts = match template {
+                ArgTemplate::Arg(arg) => std::slice::from_ref(arg),
+                ArgTemplate::Group(group) => group.as_slice(),
+            };
+            let repeat: Vec<Option<&str>> = if parts.iter().any(|p| p.contains("{image}")) {
+                images.iter().map(|image| Some(image.as_str())).collect()
+            } else {
+                vec![None]
+            };
+            for image in repeat {
+                let expanded: Option<Vec<String>> = parts
+                    .iter()
+                    .map(|part| expand_placeholders(part, image, values))
+                    .collect();
+                args.extend(expanded.into_iter().flatten());
+            }
+        }
+        args
+    }
+}
+
+/// 单遍展开一个参数中的占位符，替换进来的值不会再被展开
+///
+/// 占位符没有值时返回 `None`；未知的 `{...}` 原样保留
+fn expand_placeholders(
+    part: &str,
+    image: Option<&str>,
+    values: &[(&str, Option<&str>)],
+) -> Option<String> {
+    let mut expanded = String::with_capacity(part.len());
+    let mut rest = part;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let name = tail[1..].find('}').map(|end| &tail[1..end + 1]);
+        let value = match name {
+            Some("image") => Some(image),
+            Some(name) => values
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| *value),
+            None => None,
+        };
+        match (name, value) {
+            (Some(name), Some(value)) => {
+                expanded.push_str(value?);
+                rest = &tail[name.len() + 2..];
+            }
+            _ => {
+                expanded.push('{');
+                rest = &tail[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Some(expanded)
+}
+
+impl OutputRules {
+    /// 解析一行输出
+    pub fn parse(&self, line: &str) -> ParsedLine {
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(line) else {
+            // 非 JSON 行整行作为结果文本
+            return ParsedLine {
+                text: Some(line.to_string()).filter(|text| !text.is_empty()),
+                ..ParsedLine::default()
+            };
+        };
+        ParsedLine {
+            session_id: pointer_str(&json, self.session_id.as_deref()),
+            text: match self.text {
+                Some(ref pointer) => pointer_str(&json, Some(pointer)),
+                None => Some(line.to_string()),
+            },
+            error: pointer_str(&json, self.error.as_deref()),
+            usage: self
+                .usage
+                .as_deref()
+                .and_then(|pointer| json.pointer(pointer))
+                .cloned(),
+        }
+    }
+}
+
+/// 按输出规则从一行输出中提取的字段
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedLine {
+    /// 会话 ID
+    pub session_id: Option<String>,
+
+    /// 结果文本
+    pub text: Option<String>,
+
+    /// 错误信息
+    pub error: Option<String>,
+
+    /// token 用量对象
+    pub usage: Option<serde_json::Value>,
+}
+
+/// 按 JSON 指针提取字符串（非字符串的值序列化为 JSON，`null` 和空字符串视为没有值）
+fn pointer_str(json: &serde_json::Value, pointer: Option<&str>) -> Option<String> {
+    match json.pointer(pointer?)? {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(s) if s.is_empty() => None,
+        serde_json::Value::String(s) => Some(s.clone()),
+        value => Some(value.to_string()),
+    }
+}
@@ -0,0 +1,204 @@
+//! 后端能力定义
+//!
+//! 每个后端声明支持的选项，执行前据此校验 Agent 配置：不支持的选项返回配置错误或给出警告，不会被静默忽略
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::backend::CustomCommand;
+use super::config::{AgentConfig, AgentType, BackendSpec, CliTool};
+use super::error::OmccError;
+
+/// 视为图片的文件扩展名（其他文件按普通附件处理）
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "webp", "bmp"];
+
+/// 后端能力
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// 附加图片
+    pub images: bool,
+
+    /// 附加图片以外的文件（如 PDF）
+    pub files: bool,
+
+    /// 通过 SESSION_ID 继续会话
+    pub sessions: bool,
+
+    /// 执行沙箱策略
+    pub sandbox: bool,
+
+    /// 指定模型
+    pub model: bool,
+
+    /// 流式输出（执行过程中逐行产生事件）
+    pub streaming: bool,
+
+    /// 报告 token 用量
+    pub usage: bool,
+}
+
+impl Capabilities {
+    /// 支持全部选项（测试替身）
+    const ALL: Self = Self {
+        images: true,
+        files: true,
+        sessions: true,
+        sandbox: true,
+        model: true,
+        streaming: true,
+        usage: true,
+    };
+}
+
+impl CliTool {
+    /// 内置后端的能力（`custom` 后端的能力由命令定义决定，参见 [`CustomCommand::capabilities`]）
+    pub fn capabilities(&self) -> Capabilities {
+        let all = Capabilities::ALL;
+        match self {
+            CliTool::Claude => Capabilities {
+                images: false,
+                files: false,
+                ..all
+            },
+            CliTool::Codex => Capabilities {
+                files: false,
+                ..all
+            },
+            CliTool::OpenCode => Capabilities {
+                usage: false,
+                ..all
+            },
+            CliTool::Gemini => Capabilities {
+                images: false,
+                files: false,
+                ..all
+            },
+            CliTool::OpenAi => Capabilities {
+                images: false,
+                files: false,
+                sandbox: false,
+                ..all
+            },
+            CliTool::Mock | CliTool::Replay => all,
+            CliTool::Custom => Capabilities {
+                images: false,
+                files: false,
+                sessions: false,
+                sandbox: false,
+                model: false,
+                streaming: true,
+                usage: false,
+            },
+        }
+    }
+}
+
+impl CustomCommand {
+    /// 根据参数模板中的占位符和输出规则推断能力
+    pub fn capabilities(&self) -> Capabilities {
+        let uses = |placeholder: &str| {
+            self.args.iter().any(|template| match template {
+                super::backend::ArgTemplate::Arg(arg) => arg.contains(placeholder),
+                super::backend::ArgTemplate::Group(group) => {
+                    group.iter().any(|arg| arg.contains(placeholder))
+                }
+            })
+        };
+        Capabilities {
+            images: uses("{image}"),
+            files: uses("{image}"),
+            sessions: uses("{session_id}"),
+            sandbox: uses("{sandbox}"),
+            model: uses("{model}"),
+            streaming: true,
+            usage: self.output.as_ref().is_some_and(|o| o.usage.is_some()),
+        }
+    }
+}
+
+impl AgentConfig {
+    /// 获取后端的能力（未定义的自定义命令视为不支持任何选项）
+    pub fn capabilities(&self, backend: &BackendSpec) -> Capabilities {
+        match backend.command {
+            Some(ref name) if backend.tool == CliTool::Custom => self
+                .commands
+                .get(name)
+                .map(CustomCommand::capabilities)
+                .unwrap_or_else(|| CliTool::Custom.capabilities()),
+            _ => backend.tool.capabilities(),
+        }
+    }
+
+    /// 附加的图片：`images` 以及 Looker 要分析的图片文件
+    pub fn attached_images(&self) -> Vec<&Path> {
+        let mut images: Vec<&Path> = self.images.iter().map(|p| p.as_path()).collect();
+        images.extend(self.looker_file().filter(|path| is_image(path)));
+        images
+    }
+
+    /// 附加的非图片文件：Looker 要分析的 PDF 等文件
+    pub fn attached_files(&self) -> Vec<&Path> {
+        self.looker_file()
+            .filter(|path| !is_image(path))
+            .into_iter()
+            .collect()
+    }
+
+    /// Looker 要分析的文件
+    fn looker_file(&self) -> Option<&Path> {
+        (self.agent_type == AgentType::Looker)
+            .then_some(self.file_path.as_deref())
+            .flatten()
+    }
+
+    /// 按后端能力校验配置
+    ///
+    /// 后端无法处理的附件、会话和模型返回 `ConfigError`；无法执行的沙箱策略和无法提供的 token 用量
+    /// 只影响安全边界的说明或指标，返回警告。`resume` 表示本次调用会传入 SESSION_ID
+    pub fn check_backend(
+        &self,
+        backend: &BackendSpec,
+        resume: bool,
+    ) -> Result<Vec<String>, OmccError> {
+        let caps = self.capabilities(backend);
+        let name = backend.name();
+        let unsupported =
+            |what: &str| OmccError::ConfigError(format!("后端 {} 不支持{}", name, what));
+
+        if !caps.images && !self.attached_images().is_empty() {
+            return Err(unsupported("附加图片"));
+        }
+        if let Some(file) = self.attached_files().first().filter(|_| !caps.files) {
+            return Err(unsupported(&format!("附加文件 {}", file.display())));
+        }
+        if resume && !caps.sessions {
+            return Err(unsupported("通过 SESSION_ID 继续会话"));
+        }
+        if backend.model.is_some() && !caps.model {
+            return Err(unsupported("指定模型"));
+        }
+
+        let mut warnings = Vec::new();
+        // HTTP 后端不访问工作区，只读策略天然满足
+        let sandbox_matters = backend.tool != CliTool::OpenAi || self.sandbox.allows_writes();
+        if !caps.sandbox && sandbox_matters {
+            warnings.push(format!(
+                "后端 {} 不支持沙箱策略，{} 不会生效",
+                name,
+                self.sandbox.as_arg()
+            ));
+        }
+        if self.return_metrics && !caps.usage {
+            warnings.push(format!("后端 {} 不报告 token 用量", name));
+        }
+        Ok(warnings)
+    }
+}
+
+/// 是否为图片文件（按扩展名判断）
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
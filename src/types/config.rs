@@ -3,10 +3,16 @@
 //! 定义 Agent 配置和运行时参数
 
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
 
+use super::backend::{BackendSettings, CustomCommand};
+use super::error::ErrorKind;
+use super::policy::PathPolicy;
+use super::retry::RetryPolicy;
+
 /// Agent 类型枚举
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum AgentType {
     /// 代码审核者（原 Codex）
@@ -103,7 +109,8 @@ impl AgentType {
 }
 
 /// CLI 工具类型
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CliTool {
     /// Claude CLI (Anthropic)
     Claude,
@@ -111,6 +118,16 @@ pub enum CliTool {
     Codex,
     /// OpenCode CLI (https://opencode.ai)
     OpenCode,
+    /// Gemini CLI (Google)
+    Gemini,
+    /// 模拟后端（按脚本回放事件，用于离线测试）
+    Mock,
+    /// 回放后端（回放 `--record` 保存的调用记录）
+    Replay,
+    /// OpenAI 兼容 HTTP API（不依赖外部 CLI）
+    OpenAi,
+    /// 配置文件定义的自定义命令
+    Custom,
 }
 
 impl CliTool {
@@ -120,10 +137,148 @@ impl CliTool {
             CliTool::Claude => "claude",
             CliTool::Codex => "codex",
             CliTool::OpenCode => "opencode",
+            CliTool::Gemini => "gemini",
+            CliTool::Mock => "mock",
+            CliTool::Replay => "replay",
+            CliTool::OpenAi => "openai",
+            CliTool::Custom => "custom",
+        }
+    }
+
+    /// 获取实际执行的程序路径
+    ///
+    /// 可通过环境变量 `OMCC_<TOOL>_BIN`（如 `OMCC_CODEX_BIN`）覆盖，用于非标准安装路径或测试替身。
+    /// `mock` 和 `replay` 后端默认为 omcc 自身（通过隐藏的 `mock-cli` / `replay-cli` 子命令）
+    pub fn program(&self) -> String {
+        let var = format!("OMCC_{}_BIN", self.command().to_uppercase());
+        std::env::var(var)
+            .ok()
+            .filter(|p| !p.is_empty())
+            .unwrap_or_else(|| match self {
+                CliTool::Mock | CliTool::Replay => std::env::current_exe()
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .unwrap_or_else(|_| "omcc".to_string()),
+                _ => self.command().to_string(),
+            })
+    }
+}
+
+impl std::str::FromStr for CliTool {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "claude" => Ok(CliTool::Claude),
+            "codex" => Ok(CliTool::Codex),
+            "opencode" => Ok(CliTool::OpenCode),
+            "gemini" => Ok(CliTool::Gemini),
+            "mock" => Ok(CliTool::Mock),
+            "replay" => Ok(CliTool::Replay),
+            "openai" => Ok(CliTool::OpenAi),
+            "custom" => Ok(CliTool::Custom),
+            _ => Err(format!("未知的后端: {}", s)),
+        }
+    }
+}
+
+/// 后端规格：底层 CLI 及可选的模型，字符串形式为 `<cli>[:<model>]`（如 `codex:gpt-5`），
+/// 自定义命令为 `custom:<name>[:<model>]`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct BackendSpec {
+    /// 底层 CLI
+    pub tool: CliTool,
+
+    /// 模型（未指定时使用 CLI 默认模型）
+    pub model: Option<String>,
+
+    /// 自定义命令名称（仅 `custom` 后端）
+    pub command: Option<String>,
+}
+
+impl BackendSpec {
+    /// 创建后端规格
+    pub fn new(tool: CliTool, model: Option<String>) -> Self {
+        Self {
+            tool,
+            model,
+            command: None,
+        }
+    }
+
+    /// 创建自定义命令的后端规格
+    pub fn custom(command: impl Into<String>, model: Option<String>) -> Self {
+        Self {
+            tool: CliTool::Custom,
+            model,
+            command: Some(command.into()),
+        }
+    }
+
+    /// 后端名称（自定义命令为 `custom:<name>`）
+    pub fn name(&self) -> String {
+        match self.command {
+            Some(ref command) => format!("{}:{}", self.tool.command(), command),
+            None => self.tool.command().to_string(),
         }
     }
 }
 
+impl std::fmt::Display for BackendSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.model {
+            Some(ref model) => write!(f, "{}:{}", self.name(), model),
+            None => f.write_str(&self.name()),
+        }
+    }
+}
+
+impl std::str::FromStr for BackendSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (tool, rest) = match s.split_once(':') {
+            Some((tool, rest)) => (tool.trim().parse()?, Some(rest)),
+            None => (s.trim().parse()?, None),
+        };
+        // 自定义命令的名称位于模型之前
+        let (command, model) = match (tool, rest) {
+            (CliTool::Custom, Some(rest)) => match rest.split_once(':') {
+                Some((command, model)) => (Some(command), Some(model)),
+                None => (Some(rest), None),
+            },
+            (CliTool::Custom, None) => (None, None),
+            (_, rest) => (None, rest),
+        };
+        let command = command.map(str::trim).filter(|c| !c.is_empty());
+        if tool == CliTool::Custom && command.is_none() {
+            return Err("custom 后端需要命令名称，格式为 custom:<名称>[:<模型>]".to_string());
+        }
+        Ok(Self {
+            tool,
+            model: model
+                .map(str::trim)
+                .filter(|m| !m.is_empty())
+                .map(str::to_string),
+            command: command.map(str::to_string),
+        })
+    }
+}
+
+impl TryFrom<String> for BackendSpec {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<BackendSpec> for String {
+    fn from(spec: BackendSpec) -> Self {
+        spec.to_string()
+    }
+}
+
 /// 沙箱策略
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "kebab-case")]
@@ -146,6 +301,11 @@ impl SandboxPolicy {
             SandboxPolicy::DangerFullAccess => "danger-full-access",
         }
     }
+
+    /// 是否允许写入工作区
+    pub fn allows_writes(&self) -> bool {
+        !matches!(self, SandboxPolicy::ReadOnly)
+    }
 }
 
 impl std::str::FromStr for SandboxPolicy {
@@ -161,6 +321,43 @@ impl std::str::FromStr for SandboxPolicy {
     }
 }
 
+/// 只读任务结束后对工作区的检查方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SandboxCheck {
+    /// 不检查
+    Off,
+    /// 发现改动时输出警告
+    Warn,
+    /// 发现改动时以 `SandboxViolation` 失败
+    #[default]
+    Fail,
+}
+
+/// 工作区隔离方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum IsolationMode {
+    /// 直接在工作目录中执行
+    #[default]
+    None,
+    /// 在临时 git worktree 中执行
+    Worktree,
+}
+
+/// 隔离工作树中改动的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum WorktreeAction {
+    /// 应用到主工作区
+    Apply,
+    /// 保存为新分支，主工作区保持不变
+    #[default]
+    Branch,
+    /// 丢弃（只返回 diff）
+    Discard,
+}
+
 /// Agent 运行配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentConfig {
@@ -189,10 +386,82 @@ pub struct AgentConfig {
     #[serde(default)]
     pub max_duration: Option<u64>,
 
+    /// 软截止时间（秒）：到达后提醒 Agent 收尾（仅支持 stdin 交互的底层 CLI）
+    #[serde(default)]
+    pub soft_deadline: Option<u64>,
+
     /// 最大重试次数
     #[serde(default)]
     pub max_retries: Option<u32>,
 
+    /// 重试策略（未设置时使用默认策略）
+    #[serde(default)]
+    pub retry_policy: Option<RetryPolicy>,
+
+    /// 主后端（未设置时使用 Agent 默认的底层 CLI）
+    #[serde(default)]
+    pub backend: Option<BackendSpec>,
+
+    /// 备用后端：主后端因 `fallback_on` 中的错误失败时依次尝试
+    #[serde(default)]
+    pub fallbacks: Vec<BackendSpec>,
+
+    /// 触发切换备用后端的错误类型（未设置时为命令未找到、上游错误、限流、熔断和限流等待超时）
+    #[serde(default)]
+    pub fallback_on: Option<HashSet<ErrorKind>>,
+
+    /// 各后端的熔断与限流设置（来自配置文件）
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub backend_settings: HashMap<CliTool, BackendSettings>,
+
+    /// 自定义命令后端的定义（来自配置文件）
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub commands: BTreeMap<String, CustomCommand>,
+
+    /// 工作区写锁被占用时的最长等待时间（秒），未设置时立即失败（仅对可写沙箱策略生效）
+    #[serde(default)]
+    pub lock_wait: Option<u64>,
+
+    /// 是否在每次尝试前创建工作区检查点，失败重试前恢复（仅对可写沙箱策略生效）
+    #[serde(default)]
+    pub checkpoint: bool,
+
+    /// 只读任务结束后对工作区的检查方式
+    #[serde(default)]
+    pub sandbox_check: SandboxCheck,
+
+    /// 是否在发送前对提示词脱敏
+    #[serde(default)]
+    pub redact_prompt: bool,
+
+    /// 路径策略（来自配置文件，仅对可写沙箱策略生效）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_policy: Option<PathPolicy>,
+
+    /// 改动报告中是否附带统一 diff（仅对可写沙箱策略生效）
+    #[serde(default)]
+    pub report_diff: bool,
+
+    /// 保存每次尝试的调用记录的目录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record: Option<PathBuf>,
+
+    /// 调用记录保留未脱敏的原始内容（默认脱敏）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub record_raw: bool,
+
+    /// 工作区隔离方式（仅对可写沙箱策略生效）
+    #[serde(default)]
+    pub isolate: IsolationMode,
+
+    /// 隔离工作树的基准引用（默认 HEAD）
+    #[serde(default)]
+    pub isolate_base: Option<String>,
+
+    /// 隔离工作树中改动的处理方式
+    #[serde(default)]
+    pub on_finish: WorktreeAction,
+
     /// 是否返回完整消息
     #[serde(default)]
     pub return_all_messages: bool,
@@ -245,7 +514,25 @@ impl AgentConfig {
             session_id: None,
             timeout: None,
             max_duration: None,
+            soft_deadline: None,
             max_retries: None,
+            retry_policy: None,
+            backend: None,
+            fallbacks: Vec::new(),
+            fallback_on: None,
+            backend_settings: HashMap::new(),
+            commands: BTreeMap::new(),
+            lock_wait: None,
+            checkpoint: false,
+            sandbox_check: SandboxCheck::default(),
+            redact_prompt: false,
+            record_raw: false,
+            path_policy: None,
+            report_diff: false,
+            record: None,
+            isolate: IsolationMode::None,
+            isolate_base: None,
+            on_finish: WorktreeAction::default(),
             return_all_messages: false,
             return_metrics: false,
             log_metrics: false,
@@ -259,6 +546,16 @@ impl AgentConfig {
         }
     }
 
+    /// 应用 Agent 固有的约束规则
+    ///
+    /// Reviewer 的超时时间固定为 300s（空闲）和 7200s（总时长），不允许外部修改
+    pub fn enforce_agent_rules(&mut self) {
+        if self.agent_type == AgentType::Reviewer {
+            self.timeout = None;
+            self.max_duration = None;
+        }
+    }
+
     /// 获取实际的超时时间
     pub fn get_timeout(&self) -> u64 {
         self.timeout.unwrap_or_else(|| self.agent_type.default_timeout())
@@ -275,4 +572,72 @@ impl AgentConfig {
         self.max_retries
             .unwrap_or_else(|| self.agent_type.default_max_retries())
     }
+
+    /// 获取实际的主后端（`model` 优先于后端规格中的模型）
+    pub fn primary_backend(&self) -> BackendSpec {
+        let mut backend = self
+            .backend
+            .clone()
+            .unwrap_or_else(|| BackendSpec::new(self.agent_type.cli_tool(), None));
+        if let Some(ref model) = self.model {
+            backend.model = Some(model.clone());
+        }
+        backend
+    }
+
+    /// 获取按顺序尝试的后端链：主后端 + 备用后端
+    pub fn backend_chain(&self) -> Vec<BackendSpec> {
+        let mut chain = vec![self.primary_backend()];
+        chain.extend(self.fallbacks.iter().cloned());
+        chain
+    }
+
+    /// 获取触发切换备用后端的错误类型
+    pub fn get_fallback_on(&self) -> HashSet<ErrorKind> {
+        self.fallback_on.clone().unwrap_or_else(|| {
+            [
+                ErrorKind::CommandNotFound,
+                ErrorKind::UpstreamError,
+                ErrorKind::RateLimited,
+                ErrorKind::CircuitOpen,
+                ErrorKind::Throttled,
+            ]
+            .into_iter()
+            .collect()
+        })
+    }
+
+    /// 是否需要创建工作区检查点
+    pub fn uses_checkpoint(&self) -> bool {
+        self.checkpoint && self.sandbox.allows_writes()
+    }
+
+    /// 获取生效的路径策略（只读或没有任何限制时为 `None`）
+    pub fn active_path_policy(&self) -> Option<&PathPolicy> {
+        self.path_policy
+            .as_ref()
+            .filter(|policy| !policy.is_empty() && self.sandbox.allows_writes())
+    }
+
+    /// 是否在隔离工作树中执行
+    pub fn uses_worktree(&self) -> bool {
+        self.isolate == IsolationMode::Worktree && self.sandbox.allows_writes()
+    }
+
+    /// 获取实际的重试策略
+    pub fn get_retry_policy(&self) -> RetryPolicy {
+        self.retry_policy.clone().unwrap_or_default()
+    }
+
+    /// 获取填入全部默认值后的配置（用于 `--dry-run`）
+    pub fn resolved(&self) -> Self {
+        let mut config = self.clone();
+        config.timeout = Some(self.get_timeout());
+        config.max_duration = Some(self.get_max_duration());
+        config.max_retries = Some(self.get_max_retries());
+        config.retry_policy = Some(self.get_retry_policy());
+        config.backend = Some(self.primary_backend());
+        config.fallback_on = Some(self.get_fallback_on());
+        config
+    }
 }
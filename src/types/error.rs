@@ -4,6 +4,8 @@
 
 use thiserror::Error;
 
+use super::policy::PathViolation;
+
 /// OMCC CLI 错误类型
 #[derive(Error, Debug)]
 pub enum OmccError {
@@ -57,10 +59,46 @@ pub enum OmccError {
     /// 未预期的异常
     #[error("未预期的异常：{0}")]
     UnexpectedException(String),
+
+    /// 执行被取消（收到信号或取消令牌被触发）
+    #[error("执行已取消")]
+    Cancelled,
+
+    /// 上游限流（请求过多或额度用尽）
+    #[error("上游限流：{0}")]
+    RateLimited(String),
+
+    /// 后端熔断中（连续失败过多）
+    #[error("后端熔断中：{0}")]
+    CircuitOpen(String),
+
+    /// 达到后端限流上限且等待超时
+    #[error("后端限流：{0}")]
+    Throttled(String),
+
+    /// 工作区正被其它可写任务占用
+    #[error("工作区被占用：{0}")]
+    WorkspaceLocked(String),
+
+    /// 只读任务修改了工作区
+    #[error("沙箱违规：{0}")]
+    SandboxViolation(String),
+
+    /// 可写任务改动了路径策略不允许的文件
+    #[error("违反路径策略：{message}")]
+    PolicyViolation {
+        message: String,
+        violations: Vec<PathViolation>,
+        rolled_back: bool,
+    },
+
+    /// Git 操作失败（检查点等工作区操作）
+    #[error("Git 操作失败：{0}")]
+    GitError(String),
 }
 
 /// 错误类型枚举（用于 JSON 输出）
-#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ErrorKind {
     /// 空闲超时
@@ -87,6 +125,22 @@ pub enum ErrorKind {
     IoError,
     /// 未预期的异常
     UnexpectedException,
+    /// 执行已取消
+    Cancelled,
+    /// 上游限流
+    RateLimited,
+    /// 后端熔断中
+    CircuitOpen,
+    /// 后端限流
+    Throttled,
+    /// 工作区被占用
+    WorkspaceLocked,
+    /// 沙箱违规
+    SandboxViolation,
+    /// 违反路径策略
+    PolicyViolation,
+    /// Git 操作失败
+    GitError,
 }
 
 impl From<&OmccError> for ErrorKind {
@@ -104,6 +158,23 @@ impl From<&OmccError> for ErrorKind {
             OmccError::FileNotFound(_) => ErrorKind::FileNotFound,
             OmccError::IoError(_) => ErrorKind::IoError,
             OmccError::UnexpectedException(_) => ErrorKind::UnexpectedException,
+            OmccError::Cancelled => ErrorKind::Cancelled,
+            OmccError::RateLimited(_) => ErrorKind::RateLimited,
+            OmccError::CircuitOpen(_) => ErrorKind::CircuitOpen,
+            OmccError::Throttled(_) => ErrorKind::Throttled,
+            OmccError::WorkspaceLocked(_) => ErrorKind::WorkspaceLocked,
+            OmccError::SandboxViolation(_) => ErrorKind::SandboxViolation,
+            OmccError::PolicyViolation { .. } => ErrorKind::PolicyViolation,
+            OmccError::GitError(_) => ErrorKind::GitError,
         }
     }
 }
+
+impl std::str::FromStr for ErrorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(s.replace('-', "_")))
+            .map_err(|_| format!("未知的错误类型：{}", s))
+    }
+}
@@ -0,0 +1,70 @@
+//! 执行事件定义
+//!
+//! Agent 执行过程中产生的事件，用于 SSE 等流式输出场景
+
+use serde::{Deserialize, Serialize};
+
+use super::output::AgentResult;
+
+/// Agent 执行事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AgentEvent {
+    /// 开始一次执行尝试
+    AttemptStarted {
+        /// 尝试序号（从 0 开始）
+        attempt: u32,
+        /// 底层 CLI 命令
+        cli: String,
+        /// 模型（使用 CLI 默认模型时为空）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
+    },
+    /// 获取到会话 ID
+    Session {
+        /// 会话 ID
+        session_id: String,
+    },
+    /// 上游输出的一行内容
+    Output {
+        /// 原始输出行
+        line: String,
+    },
+    /// 到达软截止时间
+    SoftDeadline {
+        /// 已执行时长（秒）
+        elapsed_s: u64,
+        /// 收尾消息是否已送达底层 CLI
+        delivered: bool,
+    },
+    /// 本次尝试失败，即将重试
+    Retrying {
+        /// 下一次尝试序号
+        attempt: u32,
+        /// 退避等待时间（毫秒）
+        delay_ms: u64,
+        /// 失败原因
+        error: String,
+    },
+    /// 当前后端失败，切换到备用后端
+    Fallback {
+        /// 失败的后端
+        from: String,
+        /// 接下来尝试的后端
+        to: String,
+        /// 失败原因
+        error: String,
+    },
+    /// 重试前已将工作区恢复到检查点
+    CheckpointRestored {
+        /// 下一次尝试序号
+        attempt: u32,
+        /// 检查点提交
+        commit: String,
+    },
+    /// 执行结束
+    Finished {
+        /// 最终结果
+        result: AgentResult,
+    },
+}
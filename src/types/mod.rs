@@ -2,10 +2,20 @@
 //!
 //! 导出所有核心类型定义
 
+pub mod backend;
+pub mod capability;
 pub mod config;
 pub mod error;
+pub mod event;
 pub mod output;
+pub mod policy;
+pub mod retry;
 
+pub use backend::*;
+pub use capability::*;
 pub use config::*;
 pub use error::*;
+pub use event::*;
 pub use output::*;
+pub use policy::*;
+pub use retry::*;
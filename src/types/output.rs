@@ -3,10 +3,14 @@
 //! 定义 Agent 执行结果的结构化输出
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::time::Duration;
 
-use super::config::AgentType;
+use super::capability::Capabilities;
+use super::config::{AgentConfig, AgentType, BackendSpec, WorktreeAction};
 use super::error::ErrorKind;
+use super::policy::PathViolation;
 
 /// Agent 执行结果
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,10 +35,49 @@ impl AgentResult {
             session_id,
             result,
             duration: format_duration(duration),
+            backend: None,
+            model: None,
+            isolation: None,
+            changes: None,
             metrics: None,
         })
     }
 
+    /// 记录实际给出结果的后端和模型（仅对成功结果生效）
+    pub fn with_backend(mut self, backend: &BackendSpec) -> Self {
+        if let AgentResult::Success(ref mut success) = self {
+            success.backend = Some(backend.name());
+            success.model = backend.model.clone();
+        }
+        self
+    }
+
+    /// 附加执行指标（仅对成功结果生效）
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        if let AgentResult::Success(ref mut success) = self {
+            success.metrics = Some(metrics);
+        }
+        self
+    }
+
+    /// 附加隔离工作树的执行报告（仅对成功结果生效）
+    pub fn with_isolation(mut self, report: IsolationReport) -> Self {
+        if let AgentResult::Success(ref mut success) = self {
+            success.isolation = Some(Box::new(report));
+        }
+        self
+    }
+
+    /// 附加工作区改动报告
+    pub fn with_changes(mut self, report: ChangeReport) -> Self {
+        let changes = match self {
+            AgentResult::Success(ref mut success) => &mut success.changes,
+            AgentResult::Failure(ref mut failure) => &mut failure.changes,
+        };
+        *changes = Some(Box::new(report));
+        self
+    }
+
     /// 创建失败结果
     pub fn failure(
         agent: AgentType,
@@ -47,6 +90,7 @@ impl AgentResult {
             error,
             error_kind,
             error_detail,
+            changes: None,
         })
     }
 
@@ -80,11 +124,87 @@ pub struct SuccessResult {
     /// 执行时长
     pub duration: String,
 
+    /// 实际给出结果的后端
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+
+    /// 实际使用的模型（使用 CLI 默认模型时为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// 隔离工作树的执行报告
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isolation: Option<Box<IsolationReport>>,
+
+    /// 工作区改动报告（仅可写 Agent）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changes: Option<Box<ChangeReport>>,
+
     /// 指标数据（可选）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metrics: Option<Metrics>,
 }
 
+/// 隔离工作树的执行报告
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IsolationReport {
+    /// 工作树基于的提交
+    pub base: String,
+
+    /// 实际执行的操作
+    pub action: WorktreeAction,
+
+    /// 保存改动的分支
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+
+    /// 应用到主工作区失败的原因（此时改动保存为分支）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apply_error: Option<String>,
+
+    /// 相对基准提交的 diff（无改动时为空）
+    pub diff: String,
+}
+
+/// 工作区改动报告：执行前后工作区快照的差异
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChangeReport {
+    /// 新增的文件
+    pub added: Vec<FileChange>,
+
+    /// 修改的文件
+    pub modified: Vec<FileChange>,
+
+    /// 删除的文件
+    pub deleted: Vec<FileChange>,
+
+    /// 统一 diff（仅在请求时提供）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+impl ChangeReport {
+    /// 是否没有任何改动
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.deleted.is_empty()
+    }
+}
+
+/// 单个文件的改动
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChange {
+    /// 相对仓库根目录的路径
+    pub path: String,
+
+    /// 新增行数（二进制文件为空）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub additions: Option<u64>,
+
+    /// 删除行数（二进制文件为空）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deletions: Option<u64>,
+}
+
 /// 失败结果
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FailureResult {
@@ -100,6 +220,10 @@ pub struct FailureResult {
     /// 错误详情
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error_detail: Option<ErrorDetail>,
+
+    /// 工作区改动报告（仅可写 Agent）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changes: Option<Box<ChangeReport>>,
 }
 
 /// 错误详情
@@ -113,7 +237,7 @@ pub struct ErrorDetail {
     pub exit_code: Option<i32>,
 
     /// 最后几行输出
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub last_lines: Vec<String>,
 
     /// JSON 解析错误次数
@@ -128,9 +252,40 @@ pub struct ErrorDetail {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_duration_s: Option<u64>,
 
-    /// 重试次数
+    /// 实际执行的重试次数（所有后端上的尝试总数减一，切换备用后端也计入）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub retries: Option<u32>,
+
+    /// 每次尝试的错误记录
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<AttemptError>,
+
+    /// 违反路径策略的文件
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub violations: Vec<PathViolation>,
+
+    /// 是否已将工作区回滚到执行前的状态
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rolled_back: bool,
+}
+
+/// 单次尝试的错误记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttemptError {
+    /// 尝试序号（从 0 开始）
+    pub attempt: u32,
+
+    /// 本次尝试使用的后端
+    pub backend: String,
+
+    /// 错误类型
+    pub error_kind: ErrorKind,
+
+    /// 错误信息
+    pub error: String,
+
+    /// 本次尝试耗时（毫秒）
+    pub duration_ms: u64,
 }
 
 /// 执行指标
@@ -147,10 +302,65 @@ pub struct Metrics {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub output_tokens: Option<u64>,
 
-    /// 重试次数
+    /// 实际执行的重试次数（所有后端上的尝试总数减一，切换备用后端也计入）
     pub retries: u32,
 }
 
+/// 上游报告的 token 用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    /// 输入 token 数
+    pub input_tokens: u64,
+
+    /// 输出 token 数
+    pub output_tokens: u64,
+}
+
+/// 底层 CLI 命令预览（用于 --print-command）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandPreview {
+    /// 后端
+    pub backend: String,
+
+    /// 可执行文件
+    pub program: String,
+
+    /// 命令行参数（作为参数传递的提示词以占位符表示）
+    pub args: Vec<String>,
+
+    /// 工作目录
+    pub cwd: PathBuf,
+
+    /// 是否清空继承的环境变量
+    pub env_clear: bool,
+
+    /// 显式设置的环境变量（已脱敏）
+    pub env: BTreeMap<String, String>,
+
+    /// 提示词是否通过 stdin 传递
+    pub prompt_via_stdin: bool,
+}
+
+/// 试运行报告（用于 --dry-run）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryRunReport {
+    /// 填入全部默认值后的配置
+    pub config: AgentConfig,
+
+    /// 主后端将要执行的命令
+    pub command: CommandPreview,
+
+    /// 发送给底层 CLI 的完整提示词（含引导提示词）
+    pub prompt: String,
+
+    /// 主后端的能力
+    pub capabilities: Capabilities,
+
+    /// 主后端不支持、执行时不会生效的选项
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
 /// 格式化时长
 fn format_duration(duration: Duration) -> String {
     let secs = duration.as_secs();
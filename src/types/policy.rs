@@ -0,0 +1,108 @@
+//! 路径策略定义
+//!
+//! 限制可写 Agent 能改动哪些文件，由配置文件中 Agent 的 `paths` 段提供，执行后根据改动报告检查
+
+use globset::{Glob, GlobBuilder, GlobMatcher};
+use serde::{Deserialize, Serialize};
+
+use super::error::OmccError;
+
+/// 可写 Agent 的路径策略
+///
+/// 模式为相对仓库根目录的 glob；不含 `/` 的模式（如 `.env*`）同时匹配任意目录下的文件名
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathPolicy {
+    /// 允许改动的路径（为空时不限制）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+
+    /// 禁止改动的路径（优先于 `allow`）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+
+    /// 违反策略时是否将工作区回滚到执行前的状态
+    pub rollback: bool,
+}
+
+/// 违反路径策略的文件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathViolation {
+    /// 相对仓库根目录的路径
+    pub path: String,
+
+    /// 匹配到的禁止模式（为空表示不在允许列表中）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+}
+
+impl PathPolicy {
+    /// 是否没有任何限制
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// 检查改动的文件，返回违反策略的文件
+    pub fn check<'a>(
+        &self,
+        paths: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<PathViolation>, OmccError> {
+        let allow = compile(&self.allow)?;
+        let deny = compile(&self.deny)?;
+
+        let mut violations = Vec::new();
+        for path in paths {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            if let Some(index) = first_match(&deny, path, name) {
+                violations.push(PathViolation {
+                    path: path.to_string(),
+                    pattern: Some(self.deny[index].clone()),
+                });
+            } else if !self.allow.is_empty() && first_match(&allow, path, name).is_none() {
+                violations.push(PathViolation {
+                    path: path.to_string(),
+                    pattern: None,
+                });
+            }
+        }
+        Ok(violations)
+    }
+}
+
+/// 编译后的路径模式
+struct Pattern {
+    /// 匹配完整路径
+    path: GlobMatcher,
+    /// 匹配文件名（仅不含 `/` 的模式）
+    name: Option<GlobMatcher>,
+}
+
+/// 编译模式列表
+fn compile(patterns: &[String]) -> Result<Vec<Pattern>, OmccError> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            let invalid = |e: globset::Error| {
+                OmccError::ConfigError(format!("无效的路径模式 {}：{}", pattern, e))
+            };
+            let path = GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(invalid)?
+                .compile_matcher();
+            let name = if pattern.contains('/') {
+                None
+            } else {
+                Some(Glob::new(pattern).map_err(invalid)?.compile_matcher())
+            };
+            Ok(Pattern { path, name })
+        })
+        .collect()
+}
+
+/// 返回第一个匹配的模式序号
+fn first_match(patterns: &[Pattern], path: &str, name: &str) -> Option<usize> {
+    patterns.iter().position(|pattern| {
+        pattern.path.is_match(path) || pattern.name.as_ref().is_some_and(|n| n.is_match(name))
+    })
+}
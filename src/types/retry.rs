@@ -0,0 +1,101 @@
+//! 重试策略定义
+//!
+//! 控制失败后的退避时间、抖动、可重试的错误类型以及总重试时间预算
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::error::ErrorKind;
+
+/// 重试策略
+///
+/// 第 n 次重试前等待 `initial_delay_ms * multiplier^(n-1)`（不超过 `max_delay_ms`），
+/// 再按 `jitter` 比例随机缩短，避免多个调用方同时重试
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// 首次重试前的等待时间（毫秒）
+    pub initial_delay_ms: u64,
+
+    /// 退避倍数
+    pub multiplier: f64,
+
+    /// 单次等待时间上限（毫秒）
+    pub max_delay_ms: u64,
+
+    /// 抖动比例（0.0 ~ 1.0），实际等待时间在 `[delay * (1 - jitter), delay]` 之间
+    pub jitter: f64,
+
+    /// 可重试的错误类型（`cancelled` 永远不会重试）
+    pub retry_on: HashSet<ErrorKind>,
+
+    /// 总重试时间预算（秒）：从首次尝试开始计算，超出后不再重试
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_s: Option<u64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay_ms: 500,
+            multiplier: 2.0,
+            max_delay_ms: 30_000,
+            jitter: 0.2,
+            retry_on: Self::default_retry_on(),
+            budget_s: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 默认可重试的错误类型：超时、IO 错误和上游错误
+    pub fn default_retry_on() -> HashSet<ErrorKind> {
+        [
+            ErrorKind::IdleTimeout,
+            ErrorKind::Timeout,
+            ErrorKind::IoError,
+            ErrorKind::UpstreamError,
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    /// 该错误类型是否可以重试
+    pub fn should_retry(&self, kind: ErrorKind) -> bool {
+        kind != ErrorKind::Cancelled && self.retry_on.contains(&kind)
+    }
+
+    /// 第 `retry` 次重试（从 1 开始）前的基础等待时间（不含抖动）
+    pub fn base_delay(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.initial_delay_ms as f64 * self.multiplier.max(0.0).powi(exponent);
+        Duration::from_millis(delay.min(self.max_delay_ms as f64) as u64)
+    }
+
+    /// 第 `retry` 次重试（从 1 开始）前的实际等待时间（含抖动）
+    pub fn delay(&self, retry: u32) -> Duration {
+        let base = self.base_delay(retry);
+        // NaN 视为不抖动（`clamp` 会原样保留 NaN，`mul_f64` 遇到 NaN 会 panic）
+        let jitter = if self.jitter.is_nan() {
+            0.0
+        } else {
+            self.jitter.clamp(0.0, 1.0)
+        };
+        if jitter == 0.0 {
+            return base;
+        }
+        base.mul_f64(1.0 - jitter * random_fraction())
+    }
+
+    /// 总重试时间预算
+    pub fn budget(&self) -> Option<Duration> {
+        self.budget_s.map(Duration::from_secs)
+    }
+}
+
+/// `[0, 1)` 区间的随机数（取自 UUID v4 高 48 位，均为随机位）
+fn random_fraction() -> f64 {
+    (uuid::Uuid::new_v4().as_u128() >> 80) as f64 / (1u64 << 48) as f64
+}
@@ -0,0 +1,249 @@
+//! 工作区改动报告
+//!
+//! 执行前后分别用临时索引将工作区（含未跟踪、未被忽略的文件）写成树对象，
+//! 比较两棵树得到新增、修改和删除的文件及行数，不影响当前分支和暂存区。
+//! 只读校验只需要知道哪些文件被改动，使用 `TrackedSnapshot` 对工作目录下被跟踪的文件计算内容哈希
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use super::{git, snapshot_tree};
+use crate::types::{ChangeReport, FileChange, OmccError};
+
+/// 单次 `git hash-object` 调用最多传入的路径数（避免超出命令行长度限制）
+const HASH_BATCH: usize = 512;
+
+/// gitlink（子模块）的文件模式
+const GITLINK_MODE: &str = "160000";
+
+/// 工作区快照
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// 快照 ID（用于临时索引文件名）
+    id: String,
+
+    /// 仓库根目录
+    root: PathBuf,
+
+    /// `.git` 目录
+    git_dir: PathBuf,
+
+    /// 快照树对象
+    tree: String,
+}
+
+impl Snapshot {
+    /// 为 `dir` 所在的 Git 仓库创建快照
+    pub async fn take(dir: &Path) -> Result<Self, OmccError> {
+        let root = PathBuf::from(
+            git(dir, &["rev-parse", "--show-toplevel"], &[])
+                .await
+                .map_err(|_| {
+                    OmccError::GitError(format!(
+                        "{} 不在 Git 仓库中，无法生成改动报告",
+                        dir.display()
+                    ))
+                })?
+                .trim(),
+        );
+        let git_dir = PathBuf::from(
+            git(&root, &["rev-parse", "--absolute-git-dir"], &[])
+                .await?
+                .trim(),
+        );
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let tree = snapshot_tree(&root, &git_dir, &id).await?;
+        Ok(Self {
+            id,
+            root,
+            git_dir,
+            tree,
+        })
+    }
+
+    /// 与当前工作区比较，生成改动报告（`with_diff` 为真时附带统一 diff）
+    pub async fn changes(&self, with_diff: bool) -> Result<ChangeReport, OmccError> {
+        let current = snapshot_tree(&self.root, &self.git_dir, &self.id).await?;
+        let mut report = ChangeReport::default();
+        if current == self.tree {
+            return Ok(report);
+        }
+
+        // numstat：`<新增>\t<删除>\t<路径>\0`，二进制文件的行数为 `-`
+        let mut counts: HashMap<String, (Option<u64>, Option<u64>)> = HashMap::new();
+        for entry in self.diff_tree(&current, "--numstat").await?.split('\0') {
+            let mut fields = entry.splitn(3, '\t');
+            if let (Some(additions), Some(deletions), Some(path)) =
+                (fields.next(), fields.next(), fields.next())
+            {
+                counts.insert(
+                    path.to_string(),
+                    (additions.parse().ok(), deletions.parse().ok()),
+                );
+            }
+        }
+
+        // name-status：`<状态>\0<路径>\0`
+        let name_status = self.diff_tree(&current, "--name-status").await?;
+        let mut fields = name_status.split('\0').filter(|f| !f.is_empty());
+        while let (Some(status), Some(path)) = (fields.next(), fields.next()) {
+            let (additions, deletions) = counts.get(path).copied().unwrap_or_default();
+            let change = FileChange {
+                path: path.to_string(),
+                additions,
+                deletions,
+            };
+            match status {
+                "A" => report.added.push(change),
+                "D" => report.deleted.push(change),
+                _ => report.modified.push(change),
+            }
+        }
+
+        if with_diff {
+            report.diff = Some(
+                git(
+                    &self.root,
+                    &[
+                        "diff-tree",
+                        "-r",
+                        "-p",
+                        "--no-renames",
+                        &self.tree,
+                        &current,
+                    ],
+                    &[],
+                )
+                .await?,
+            );
+        }
+        Ok(report)
+    }
+
+    /// 以指定格式比较快照树与 `current`
+    async fn diff_tree(&self, current: &str, format: &str) -> Result<String, OmccError> {
+        git(
+            &self.root,
+            &[
+                "diff-tree",
+                "-r",
+                "-z",
+                "--no-renames",
+                format,
+                &self.tree,
+                current,
+            ],
+            &[],
+        )
+        .await
+    }
+}
+
+/// 工作目录下被跟踪文件的内容快照
+///
+/// 用 `git ls-files` 列出工作目录下被跟踪的文件，再用 `git hash-object --no-filters` 计算内容哈希，
+/// 不写入对象库、不读写暂存区，也不扫描工作目录之外的文件
+#[derive(Debug, Clone)]
+pub struct TrackedSnapshot {
+    /// 工作目录
+    dir: PathBuf,
+
+    /// 仓库根目录
+    root: PathBuf,
+
+    /// 相对仓库根目录的路径到内容哈希的映射（文件不存在时为 `None`）
+    hashes: BTreeMap<String, Option<String>>,
+}
+
+impl TrackedSnapshot {
+    /// 为 `dir` 下被跟踪的文件创建快照
+    pub async fn take(dir: &Path) -> Result<Self, OmccError> {
+        let root = PathBuf::from(
+            git(dir, &["rev-parse", "--show-toplevel"], &[])
+                .await
+                .map_err(|_| {
+                    OmccError::GitError(format!(
+                        "{} 不在 Git 仓库中，无法校验只读任务",
+                        dir.display()
+                    ))
+                })?
+                .trim(),
+        );
+        let hashes = tracked_hashes(&root, dir).await?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            root,
+            hashes,
+        })
+    }
+
+    /// 与当前内容比较，列出新增、修改和删除的文件（不统计行数）
+    ///
+    /// 新增文件指执行期间开始被跟踪的文件
+    pub async fn changes(&self) -> Result<ChangeReport, OmccError> {
+        let current = tracked_hashes(&self.root, &self.dir).await?;
+        let change = |path: &str| FileChange {
+            path: path.to_string(),
+            additions: None,
+            deletions: None,
+        };
+        let mut report = ChangeReport::default();
+        for (path, before) in &self.hashes {
+            let after = current.get(path).cloned().flatten();
+            match (before, after) {
+                (Some(before), Some(after)) if *before != after => {
+                    report.modified.push(change(path))
+                }
+                (Some(_), None) => report.deleted.push(change(path)),
+                (None, Some(_)) => report.added.push(change(path)),
+                _ => {}
+            }
+        }
+        for (path, hash) in &current {
+            if hash.is_some() && !self.hashes.contains_key(path) {
+                report.added.push(change(path));
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// 计算 `dir` 下被跟踪文件的内容哈希（跳过子模块；符号链接记录其目标）
+async fn tracked_hashes(
+    root: &Path,
+    dir: &Path,
+) -> Result<BTreeMap<String, Option<String>>, OmccError> {
+    // `<模式> <对象> <阶段>\t<路径>\0`，有冲突的文件会出现多次
+    let listing = git(dir, &["ls-files", "-s", "-z", "--full-name"], &[]).await?;
+    let mut hashes = BTreeMap::new();
+    let mut files = Vec::new();
+    for entry in listing.split('\0').filter(|e| !e.is_empty()) {
+        let Some((meta, path)) = entry.split_once('\t') else {
+            continue;
+        };
+        if meta.starts_with(GITLINK_MODE) || hashes.contains_key(path) {
+            continue;
+        }
+        let hash = match std::fs::symlink_metadata(root.join(path)) {
+            Ok(meta) if meta.file_type().is_symlink() => std::fs::read_link(root.join(path))
+                .ok()
+                .map(|target| format!("symlink:{}", target.display())),
+            Ok(meta) if meta.is_file() => {
+                files.push(path.to_string());
+                None
+            }
+            _ => None,
+        };
+        hashes.insert(path.to_string(), hash);
+    }
+
+    for batch in files.chunks(HASH_BATCH) {
+        let mut args = vec!["hash-object", "--no-filters", "--"];
+        args.extend(batch.iter().map(String::as_str));
+        let output = git(root, &args, &[]).await?;
+        for (path, hash) in batch.iter().zip(output.lines()) {
+            hashes.insert(path.clone(), Some(hash.to_string()));
+        }
+    }
+    Ok(hashes)
+}
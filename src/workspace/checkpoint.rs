@@ -0,0 +1,192 @@
+//! 工作区检查点
+//!
+//! 使用临时索引将工作区（含未跟踪、未被忽略的文件）写成隐藏提交 `refs/omcc/checkpoints/<id>`，
+//! 不影响当前分支、暂存区和 stash。恢复时先还原修改和删除的文件（含 `.gitignore`）、HEAD 与暂存区，
+//! 再按检查点时的忽略规则删除新增文件
+
+use std::path::{Path, PathBuf};
+
+use super::{git, identity_env, index_path, snapshot_tree};
+use crate::types::OmccError;
+
+/// 检查点引用前缀
+pub const CHECKPOINT_REF_PREFIX: &str = "refs/omcc/checkpoints/";
+
+/// 工作区检查点
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    /// 检查点 ID
+    pub id: String,
+
+    /// 检查点提交
+    pub commit: String,
+
+    /// 仓库根目录
+    root: PathBuf,
+
+    /// `.git` 目录
+    git_dir: PathBuf,
+
+    /// 创建时的 HEAD（空仓库为 `None`）
+    head: Option<String>,
+
+    /// 创建时 HEAD 指向的分支（分离 HEAD 时为 `None`）
+    head_ref: Option<String>,
+
+    /// 创建时暂存区的副本（仓库尚无暂存区时为 `None`）
+    index_backup: Option<PathBuf>,
+}
+
+impl Checkpoint {
+    /// 为 `dir` 所在的 Git 仓库创建检查点
+    pub async fn create(dir: &Path) -> Result<Self, OmccError> {
+        let root = PathBuf::from(
+            git(dir, &["rev-parse", "--show-toplevel"], &[])
+                .await
+                .map_err(|_| {
+                    OmccError::GitError(format!(
+                        "{} 不在 Git 仓库中，无法创建检查点",
+                        dir.display()
+                    ))
+                })?
+                .trim(),
+        );
+        let git_dir = PathBuf::from(
+            git(&root, &["rev-parse", "--absolute-git-dir"], &[])
+                .await?
+                .trim(),
+        );
+        let head = git(&root, &["rev-parse", "--verify", "-q", "HEAD"], &[])
+            .await
+            .ok()
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty());
+        let head_ref = git(&root, &["symbolic-ref", "-q", "HEAD"], &[])
+            .await
+            .ok()
+            .map(|r| r.trim().to_string())
+            .filter(|r| !r.is_empty());
+        let id = uuid::Uuid::new_v4().simple().to_string();
+
+        let tree = snapshot_tree(&root, &git_dir, &id).await?;
+        let message = format!("omcc checkpoint {}", id);
+        let mut args = vec!["commit-tree", tree.as_str(), "-m", message.as_str()];
+        if let Some(ref head) = head {
+            args.extend(["-p", head.as_str()]);
+        }
+        let commit = git(&root, &args, &identity_env()).await?.trim().to_string();
+
+        let reference = format!("{}{}", CHECKPOINT_REF_PREFIX, id);
+        git(&root, &["update-ref", &reference, &commit], &[]).await?;
+
+        let index = index_path(&root).await?;
+        let index_backup = if index.exists() {
+            let backup = git_dir.join(format!("omcc-checkpoint-{}.index", id));
+            std::fs::copy(&index, &backup)?;
+            Some(backup)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            id,
+            commit,
+            root,
+            git_dir,
+            head,
+            head_ref,
+            index_backup,
+        })
+    }
+
+    /// 检查点引用名称
+    pub fn reference(&self) -> String {
+        format!("{}{}", CHECKPOINT_REF_PREFIX, self.id)
+    }
+
+    /// 将工作区恢复到检查点状态
+    ///
+    /// 被 `.gitignore` 忽略的文件不在检查点范围内，不会被删除或还原。
+    /// 新增文件在 `.gitignore` 和暂存区还原之后计算，Agent 修改忽略规则或暂存文件不会导致误删
+    pub async fn restore(&self) -> Result<(), OmccError> {
+        // 还原被修改和删除的文件
+        let temp_index = self.git_dir.join(format!("omcc-restore-{}.index", self.id));
+        let env = [("GIT_INDEX_FILE", temp_index.as_os_str())];
+        let restored = async {
+            git(&self.root, &["read-tree", &self.commit], &env).await?;
+            git(&self.root, &["checkout-index", "-a", "-f"], &env).await
+        }
+        .await;
+        let _ = std::fs::remove_file(&temp_index);
+        restored?;
+
+        // 还原 HEAD 和暂存区：先切回原分支（或原提交），再将该分支移回检查点时的提交，
+        // 不移动 Agent 切换到的其它分支
+        match self.head_ref {
+            Some(ref head_ref) => {
+                git(&self.root, &["symbolic-ref", "HEAD", head_ref], &[]).await?;
+                if let Some(ref head) = self.head {
+                    git(&self.root, &["update-ref", head_ref, head], &[]).await?;
+                }
+            }
+            None => {
+                if let Some(ref head) = self.head {
+                    git(&self.root, &["update-ref", "--no-deref", "HEAD", head], &[]).await?;
+                }
+            }
+        }
+        let index = index_path(&self.root).await?;
+        match self.index_backup {
+            Some(ref backup) => {
+                std::fs::copy(backup, &index)?;
+            }
+            None => {
+                let _ = std::fs::remove_file(&index);
+            }
+        }
+
+        // 删除检查点之后新增的文件
+        let current = snapshot_tree(&self.root, &self.git_dir, &self.id).await?;
+        let added = git(
+            &self.root,
+            &[
+                "diff-tree",
+                "-r",
+                "-z",
+                "--name-only",
+                "--no-renames",
+                "--diff-filter=A",
+                &self.commit,
+                &current,
+            ],
+            &[],
+        )
+        .await?;
+        for path in added.split('\0').filter(|p| !p.is_empty()) {
+            let path = self.root.join(path);
+            let _ = std::fs::remove_file(&path);
+            remove_empty_parents(&path, &self.root);
+        }
+        Ok(())
+    }
+
+    /// 删除检查点引用和暂存区副本
+    pub async fn discard(self) -> Result<(), OmccError> {
+        if let Some(ref backup) = self.index_backup {
+            let _ = std::fs::remove_file(backup);
+        }
+        git(&self.root, &["update-ref", "-d", &self.reference()], &[]).await?;
+        Ok(())
+    }
+}
+
+/// 删除文件后逐级清理空目录（不超出仓库根目录）
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current == root || !current.starts_with(root) || std::fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
@@ -0,0 +1,177 @@
+//! 工作区写锁
+//!
+//! 可写 Agent 运行期间在状态目录 `locks/` 下持有锁文件，防止两个写入任务同时修改同一工作区。
+//! 工作目录在 Git 仓库中时以仓库根目录为键（检查点回滚和改动报告覆盖整个仓库），否则以规范化的工作目录为键。
+//! 锁文件记录持有者 PID 及其启动时间，持有者进程已退出（或 PID 已被复用）的锁视为过期并被接管
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use super::git;
+use crate::process;
+use crate::state::{state_subdir, FileLock};
+use crate::types::{AgentType, OmccError};
+
+/// 等待锁时的轮询间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 锁文件内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockOwner {
+    /// 锁 ID
+    pub id: String,
+
+    /// 持有者进程 PID
+    pub pid: u32,
+
+    /// 持有者进程启动时间（用于识别 PID 复用）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid_start: Option<u64>,
+
+    /// 持有锁的 Agent
+    pub agent: String,
+
+    /// 被锁定的目录（Git 仓库根目录或规范化后的工作目录）
+    pub working_dir: PathBuf,
+
+    /// 获取时间（Unix 秒）
+    pub acquired_at: u64,
+}
+
+/// 工作区写锁，离开作用域时释放
+#[derive(Debug)]
+pub struct WorkspaceLock {
+    path: PathBuf,
+    guard: PathBuf,
+    id: String,
+}
+
+impl WorkspaceLock {
+    /// 获取工作区写锁
+    ///
+    /// `wait` 为 `None` 时锁被占用立即返回 `WorkspaceLocked`，否则最多等待指定时间
+    pub async fn acquire(
+        working_dir: &Path,
+        agent: AgentType,
+        wait: Option<Duration>,
+        cancel: &CancellationToken,
+    ) -> Result<Self, OmccError> {
+        let working_dir = lock_root(working_dir).await;
+        let dir = state_subdir("locks")?;
+        let key = format!("{:016x}", fnv1a(working_dir.as_os_str().as_encoded_bytes()));
+        let path = dir.join(format!("{}.lock", key));
+        let guard = dir.join(format!("{}.guard", key));
+        let owner = LockOwner {
+            id: uuid::Uuid::new_v4().to_string(),
+            pid: std::process::id(),
+            pid_start: process::start_time(std::process::id()),
+            agent: agent.name().to_string(),
+            working_dir: working_dir.clone(),
+            acquired_at: unix_now(),
+        };
+
+        let start = Instant::now();
+        loop {
+            let holder = try_acquire(&path, &guard, &owner)?;
+            let Some(holder) = holder else {
+                return Ok(Self {
+                    path,
+                    guard,
+                    id: owner.id,
+                });
+            };
+
+            let waited = start.elapsed();
+            if wait.is_none_or(|w| waited >= w) {
+                return Err(OmccError::WorkspaceLocked(format!(
+                    "{} 正被 {}（PID {}）写入",
+                    working_dir.display(),
+                    holder.agent,
+                    holder.pid
+                )));
+            }
+            tokio::select! {
+                _ = cancel.cancelled() => return Err(OmccError::Cancelled),
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            }
+        }
+    }
+}
+
+impl Drop for WorkspaceLock {
+    fn drop(&mut self) {
+        let Ok(_guard) = FileLock::acquire(&self.guard) else {
+            return;
+        };
+        // 只删除自己持有的锁
+        if read_owner(&self.path).is_some_and(|owner| owner.id == self.id) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// 锁的键对应的目录：Git 仓库根目录，不在仓库中时为工作目录（均已规范化）
+async fn lock_root(working_dir: &Path) -> PathBuf {
+    let root = git(working_dir, &["rev-parse", "--show-toplevel"], &[])
+        .await
+        .map(|root| PathBuf::from(root.trim()))
+        .unwrap_or_else(|_| working_dir.to_path_buf());
+    std::fs::canonicalize(&root).unwrap_or(root)
+}
+
+/// 尝试获取锁，成功返回 `None`，被占用时返回当前持有者
+fn try_acquire(
+    path: &Path,
+    guard: &Path,
+    owner: &LockOwner,
+) -> Result<Option<LockOwner>, OmccError> {
+    let _guard = FileLock::acquire(guard)?;
+
+    if let Some(holder) = read_owner(path) {
+        if process::is_running(holder.pid, holder.pid_start) {
+            return Ok(Some(holder));
+        }
+        // 持有者已退出或 PID 已被复用：接管过期锁
+        eprintln!(
+            "[OMCC] 清理过期的工作区锁：{}（PID {} 已退出）",
+            holder.working_dir.display(),
+            holder.pid
+        );
+    }
+
+    let content =
+        serde_json::to_vec_pretty(owner).map_err(|e| OmccError::JsonDecode(e.to_string()))?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    file.write_all(&content)?;
+    Ok(None)
+}
+
+/// 读取锁文件（不存在或无法解析时返回 `None`）
+fn read_owner(path: &Path) -> Option<LockOwner> {
+    let content = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// FNV-1a 64 位哈希（跨进程、跨版本稳定）
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// 当前 Unix 时间（秒）
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
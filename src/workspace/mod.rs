@@ -0,0 +1,96 @@
+//! 工作区模块
+//!
+//! 可写 Agent 的工作区保护：基于 Git 的检查点（失败后恢复工作区）、跨进程写锁、隔离工作树和改动报告
+
+pub mod changes;
+pub mod checkpoint;
+pub mod lock;
+pub mod worktree;
+
+pub use changes::{Snapshot, TrackedSnapshot};
+pub use checkpoint::Checkpoint;
+pub use lock::{LockOwner, WorkspaceLock};
+pub use worktree::Worktree;
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use tokio::process::Command;
+
+use crate::types::OmccError;
+
+/// omcc 创建的提交使用的身份（避免依赖用户的 git 配置）
+const OMCC_IDENTITY: [(&str, &str); 4] = [
+    ("GIT_AUTHOR_NAME", "omcc"),
+    ("GIT_AUTHOR_EMAIL", "omcc@localhost"),
+    ("GIT_COMMITTER_NAME", "omcc"),
+    ("GIT_COMMITTER_EMAIL", "omcc@localhost"),
+];
+
+/// omcc 提交身份的环境变量
+pub(crate) fn identity_env() -> Vec<(&'static str, &'static OsStr)> {
+    OMCC_IDENTITY
+        .iter()
+        .map(|(k, v)| (*k, OsStr::new(*v)))
+        .collect()
+}
+
+/// 在指定目录执行 git 命令，返回标准输出
+pub(crate) async fn git(
+    dir: &Path,
+    args: &[&str],
+    envs: &[(&str, &OsStr)],
+) -> Result<String, OmccError> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .envs(envs.iter().copied())
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                OmccError::CommandNotFound("git".to_string())
+            } else {
+                OmccError::IoError(e)
+            }
+        })?;
+    if !output.status.success() {
+        return Err(OmccError::GitError(format!(
+            "git {} 失败：{}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// 使用临时索引将当前工作区写成树对象
+///
+/// 临时索引从当前暂存区复制，复用其中的文件状态缓存以加快 `git add`
+pub(crate) async fn snapshot_tree(
+    root: &Path,
+    git_dir: &Path,
+    id: &str,
+) -> Result<String, OmccError> {
+    let temp_index = git_dir.join(format!("omcc-snapshot-{}.index", id));
+    let index = index_path(root).await?;
+    if index.exists() {
+        std::fs::copy(&index, &temp_index)?;
+    }
+    let env = [("GIT_INDEX_FILE", temp_index.as_os_str())];
+    let tree = async {
+        git(root, &["add", "-A", "."], &env).await?;
+        git(root, &["write-tree"], &env).await
+    }
+    .await;
+    let _ = std::fs::remove_file(&temp_index);
+    Ok(tree?.trim().to_string())
+}
+
+/// 获取暂存区文件路径
+pub(crate) async fn index_path(root: &Path) -> Result<PathBuf, OmccError> {
+    let path = git(root, &["rev-parse", "--git-path", "index"], &[]).await?;
+    Ok(root.join(path.trim()))
+}
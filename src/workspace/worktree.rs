@@ -0,0 +1,165 @@
+//! 隔离工作树
+//!
+//! 在状态目录 `worktrees/` 下从指定引用创建临时的 `git worktree`，Agent 在其中执行，
+//! 主工作区保持不变。执行结束后生成相对基准提交的 diff，可将其应用回主工作区、保存为分支或丢弃
+
+use std::path::{Path, PathBuf};
+
+use super::{git, identity_env};
+use crate::state::state_subdir;
+use crate::types::OmccError;
+
+/// 隔离工作树
+#[derive(Debug)]
+pub struct Worktree {
+    /// 工作树 ID
+    pub id: String,
+
+    /// 工作树基于的提交
+    pub base: String,
+
+    /// 工作树根目录
+    pub path: PathBuf,
+
+    /// 主仓库根目录
+    root: PathBuf,
+
+    /// 工作树中与原工作目录对应的目录
+    working_dir: PathBuf,
+}
+
+impl Worktree {
+    /// 从 `base`（默认 HEAD）为 `dir` 所在的 Git 仓库创建隔离工作树
+    pub async fn create(dir: &Path, base: Option<&str>) -> Result<Self, OmccError> {
+        let root = PathBuf::from(
+            git(dir, &["rev-parse", "--show-toplevel"], &[])
+                .await
+                .map_err(|_| {
+                    OmccError::GitError(format!(
+                        "{} 不在 Git 仓库中，无法创建隔离工作树",
+                        dir.display()
+                    ))
+                })?
+                .trim(),
+        );
+        let reference = base.unwrap_or("HEAD");
+        let base = git(
+            &root,
+            &[
+                "rev-parse",
+                "--verify",
+                "-q",
+                &format!("{}^{{commit}}", reference),
+            ],
+            &[],
+        )
+        .await
+        .map_err(|_| OmccError::GitError(format!("无效的基准引用：{}", reference)))?
+        .trim()
+        .to_string();
+
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let path = state_subdir("worktrees")?.join(&id);
+        git(
+            &root,
+            &[
+                "worktree",
+                "add",
+                "--detach",
+                &path.to_string_lossy(),
+                &base,
+            ],
+            &[],
+        )
+        .await?;
+
+        // 保持原工作目录在仓库中的相对位置
+        let relative = std::fs::canonicalize(dir)
+            .ok()
+            .zip(std::fs::canonicalize(&root).ok())
+            .and_then(|(dir, root)| dir.strip_prefix(root).map(Path::to_path_buf).ok())
+            .unwrap_or_default();
+        let working_dir = path.join(relative);
+        // 工作目录中没有被跟踪的文件时，检出结果里不存在该目录
+        std::fs::create_dir_all(&working_dir)?;
+
+        Ok(Self {
+            id,
+            base,
+            path,
+            root,
+            working_dir,
+        })
+    }
+
+    /// Agent 应使用的工作目录
+    pub fn working_dir(&self) -> &Path {
+        &self.working_dir
+    }
+
+    /// 生成工作树相对基准提交的 diff（含未跟踪、未被忽略的文件和 Agent 的提交）
+    pub async fn diff(&self) -> Result<String, OmccError> {
+        self.stage().await?;
+        git(
+            &self.path,
+            &["diff", "--cached", "--binary", &self.base],
+            &[],
+        )
+        .await
+    }
+
+    /// 将 diff 应用到主工作区（不修改暂存区）
+    pub async fn apply(&self, diff: &str) -> Result<(), OmccError> {
+        if diff.is_empty() {
+            return Ok(());
+        }
+        let patch = state_subdir("worktrees")?.join(format!("{}.patch", self.id));
+        std::fs::write(&patch, diff)?;
+        let applied = git(
+            &self.root,
+            &["apply", "--binary", &patch.to_string_lossy()],
+            &[],
+        )
+        .await;
+        let _ = std::fs::remove_file(&patch);
+        applied.map(|_| ())
+    }
+
+    /// 将工作树的改动提交到以基准提交为父提交的新分支，返回提交 ID
+    pub async fn branch(&self, name: &str, message: &str) -> Result<String, OmccError> {
+        self.stage().await?;
+        let tree = git(&self.path, &["write-tree"], &[]).await?;
+        let commit = git(
+            &self.path,
+            &["commit-tree", tree.trim(), "-p", &self.base, "-m", message],
+            &identity_env(),
+        )
+        .await?
+        .trim()
+        .to_string();
+        git(&self.root, &["branch", name, &commit], &[]).await?;
+        Ok(commit)
+    }
+
+    /// 删除工作树
+    pub async fn remove(self) -> Result<(), OmccError> {
+        git(
+            &self.root,
+            &[
+                "worktree",
+                "remove",
+                "--force",
+                &self.path.to_string_lossy(),
+            ],
+            &[],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// 将工作树的全部改动加入其暂存区
+    async fn stage(&self) -> Result<(), OmccError> {
+        git(&self.path, &["add", "-A", "."], &[]).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! 后端能力校验测试
+//!
+//! 覆盖能力矩阵、按能力拒绝或警告不支持的选项、附件和模型参数在各后端命令中的映射，以及 OpenCode 的权限配置，不需要真实 CLI
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use omcc::{
+    AgentConfig, AgentExecutor, AgentResult, AgentType, ArgTemplate, BackendSpec, CliTool,
+    CustomCommand, ErrorKind, OmccError, PromptDelivery,
+};
+
+/// 在指定后端上构建配置（不会启动进程，工作目录不需要存在）
+fn config(agent_type: AgentType, backend: &str) -> AgentConfig {
+    let mut config = common::config(agent_type, Path::new("/nonexistent"));
+    config.backend = Some(backend.parse().unwrap());
+    config
+}
+
+/// Looker 分析指定文件
+fn looker(backend: &str, file: &str) -> AgentConfig {
+    let mut config = config(AgentType::Looker, backend);
+    config.file_path = Some(PathBuf::from(file));
+    config
+}
+
+/// 主后端命令的参数
+fn preview_args(config: AgentConfig) -> Vec<String> {
+    AgentExecutor::new(config).preview_command().unwrap().args
+}
+
+/// 断言试运行因配置错误失败
+fn assert_rejected(config: AgentConfig) {
+    match AgentExecutor::new(config).dry_run() {
+        Err(OmccError::ConfigError(_)) => {}
+        other => panic!(
+            "预期配置错误，实际：{:?}",
+            other.map(|report| report.command)
+        ),
+    }
+}
+
+/// 试运行的警告
+fn warnings(config: AgentConfig) -> Vec<String> {
+    AgentExecutor::new(config).dry_run().unwrap().warnings
+}
+
+#[test]
+fn builtin_matrix() {
+    let claude = CliTool::Claude.capabilities();
+    assert!(!claude.images && !claude.files && claude.sessions && claude.usage);
+    let codex = CliTool::Codex.capabilities();
+    assert!(codex.images && !codex.files && codex.model);
+    let opencode = CliTool::OpenCode.capabilities();
+    assert!(opencode.images && opencode.files && opencode.sessions && !opencode.usage);
+    let openai = CliTool::OpenAi.capabilities();
+    assert!(!openai.images && !openai.sandbox && openai.sessions && openai.usage);
+}
+
+#[test]
+fn custom_capabilities_follow_placeholders() {
+    let command = CustomCommand {
+        program: "tool".to_string(),
+        args: vec![
+            ArgTemplate::Group(vec!["--model".to_string(), "{model}".to_string()]),
+            ArgTemplate::Arg("{prompt}".to_string()),
+        ],
+        prompt: PromptDelivery::Arg,
+        output: None,
+    };
+    let caps = command.capabilities();
+    assert!(caps.model);
+    assert!(!caps.images && !caps.sessions && !caps.sandbox && !caps.usage);
+
+    let mut config = config(AgentType::Researcher, "custom:tool:m1");
+    config.commands.insert("tool".to_string(), command);
+    config.session_id = Some("s-1".to_string());
+    assert_rejected(config.clone());
+
+    config.session_id = None;
+    let report = AgentExecutor::new(config).dry_run().unwrap();
+    assert_eq!(report.command.args, vec!["--model", "m1", "<prompt>"]);
+    assert_eq!(report.warnings.len(), 1, "沙箱策略无法传给后端时应警告");
+}
+
+#[test]
+fn images_need_image_support() {
+    let mut claude = config(AgentType::Advisor, "claude");
+    claude.images = vec![PathBuf::from("shot.png")];
+    assert_rejected(claude);
+
+    let mut codex = config(AgentType::Advisor, "codex");
+    codex.images = vec![PathBuf::from("shot.png")];
+    let args = preview_args(codex);
+    assert!(args.windows(2).any(|w| w == ["--image", "shot.png"]));
+
+    let mut opencode = config(AgentType::Advisor, "opencode");
+    opencode.images = vec![PathBuf::from("shot.png")];
+    let args = preview_args(opencode);
+    assert!(args.windows(2).any(|w| w == ["--file", "shot.png"]));
+}
+
+#[test]
+fn looker_files_map_by_type() {
+    let args = preview_args(looker("opencode", "paper.pdf"));
+    assert!(args.windows(2).any(|w| w == ["--file", "paper.pdf"]));
+    assert!(!args.contains(&"--image".to_string()));
+
+    let args = preview_args(looker("codex", "diagram.PNG"));
+    assert!(args.windows(2).any(|w| w == ["--image", "diagram.PNG"]));
+
+    assert_rejected(looker("codex", "paper.pdf"));
+    assert_rejected(looker("gemini", "diagram.png"));
+}
+
+#[test]
+fn codex_receives_model_and_opencode_session() {
+    let args = preview_args(config(AgentType::Advisor, "codex:gpt-5"));
+    assert!(args.windows(2).any(|w| w == ["--model", "gpt-5"]));
+
+    let mut opencode = config(AgentType::Advisor, "opencode");
+    opencode.session_id = Some("ses_1".to_string());
+    let args = preview_args(opencode);
+    assert!(args.windows(2).any(|w| w == ["--session", "ses_1"]));
+}
+
+#[test]
+fn soft_gaps_are_warnings() {
+    let mut opencode = config(AgentType::Researcher, "opencode");
+    opencode.return_metrics = true;
+    assert_eq!(warnings(opencode).len(), 1);
+
+    // HTTP 后端不访问工作区：只读任务无需警告，需要写入的任务提示沙箱不会生效
+    assert!(warnings(config(AgentType::Researcher, "openai:gpt-4.1")).is_empty());
+    assert_eq!(
+        warnings(config(AgentType::Chore, "openai:gpt-4.1")).len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn unsupported_option_fails_before_spawning() {
+    // 命令不存在也应先报告配置错误，而不是 command_not_found
+    let mut config = config(AgentType::Advisor, "custom:missing-tool");
+    config.commands.insert(
+        "missing-tool".to_string(),
+        CustomCommand {
+            program: "omcc-no-such-program".to_string(),
+            args: Vec::new(),
+            prompt: PromptDelivery::Stdin,
+            output: None,
+        },
+    );
+    config.images = vec![PathBuf::from("shot.png")];
+    match AgentExecutor::new(config).execute().await {
+        AgentResult::Failure(failure) => assert_eq!(failure.error_kind, ErrorKind::ConfigError),
+        AgentResult::Success(success) => panic!("预期失败，实际成功：{}", success.result),
+    }
+}
+
+#[test]
+fn backend_spec_name_in_errors() {
+    let mut config = config(AgentType::Advisor, "gemini");
+    config.images = vec![PathBuf::from("a.png")];
+    let backend = BackendSpec::new(CliTool::Gemini, None);
+    let error = config.check_backend(&backend, false).unwrap_err();
+    assert!(error.to_string().contains("gemini"));
+}
+
+#[test]
+fn opencode_read_only_denies_shell() {
+    let permission = |sandbox: omcc::SandboxPolicy| {
+        let mut config = config(AgentType::Researcher, "opencode");
+        config.sandbox = sandbox;
+        let preview = AgentExecutor::new(config).preview_command().unwrap();
+        let content: serde_json::Value =
+            serde_json::from_str(&preview.env["OPENCODE_CONFIG_CONTENT"]).unwrap();
+        content["permission"].clone()
+    };
+    let read_only = permission(omcc::SandboxPolicy::ReadOnly);
+    assert_eq!(
+        read_only["bash"], "deny",
+        "按前缀放行的 shell 命令仍可写入文件"
+    );
+    assert_eq!(read_only["edit"], "deny");
+    assert_eq!(
+        permission(omcc::SandboxPolicy::WorkspaceWrite)["bash"],
+        "allow"
+    );
+}
@@ -0,0 +1,78 @@
+//! 集成测试共用的夹具
+//!
+//! 每个测试在独立的临时工作目录中运行，目录随 `TempDir` 离开作用域删除；
+//! 状态目录和配置文件指向 cargo 的测试临时目录，不读写用户环境
+
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+
+use omcc::{
+    AgentConfig, AgentExecutor, AgentResult, AgentType, BackendSpec, CliTool, ErrorKind,
+    SuccessResult,
+};
+use tempfile::TempDir;
+
+/// 设置测试进程的环境（只执行一次）
+///
+/// mock 和 replay 后端启动被测的 omcc 二进制（测试进程自身没有对应的隐藏子命令）
+pub fn setup() {
+    static SETUP: Once = Once::new();
+    SETUP.call_once(|| {
+        let root = shared_dir("");
+        std::env::set_var("OMCC_MOCK_BIN", env!("CARGO_BIN_EXE_omcc"));
+        std::env::set_var("OMCC_REPLAY_BIN", env!("CARGO_BIN_EXE_omcc"));
+        std::env::set_var("OMCC_STATE_DIR", root.join("state"));
+        std::env::set_var("OMCC_CONFIG", root.join("config.json"));
+    });
+}
+
+/// 当前测试二进制在 cargo 测试临时目录下的共享子目录（多个测试共用，不删除）
+pub fn shared_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join(env!("CARGO_CRATE_NAME"))
+        .join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// 创建临时工作目录
+pub fn workspace() -> TempDir {
+    setup();
+    tempfile::Builder::new()
+        .prefix("omcc-test-")
+        .tempdir()
+        .unwrap()
+}
+
+/// 在 `dir` 中以提示词 `hello` 构建配置，默认不重试
+pub fn config(agent_type: AgentType, dir: &Path) -> AgentConfig {
+    setup();
+    let mut config = AgentConfig::new(agent_type, "hello".to_string(), dir.to_path_buf());
+    config.max_retries = Some(0);
+    config
+}
+
+/// 在 `dir` 中写入 mock 脚本，返回 `mock:<脚本>` 后端
+pub fn mock_backend(dir: &Path, name: &str, script: &str) -> BackendSpec {
+    let path = dir.join(name);
+    std::fs::write(&path, script).unwrap();
+    BackendSpec::new(CliTool::Mock, Some(path.to_string_lossy().into_owned()))
+}
+
+/// 断言执行成功并返回结果
+pub async fn run(config: AgentConfig) -> SuccessResult {
+    match AgentExecutor::new(config).execute().await {
+        AgentResult::Success(success) => success,
+        AgentResult::Failure(failure) => panic!("预期成功，实际失败：{}", failure.error),
+    }
+}
+
+/// 断言执行失败并返回错误类型
+pub fn error_kind(result: &AgentResult) -> ErrorKind {
+    match result {
+        AgentResult::Failure(failure) => failure.error_kind,
+        AgentResult::Success(success) => panic!("预期失败，实际成功：{}", success.result),
+    }
+}
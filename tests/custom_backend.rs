@@ -0,0 +1,212 @@
+//! 自定义命令后端测试
+//!
+//! 用 `sh` 和 mock 后端的 `mock-cli` 子命令充当任意 CLI，覆盖参数模板展开、三种提示词传递方式和输出解析规则
+
+mod common;
+
+use std::path::PathBuf;
+
+use omcc::{
+    AgentConfig, AgentExecutor, AgentResult, AgentType, ArgTemplate, BackendSpec, CustomCommand,
+    ErrorKind, OutputRules, PromptDelivery,
+};
+use tempfile::TempDir;
+
+use common::run;
+
+/// 单个参数模板
+fn arg(value: &str) -> ArgTemplate {
+    ArgTemplate::Arg(value.to_string())
+}
+
+/// 一组参数模板
+fn group(values: &[&str]) -> ArgTemplate {
+    ArgTemplate::Group(values.iter().map(|v| v.to_string()).collect())
+}
+
+/// 以 `sh -c <script>` 定义命令，`args` 作为脚本的位置参数
+fn shell(script: &str, args: Vec<ArgTemplate>, prompt: PromptDelivery) -> CustomCommand {
+    let mut templates = vec![arg("-c"), arg(script), arg("sh")];
+    templates.extend(args);
+    CustomCommand {
+        program: "sh".to_string(),
+        args: templates,
+        prompt,
+        output: None,
+    }
+}
+
+/// 以 Researcher（只读）在 `custom:test` 后端上构建配置，默认不重试
+fn config(command: CustomCommand, model: Option<&str>) -> (TempDir, AgentConfig) {
+    let dir = common::workspace();
+    let mut config = common::config(AgentType::Researcher, dir.path());
+    config.backend = Some(BackendSpec::custom("test", model.map(str::to_string)));
+    config.commands.insert("test".to_string(), command);
+    (dir, config)
+}
+
+#[test]
+fn backend_spec_round_trips() {
+    let spec: BackendSpec = "custom:aider:gpt-4.1".parse().unwrap();
+    assert_eq!(
+        spec,
+        BackendSpec::custom("aider", Some("gpt-4.1".to_string()))
+    );
+    assert_eq!(spec.to_string(), "custom:aider:gpt-4.1");
+    assert_eq!(spec.name(), "custom:aider");
+    assert!("custom".parse::<BackendSpec>().is_err());
+}
+
+#[test]
+fn missing_placeholders_drop_their_group() {
+    let command = CustomCommand {
+        program: "tool".to_string(),
+        args: vec![
+            arg("run"),
+            group(&["--model", "{model}"]),
+            group(&["--resume", "{session_id}"]),
+            group(&["--image", "{image}"]),
+            arg("--sandbox={sandbox}"),
+        ],
+        prompt: PromptDelivery::Stdin,
+        output: None,
+    };
+    let values = [
+        ("model", Some("m1")),
+        ("session_id", None),
+        ("sandbox", Some("read-only")),
+    ];
+    let images = ["a.png".to_string(), "b.png".to_string()];
+    assert_eq!(
+        command.expand_args(&values, &images),
+        vec![
+            "run",
+            "--model",
+            "m1",
+            "--image",
+            "a.png",
+            "--image",
+            "b.png",
+            "--sandbox=read-only"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn prompt_as_argument() {
+    let command = shell(
+        "printf '%s\\n' \"$1\" | head -n 1; echo \"args=$#\"",
+        vec![arg("{prompt}"), group(&["--model", "{model}"])],
+        PromptDelivery::Arg,
+    );
+    let (_dir, config) = config(command, None);
+    let success = run(config).await;
+    assert_eq!(success.result, "hello\nargs=1");
+    assert_eq!(success.backend.as_deref(), Some("custom:test"));
+}
+
+#[tokio::test]
+async fn prompt_placeholders_are_not_expanded_again() {
+    let command = shell(
+        "printf '%s\\n' \"$1\" | head -n 1",
+        vec![arg("{prompt}"), arg("{model}")],
+        PromptDelivery::Arg,
+    );
+    let (_dir, mut config) = config(command, Some("m1"));
+    config.prompt = "print {session_id} and {model} please".to_string();
+    let success = run(config).await;
+    assert_eq!(success.result, "print {session_id} and {model} please");
+}
+
+#[tokio::test]
+async fn prompt_via_stdin() {
+    let command = shell(
+        "head -n 1; echo \"model=$1\"",
+        vec![arg("{model}")],
+        PromptDelivery::Stdin,
+    );
+    let (_dir, config) = config(command, Some("m2"));
+    let success = run(config).await;
+    assert_eq!(success.result, "hello\nmodel=m2");
+}
+
+#[tokio::test]
+async fn prompt_via_file_is_removed_afterwards() {
+    let command = shell(
+        "head -n 1 \"$1\"; stat -c %a \"$1\"; echo \"$1\"",
+        vec![arg("{prompt}")],
+        PromptDelivery::File,
+    );
+    let (_dir, config) = config(command, None);
+    let success = run(config).await;
+    let lines: Vec<&str> = success.result.lines().collect();
+    let [first, mode, path] = lines[..] else {
+        panic!("unexpected output: {}", success.result);
+    };
+    assert_eq!(first, "hello");
+    assert_eq!(mode, "600", "提示词文件应仅当前用户可读写");
+    assert!(
+        !PathBuf::from(path).exists(),
+        "提示词文件应在执行结束后删除"
+    );
+}
+
+#[tokio::test]
+async fn output_rules_extract_fields() {
+    let command = CustomCommand {
+        program: env!("CARGO_BIN_EXE_omcc").to_string(),
+        args: vec![arg("mock-cli"), arg("{model}")],
+        prompt: PromptDelivery::Stdin,
+        output: Some(OutputRules {
+            session_id: Some("/thread/id".to_string()),
+            text: Some("/delta/text".to_string()),
+            error: Some("/failure/reason".to_string()),
+            usage: Some("/meta/usage".to_string()),
+        }),
+    };
+    let (_dir, mut config) = config(command, None);
+    let script = config.working_dir.join("script.ndjson");
+    std::fs::write(
+        &script,
+        r#"{"stdout": {"thread": {"id": "t-1"}, "kind": "start"}}
+{"stdout": {"delta": {"text": "first"}}}
+{"stdout": {"kind": "tool", "name": "grep"}}
+{"stdout": "plain line"}
+{"stdout": {"delta": {"text": "second"}, "meta": {"usage": {"prompt_tokens": 9, "completion_tokens": 4}}}}
+"#,
+    )
+    .unwrap();
+    config.backend = Some(BackendSpec::custom(
+        "test",
+        Some(script.to_string_lossy().into_owned()),
+    ));
+    config.return_metrics = true;
+
+    let success = run(config.clone()).await;
+    assert_eq!(success.session_id, "t-1");
+    assert_eq!(success.result, "first\nplain line\nsecond");
+    let metrics = success.metrics.expect("应包含执行指标");
+    assert_eq!(metrics.input_tokens, Some(9));
+    assert_eq!(metrics.output_tokens, Some(4));
+
+    // 提取到错误信息时返回上游错误
+    std::fs::write(
+        &script,
+        "{\"stdout\": {\"failure\": {\"reason\": \"quota\"}}}\n{\"hang\": true}\n",
+    )
+    .unwrap();
+    match AgentExecutor::new(config).execute().await {
+        AgentResult::Failure(failure) => assert_eq!(failure.error_kind, ErrorKind::UpstreamError),
+        AgentResult::Success(success) => panic!("预期失败，实际成功：{}", success.result),
+    }
+}
+
+#[tokio::test]
+async fn undefined_command_is_config_error() {
+    let (_dir, mut config) = config(shell("true", Vec::new(), PromptDelivery::Stdin), None);
+    config.backend = Some(BackendSpec::custom("missing", None));
+    match AgentExecutor::new(config).execute().await {
+        AgentResult::Failure(failure) => assert_eq!(failure.error_kind, ErrorKind::ConfigError),
+        AgentResult::Success(success) => panic!("预期失败，实际成功：{}", success.result),
+    }
+}
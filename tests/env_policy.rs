@@ -0,0 +1,100 @@
+//! 环境变量策略测试
+//!
+//! 覆盖 `EnvPolicy::file_vars` 的 `.env` 解析规则和清空环境时保留变量的匹配
+
+mod common;
+
+use omcc::{EnvPolicy, OmccError};
+
+/// 将 `content` 写入临时 `.env` 文件并解析
+fn parse(content: &str) -> Vec<(String, String)> {
+    let dir = common::workspace();
+    let file = dir.path().join(".env");
+    std::fs::write(&file, content).unwrap();
+    EnvPolicy {
+        file: Some(file),
+        ..EnvPolicy::default()
+    }
+    .file_vars()
+    .unwrap()
+}
+
+/// 构造期望的变量列表
+fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn parses_assignments_in_order() {
+    let content = "\
+# 注释
+A=1
+
+export B = two
+  C=\"quoted value\"
+D='single # not a comment'
+E=
+F=a=b
+";
+    assert_eq!(
+        parse(content),
+        vars(&[
+            ("A", "1"),
+            ("B", "two"),
+            ("C", "quoted value"),
+            ("D", "single # not a comment"),
+            ("E", ""),
+            ("F", "a=b"),
+        ])
+    );
+}
+
+#[test]
+fn quotes_must_pair_and_values_are_not_expanded() {
+    assert_eq!(
+        parse("A=\"unterminated\nB='mixed\"\nC=\"\"\nD=$HOME/bin\n"),
+        vars(&[
+            ("A", "\"unterminated"),
+            ("B", "'mixed\""),
+            ("C", ""),
+            ("D", "$HOME/bin"),
+        ])
+    );
+}
+
+#[test]
+fn skips_lines_without_valid_names() {
+    assert_eq!(
+        parse("no equals sign\n=value\nnot a name=1\n1ABC=2\n_OK=3\r\nLAST=4\r\n"),
+        vars(&[("_OK", "3"), ("LAST", "4")])
+    );
+}
+
+#[test]
+fn missing_file_is_config_error() {
+    let dir = common::workspace();
+    let policy = EnvPolicy {
+        file: Some(dir.path().join("missing.env")),
+        ..EnvPolicy::default()
+    };
+    assert!(matches!(policy.file_vars(), Err(OmccError::ConfigError(_))));
+    assert!(EnvPolicy::default().file_vars().unwrap().is_empty());
+}
+
+#[test]
+fn pass_patterns() {
+    let policy = EnvPolicy {
+        clear: true,
+        pass: vec!["OPENAI_*".to_string(), "HTTPS_PROXY".to_string()],
+        ..EnvPolicy::default()
+    };
+    assert!(policy.passes("PATH"));
+    assert!(policy.passes("LC_ALL"));
+    assert!(policy.passes("OPENAI_API_KEY"));
+    assert!(policy.passes("HTTPS_PROXY"));
+    assert!(!policy.passes("HTTPS_PROXY_USER"));
+    assert!(!policy.passes("AWS_SECRET_ACCESS_KEY"));
+}
@@ -0,0 +1,189 @@
+//! 后端熔断与限流测试
+//!
+//! 每个测试使用不同的底层 CLI，避免共享同一份状态文件；
+//! 覆盖熔断、半开状态只放行一次试探、每分钟调用次数和并发数上限，以及 PID 复用时回收并发名额
+
+mod common;
+
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::time::Duration;
+
+use omcc::agents::CancellationToken;
+use omcc::guard::{self, BackendPermit};
+use omcc::{
+    AgentExecutor, AgentType, BackendSettings, CircuitBreakerPolicy, CliTool, ErrorKind, OmccError,
+    RateLimitPolicy,
+};
+
+/// 清除该 CLI 上次运行遗留的状态
+fn reset(tool: CliTool) {
+    common::setup();
+    let path = omcc::state::state_dir()
+        .join("backends")
+        .join(format!("{}.json", tool.command()));
+    let _ = std::fs::remove_file(path);
+}
+
+/// 熔断策略
+fn breaker(failure_threshold: u32, cooldown_s: u64) -> BackendSettings {
+    BackendSettings {
+        circuit_breaker: Some(CircuitBreakerPolicy {
+            failure_threshold,
+            cooldown_s,
+        }),
+        ..BackendSettings::default()
+    }
+}
+
+/// 立即失败的限流策略
+fn rate_limit(max_concurrent: Option<u32>, per_minute: Option<u32>) -> BackendSettings {
+    BackendSettings {
+        rate_limit: Some(RateLimitPolicy {
+            max_concurrent,
+            per_minute,
+            max_wait_s: 0,
+        }),
+        ..BackendSettings::default()
+    }
+}
+
+/// 获取许可
+async fn acquire(tool: CliTool, settings: &BackendSettings) -> Result<BackendPermit, OmccError> {
+    guard::acquire(tool, Some(settings), &CancellationToken::new())
+        .await
+        .map(|permit| permit.expect("配置了熔断或限流时应返回许可"))
+}
+
+/// 断言获取许可失败并返回错误类型
+async fn rejected(tool: CliTool, settings: &BackendSettings) -> ErrorKind {
+    match acquire(tool, settings).await {
+        Ok(_) => panic!("预期拒绝，实际获得许可"),
+        Err(e) => ErrorKind::from(&e),
+    }
+}
+
+#[tokio::test]
+async fn failures_open_the_breaker() {
+    reset(CliTool::Mock);
+    let dir = common::workspace();
+    let mut config = common::config(AgentType::Researcher, dir.path());
+    config.backend = Some(common::mock_backend(
+        dir.path(),
+        "script.ndjson",
+        "{\"exit\": 1}\n",
+    ));
+    config
+        .backend_settings
+        .insert(CliTool::Mock, breaker(2, 60));
+
+    for _ in 0..2 {
+        let result = AgentExecutor::new(config.clone()).execute().await;
+        assert_eq!(common::error_kind(&result), ErrorKind::SubprocessError);
+    }
+    let result = AgentExecutor::new(config).execute().await;
+    assert_eq!(common::error_kind(&result), ErrorKind::CircuitOpen);
+}
+
+#[tokio::test]
+async fn timeouts_count_as_failures() {
+    reset(CliTool::Replay);
+    let settings = breaker(1, 60);
+    acquire(CliTool::Replay, &settings)
+        .await
+        .unwrap()
+        .record(Some(ErrorKind::Timeout));
+    assert_eq!(
+        rejected(CliTool::Replay, &settings).await,
+        ErrorKind::CircuitOpen
+    );
+}
+
+#[tokio::test]
+async fn half_open_admits_one_probe() {
+    reset(CliTool::Gemini);
+    let settings = breaker(1, 1);
+    acquire(CliTool::Gemini, &settings)
+        .await
+        .unwrap()
+        .record(Some(ErrorKind::UpstreamError));
+    assert_eq!(
+        rejected(CliTool::Gemini, &settings).await,
+        ErrorKind::CircuitOpen
+    );
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let probe = acquire(CliTool::Gemini, &settings).await.unwrap();
+    assert_eq!(
+        rejected(CliTool::Gemini, &settings).await,
+        ErrorKind::CircuitOpen,
+        "试探调用进行中时应继续拒绝"
+    );
+
+    // 试探失败：重新熔断
+    probe.record(Some(ErrorKind::UpstreamError));
+    drop(probe);
+    assert_eq!(
+        rejected(CliTool::Gemini, &settings).await,
+        ErrorKind::CircuitOpen
+    );
+
+    // 试探成功：关闭熔断器
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let probe = acquire(CliTool::Gemini, &settings).await.unwrap();
+    probe.record(None);
+    drop(probe);
+    let first = acquire(CliTool::Gemini, &settings).await.unwrap();
+    let second = acquire(CliTool::Gemini, &settings).await.unwrap();
+    drop((first, second));
+}
+
+#[tokio::test]
+async fn per_minute_limit_is_throttled() {
+    reset(CliTool::Codex);
+    let settings = rate_limit(None, Some(2));
+    for _ in 0..2 {
+        acquire(CliTool::Codex, &settings).await.unwrap();
+    }
+    assert_eq!(
+        rejected(CliTool::Codex, &settings).await,
+        ErrorKind::Throttled
+    );
+}
+
+#[tokio::test]
+async fn concurrency_limit_is_throttled() {
+    reset(CliTool::Claude);
+    let settings = rate_limit(Some(1), None);
+    let permit = acquire(CliTool::Claude, &settings).await.unwrap();
+    assert_eq!(
+        rejected(CliTool::Claude, &settings).await,
+        ErrorKind::Throttled
+    );
+    drop(permit);
+    acquire(CliTool::Claude, &settings).await.unwrap();
+}
+
+#[tokio::test]
+async fn slot_of_reused_pid_is_reclaimed() {
+    reset(CliTool::OpenCode);
+    let mut unrelated = Command::new("sleep")
+        .arg("30")
+        .process_group(0)
+        .spawn()
+        .unwrap();
+    let pid = unrelated.id();
+    // 名额登记的进程已退出，其 PID 被无关进程复用：启动时间与记录不符
+    let start = omcc::process::start_time(pid).unwrap() + 1;
+    let state = serde_json::json!({
+        "active": [{ "id": "stale", "pid": pid, "start": start }],
+    });
+    let dir = omcc::state::state_subdir("backends").unwrap();
+    let path = dir.join(format!("{}.json", CliTool::OpenCode.command()));
+    std::fs::write(path, state.to_string()).unwrap();
+
+    let settings = rate_limit(Some(1), None);
+    acquire(CliTool::OpenCode, &settings).await.unwrap();
+    unrelated.kill().unwrap();
+    unrelated.wait().unwrap();
+}
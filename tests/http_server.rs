@@ -0,0 +1,237 @@
+//! HTTP 服务测试
+//!
+//! 在本地端口启动服务，覆盖访问控制（Host 检查和访问令牌）、提交参数白名单、任务保留策略和关闭时取消任务
+
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use omcc::server::{self, JobRegistry, JobStatus, Retention, ServeOptions};
+use reqwest::header::{AUTHORIZATION, HOST};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+/// 启动服务，返回监听地址
+async fn start(registry: JobRegistry, token: Option<&str>) -> SocketAddr {
+    common::setup();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = server::router(registry, token.map(str::to_string));
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    addr
+}
+
+/// 提交任务
+async fn submit(addr: SocketAddr, body: Value) -> (StatusCode, Value) {
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/jobs", addr))
+        .header("content-type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    (
+        status,
+        response.text().await.map_or(Value::Null, |t| {
+            serde_json::from_str(&t).unwrap_or(Value::Null)
+        }),
+    )
+}
+
+/// 等待任务结束
+async fn wait(registry: &JobRegistry, id: &str) -> JobStatus {
+    for _ in 0..100 {
+        let status = registry.get(id).map(|job| job.status);
+        if let Some(status) = status.filter(|s| s.is_terminal()) {
+            return status;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("任务 {} 未在预期时间内结束", id);
+}
+
+#[test]
+fn non_loopback_listen_needs_token() {
+    let options = |listen: &str, token: Option<&str>| ServeOptions {
+        listen: listen.parse().unwrap(),
+        token: token.map(str::to_string),
+    };
+    assert!(options("127.0.0.1:8765", None).validate().is_ok());
+    assert!(options("[::1]:8765", None).validate().is_ok());
+    assert!(options("0.0.0.0:8765", None).validate().is_err());
+    assert!(options("0.0.0.0:8765", Some("")).validate().is_err());
+    assert!(options("0.0.0.0:8765", Some("secret")).validate().is_ok());
+}
+
+#[tokio::test]
+async fn foreign_host_is_rejected_without_token() {
+    let addr = start(JobRegistry::new(), None).await;
+    let client = reqwest::Client::new();
+    let health = format!("http://{}/health", addr);
+
+    let response = client.get(&health).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client
+        .get(&health)
+        .header(HOST, format!("localhost:{}", addr.port()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // DNS 重绑定：域名解析到 127.0.0.1，但 Host 仍为攻击者的域名
+    let response = client
+        .get(format!("http://{}/jobs", addr))
+        .header(HOST, format!("attacker.example:{}", addr.port()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn token_is_required_except_for_health() {
+    let addr = start(JobRegistry::new(), Some("secret")).await;
+    let client = reqwest::Client::new();
+    let jobs = format!("http://{}/jobs", addr);
+
+    let response = client.get(&jobs).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = client
+        .get(&jobs)
+        .header(AUTHORIZATION, "Bearer wrong")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = client
+        .get(&jobs)
+        .header(AUTHORIZATION, "Bearer secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .get(format!("http://{}/health", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn submit_accepts_only_whitelisted_fields() {
+    let addr = start(JobRegistry::new(), None).await;
+    let dir = common::workspace();
+
+    let rejected = [
+        json!({ "prompt": "x" }),
+        json!({ "agent": "writer", "prompt": "x" }),
+        json!({
+            "agent": "chore",
+            "prompt": "x",
+            "backend": "custom:evil",
+            "commands": { "evil": { "program": "sh", "args": ["-c", "id"] } },
+        }),
+        json!({ "agent": "chore", "prompt": "x", "backend_settings": {} }),
+        json!({ "agent": "chore", "prompt": "x", "path_policy": {} }),
+        json!({ "agent": "chore", "prompt": "x", "record": dir.path() }),
+        json!({ "agent": "reviewer", "prompt": "x", "yolo": true }),
+        json!({ "agent": "researcher", "prompt": "x", "sandbox_check": "off" }),
+        json!({ "agent": "chore", "prompt": "x", "sandbox": "danger-full-access" }),
+    ];
+    for body in rejected {
+        let (status, response) = submit(addr, body.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{} -> {}", body, response);
+    }
+}
+
+#[tokio::test]
+async fn submitted_job_runs_to_completion() {
+    let registry = JobRegistry::new();
+    let addr = start(registry.clone(), None).await;
+    let dir = common::workspace();
+    let backend = common::mock_backend(
+        dir.path(),
+        "script.ndjson",
+        "{\"stdout\": {\"session_id\": \"s-1\", \"content\": \"done\"}}\n",
+    );
+
+    let (status, job) = submit(
+        addr,
+        json!({
+            "agent": "researcher",
+            "prompt": "look around",
+            "working_dir": dir.path(),
+            "backend": backend.to_string(),
+            "max_retries": 0,
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let id = job["id"].as_str().unwrap();
+    assert_eq!(wait(&registry, id).await, JobStatus::Succeeded);
+    assert_eq!(registry.get(id).unwrap().session_id.as_deref(), Some("s-1"));
+}
+
+#[tokio::test]
+async fn finished_jobs_and_events_are_bounded() {
+    common::setup();
+    let registry = JobRegistry::with_retention(Retention {
+        max_finished: 1,
+        max_events: 2,
+        ..Retention::default()
+    });
+    let dir = common::workspace();
+    let backend = common::mock_backend(
+        dir.path(),
+        "script.ndjson",
+        &"{\"stdout\": {\"content\": \"line\"}}\n".repeat(5),
+    );
+    let mut config = common::config(omcc::AgentType::Researcher, dir.path());
+    config.backend = Some(backend);
+
+    let first = registry.submit(config.clone()).id;
+    wait(&registry, &first).await;
+    let (events, _) = registry.subscribe(&first).unwrap();
+    assert_eq!(events.len(), 2, "只保留最近的事件");
+    assert!(matches!(
+        events.last(),
+        Some(omcc::AgentEvent::Finished { .. })
+    ));
+
+    let second = registry.submit(config.clone()).id;
+    wait(&registry, &second).await;
+    let third = registry.submit(config).id;
+    assert!(
+        registry.get(&first).is_none(),
+        "超出上限的已结束任务应被清理"
+    );
+    assert!(registry.get(&second).is_some());
+    wait(&registry, &third).await;
+}
+
+#[tokio::test]
+async fn shutdown_cancels_running_jobs() {
+    let dir = common::workspace();
+    let backend = common::mock_backend(
+        dir.path(),
+        "script.ndjson",
+        "{\"stdout\": {\"content\": \"tick\"}, \"sleep_ms\": 30000}\n",
+    );
+    let mut config = common::config(omcc::AgentType::Researcher, dir.path());
+    config.backend = Some(backend);
+    let registry = JobRegistry::new().with_cancellation(omcc::agents::CancellationToken::new());
+
+    let id = registry.submit(config).id;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(registry.get(&id).unwrap().status, JobStatus::Running);
+
+    tokio::time::timeout(Duration::from_secs(10), registry.shutdown())
+        .await
+        .expect("关闭时应取消并等待进行中的任务");
+    assert_eq!(registry.get(&id).unwrap().status, JobStatus::Cancelled);
+}
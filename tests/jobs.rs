@@ -0,0 +1,241 @@
+//! 后台任务状态测试
+//!
+//! 覆盖任务记录的状态转换：后台进程执行、失去后台进程时的校正、PID 复用时的取消和等待超时，
+//! 以及任务文件的权限和凭据脱敏
+
+mod common;
+
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command};
+use std::time::Duration;
+
+use omcc::jobs::{self, JobRecord, JobState, JobStore};
+use omcc::process;
+use omcc::{AgentResult, AgentType, ApiSettings, BackendSettings, CliTool, EnvPolicy, ErrorKind};
+use tempfile::TempDir;
+
+/// 创建一个使用 mock 后端的排队任务
+fn queued_job(script: &str) -> (TempDir, JobStore, JobRecord) {
+    let dir = common::workspace();
+    let mut config = common::config(AgentType::Researcher, dir.path());
+    config.backend = Some(common::mock_backend(dir.path(), "script.ndjson", script));
+    let store = JobStore::open().unwrap();
+    let record = store.create(config).unwrap();
+    (dir, store, record)
+}
+
+/// 在独立进程组中启动长时间运行的进程（充当后台进程）
+fn spawn_runner() -> Child {
+    Command::new("sleep")
+        .arg("30")
+        .process_group(0)
+        .spawn()
+        .unwrap()
+}
+
+/// 已退出进程的 PID
+fn dead_pid() -> u32 {
+    let mut child = Command::new("true").spawn().unwrap();
+    let pid = child.id();
+    child.wait().unwrap();
+    pid
+}
+
+#[test]
+fn create_and_load() {
+    let (_dir, store, record) = queued_job("{\"exit\": 0}\n");
+    assert_eq!(record.status, JobState::Queued);
+    assert_eq!(store.load(&record.id).unwrap().agent, "researcher");
+    assert!(store.load("../jobs").is_err());
+    assert!(store.load("no-such-job").is_err());
+}
+
+#[tokio::test]
+async fn run_records_result_and_session() {
+    let (_dir, store, record) =
+        queued_job("{\"stdout\": {\"session_id\": \"s-1\", \"content\": \"done\"}}\n");
+    assert!(jobs::run(&record.id).await.unwrap().is_success());
+
+    let record = store.refresh(&record.id).unwrap();
+    assert_eq!(record.status, JobState::Succeeded);
+    assert_eq!(record.session_id.as_deref(), Some("s-1"));
+    assert!(record.started_at.is_some() && record.finished_at.is_some());
+    assert!(store.read_result(&record).is_some());
+}
+
+#[tokio::test]
+async fn job_files_are_private_and_redacted() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = common::workspace();
+    let mut config = common::config(AgentType::Researcher, dir.path());
+    config.backend = Some(common::mock_backend(
+        dir.path(),
+        "script.ndjson",
+        "{\"stdout\": {\"content\": \"done\"}}\n",
+    ));
+    config.backend_settings.insert(
+        CliTool::OpenAi,
+        BackendSettings {
+            api: Some(ApiSettings {
+                api_key: Some("sk-job-secret".to_string()),
+                ..ApiSettings::default()
+            }),
+            env: Some(EnvPolicy {
+                set: [("GITHUB_TOKEN".to_string(), "ghp_job_secret".to_string())].into(),
+                ..EnvPolicy::default()
+            }),
+            ..BackendSettings::default()
+        },
+    );
+    let store = JobStore::open().unwrap();
+    let record = store.create(config).unwrap();
+    assert!(jobs::run(&record.id).await.unwrap().is_success());
+
+    let mode =
+        |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    let job_dir = record.result_file.parent().unwrap();
+    assert_eq!(mode(job_dir), 0o700);
+    for name in ["job.json", "job.lock", "result.json"] {
+        assert_eq!(mode(&job_dir.join(name)), 0o600, "{}", name);
+    }
+    let saved = std::fs::read_to_string(job_dir.join("job.json")).unwrap();
+    let printed = serde_json::to_string(&record).unwrap();
+    for content in [saved, printed] {
+        assert!(!content.contains("sk-job-secret"), "{}", content);
+        assert!(!content.contains("ghp_job_secret"), "{}", content);
+    }
+}
+
+#[tokio::test]
+async fn cancelled_queued_job_is_not_run() {
+    let (_dir, store, record) =
+        queued_job("{\"stdout\": {\"session_id\": \"s-1\", \"content\": \"done\"}}\n");
+    assert_eq!(
+        jobs::cancel(&record.id).unwrap().status,
+        JobState::Cancelled
+    );
+
+    let result = jobs::run(&record.id).await.unwrap();
+    assert_eq!(common::error_kind(&result), ErrorKind::Cancelled);
+    let record = store.load(&record.id).unwrap();
+    assert_eq!(record.status, JobState::Cancelled);
+    assert!(record.started_at.is_none() && record.session_id.is_none());
+    assert!(!record.result_file.exists());
+}
+
+#[test]
+fn queued_job_with_dead_runner_fails() {
+    let (_dir, store, record) = queued_job("{\"exit\": 0}\n");
+    let pid = dead_pid();
+    store.update(&record.id, |r| r.pid = Some(pid)).unwrap();
+
+    let record = store.refresh(&record.id).unwrap();
+    assert_eq!(record.status, JobState::Failed);
+    assert_eq!(record.error.as_deref(), Some("后台进程未能启动"));
+}
+
+#[test]
+fn queued_job_without_pid_fails_after_grace() {
+    let (_dir, store, record) = queued_job("{\"exit\": 0}\n");
+    assert_eq!(
+        store.refresh(&record.id).unwrap().status,
+        JobState::Queued,
+        "刚提交的任务可能还没记录 PID"
+    );
+
+    store.update(&record.id, |r| r.created_at -= 3600).unwrap();
+    assert_eq!(store.refresh(&record.id).unwrap().status, JobState::Failed);
+}
+
+#[test]
+fn running_job_takes_state_from_result_file() {
+    let (_dir, store, record) = queued_job("{\"exit\": 0}\n");
+    let pid = dead_pid();
+    store
+        .update(&record.id, |r| {
+            r.status = JobState::Running;
+            r.pid = Some(pid);
+        })
+        .unwrap();
+    let result = AgentResult::failure(
+        AgentType::Researcher,
+        "已取消".to_string(),
+        ErrorKind::Cancelled,
+        None,
+    );
+    std::fs::write(&record.result_file, serde_json::to_vec(&result).unwrap()).unwrap();
+
+    assert_eq!(
+        store.refresh(&record.id).unwrap().status,
+        JobState::Cancelled
+    );
+}
+
+#[test]
+fn reused_pid_is_not_signalled() {
+    let (_dir, store, record) = queued_job("{\"exit\": 0}\n");
+    let mut unrelated = spawn_runner();
+    let pid = unrelated.id();
+    // PID 被无关进程复用：启动时间与记录不符
+    let wrong_start = process::start_time(pid).unwrap() + 1;
+    store
+        .update(&record.id, |r| {
+            r.status = JobState::Running;
+            r.pid = Some(pid);
+            r.pid_start = Some(wrong_start);
+        })
+        .unwrap();
+
+    let record = jobs::cancel(&record.id).unwrap();
+    assert_eq!(record.status, JobState::Failed);
+    assert!(process::is_alive(pid), "不应向复用 PID 的进程发送信号");
+    unrelated.kill().unwrap();
+    unrelated.wait().unwrap();
+}
+
+#[test]
+fn cancel_signals_live_runner() {
+    let (_dir, store, record) = queued_job("{\"exit\": 0}\n");
+    let mut runner = spawn_runner();
+    let pid = runner.id();
+    store
+        .update(&record.id, |r| {
+            r.status = JobState::Running;
+            r.pid = Some(pid);
+            r.pid_start = process::start_time(pid);
+        })
+        .unwrap();
+
+    assert_eq!(
+        jobs::cancel(&record.id).unwrap().status,
+        JobState::Cancelled
+    );
+    runner.wait().unwrap();
+    assert!(!process::is_alive(pid));
+}
+
+#[tokio::test]
+async fn wait_times_out_on_live_job() {
+    let (_dir, store, record) = queued_job("{\"exit\": 0}\n");
+    let mut runner = spawn_runner();
+    let pid = runner.id();
+    store
+        .update(&record.id, |r| {
+            r.pid = Some(pid);
+            r.pid_start = process::start_time(pid);
+        })
+        .unwrap();
+
+    let waited = jobs::wait(&record.id, Some(Duration::from_millis(100)))
+        .await
+        .unwrap();
+    assert!(waited.is_none());
+    runner.kill().unwrap();
+    runner.wait().unwrap();
+
+    let waited = jobs::wait(&record.id, Some(Duration::from_secs(5)))
+        .await
+        .unwrap();
+    assert_eq!(waited.map(|r| r.status), Some(JobState::Failed));
+}
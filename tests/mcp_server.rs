@@ -0,0 +1,369 @@
+//! MCP 服务器测试
+//!
+//! 在进程内调用 `McpServer::handle` 覆盖 JSON-RPC 的请求、通知、批量、错误码和取消，工具调用使用 mock 后端；
+//! 并校验手写的工具 schema 与工具参数的字段一致
+
+mod common;
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use omcc::agents::CancellationToken;
+use omcc::mcp::schema::{tool_input_schema, ToolArguments, TOOL_AGENTS};
+use omcc::mcp::McpServer;
+use omcc::{AgentType, RetryPolicy};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+/// 发送一条请求并返回响应
+async fn request(method: &str, params: Value) -> Value {
+    common::setup();
+    McpServer::new()
+        .handle(json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }))
+        .await
+        .expect("请求应有响应")
+}
+
+/// 响应中的错误码
+fn error_code(response: &Value) -> i64 {
+    response["error"]["code"]
+        .as_i64()
+        .unwrap_or_else(|| panic!("预期错误响应，实际：{}", response))
+}
+
+#[tokio::test]
+async fn initialize_negotiates_protocol_version() {
+    let response = request("initialize", json!({ "protocolVersion": "2025-06-18" })).await;
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["result"]["protocolVersion"], "2025-06-18");
+    assert_eq!(response["result"]["serverInfo"]["name"], "omcc");
+
+    let response = request("initialize", Value::Null).await;
+    assert_eq!(response["result"]["protocolVersion"], "2024-11-05");
+
+    // 不支持的版本以默认版本应答，由客户端决定是否断开
+    let response = request("initialize", json!({ "protocolVersion": "1999-01-01" })).await;
+    assert_eq!(response["result"]["protocolVersion"], "2024-11-05");
+}
+
+#[tokio::test]
+async fn lists_agent_tools() {
+    let response = request("tools/list", Value::Null).await;
+    let tools = response["result"]["tools"].as_array().unwrap();
+    let names: Vec<_> = tools.iter().map(|t| t["name"].as_str().unwrap()).collect();
+    assert_eq!(
+        names,
+        ["reviewer", "advisor", "chore", "researcher", "looker"]
+    );
+    let looker = &tools[4]["inputSchema"];
+    assert_eq!(looker["required"], json!(["prompt", "file_path"]));
+    assert_eq!(looker["additionalProperties"], false);
+}
+
+#[test]
+fn tool_schemas_match_arguments() {
+    let arguments = serde_json::to_value(ToolArguments::default()).unwrap();
+    let fields: BTreeSet<&str> = arguments
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+
+    let mut properties = BTreeSet::new();
+    for agent in TOOL_AGENTS {
+        let schema = tool_input_schema(agent);
+        for (name, property) in schema["properties"].as_object().unwrap() {
+            assert!(
+                fields.contains(name.as_str()),
+                "{} 的 schema 字段 {} 不是工具参数",
+                agent.name(),
+                name
+            );
+            properties.insert(name.clone());
+            // 枚举值和默认值必须能被参数解析接受
+            let values = property["enum"].as_array().cloned().unwrap_or_default();
+            for value in values.into_iter().chain(property.get("default").cloned()) {
+                let args = json!({ name.as_str(): value });
+                assert!(
+                    ToolArguments::from_value(Some(args.clone())).is_ok(),
+                    "{} 的 schema 值无效：{}",
+                    agent.name(),
+                    args
+                );
+            }
+        }
+    }
+    let properties: BTreeSet<&str> = properties.iter().map(String::as_str).collect();
+    assert_eq!(properties, fields, "schema 与工具参数字段不一致");
+
+    // 重试策略的子字段
+    let retry = serde_json::to_value(RetryPolicy {
+        budget_s: Some(0),
+        ..RetryPolicy::default()
+    })
+    .unwrap();
+    let schema = tool_input_schema(AgentType::Chore);
+    let retry_properties = &schema["properties"]["retry_policy"]["properties"];
+    let expected: BTreeSet<_> = retry.as_object().unwrap().keys().collect();
+    let actual: BTreeSet<_> = retry_properties.as_object().unwrap().keys().collect();
+    assert_eq!(actual, expected);
+}
+
+#[tokio::test]
+async fn notifications_and_responses_get_no_reply() {
+    let server = McpServer::new();
+    let notification = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+    assert_eq!(server.handle(notification.clone()).await, None);
+    // 客户端发来的响应没有 method 也没有需要回复的 id
+    let reply = json!({ "jsonrpc": "2.0", "result": {} });
+    assert_eq!(server.handle(reply).await, None);
+
+    let batch = json!([notification, { "jsonrpc": "2.0", "id": 7, "method": "ping" }]);
+    let responses = server.handle(batch).await.unwrap();
+    assert_eq!(
+        responses,
+        json!([{ "jsonrpc": "2.0", "id": 7, "result": {} }])
+    );
+}
+
+#[tokio::test]
+async fn protocol_errors_use_json_rpc_codes() {
+    assert_eq!(error_code(&request("no/such", Value::Null).await), -32601);
+
+    let missing_method = McpServer::new()
+        .handle(json!({ "jsonrpc": "2.0", "id": 2 }))
+        .await
+        .unwrap();
+    assert_eq!(error_code(&missing_method), -32600);
+
+    let unknown_tool = request("tools/call", json!({ "name": "writer" })).await;
+    assert_eq!(error_code(&unknown_tool), -32602);
+
+    let unknown_field = request(
+        "tools/call",
+        json!({ "name": "advisor", "arguments": { "prompt": "x", "shell": "rm -rf /" } }),
+    )
+    .await;
+    assert_eq!(error_code(&unknown_field), -32602);
+
+    let missing_prompt = request("tools/call", json!({ "name": "advisor" })).await;
+    assert_eq!(error_code(&missing_prompt), -32602);
+
+    let missing_file = request(
+        "tools/call",
+        json!({ "name": "looker", "arguments": { "prompt": "x" } }),
+    )
+    .await;
+    assert_eq!(error_code(&missing_file), -32602);
+}
+
+#[test]
+fn invalid_settings_are_internal_errors() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    // 在子进程中运行，避免改动本进程共用的配置文件
+    let dir = common::workspace();
+    let config = dir.path().join("config.json");
+    std::fs::write(&config, "{ not json").unwrap();
+    let mut server = Command::new(env!("CARGO_BIN_EXE_omcc"))
+        .args(["mcp", "serve"])
+        .env("OMCC_CONFIG", &config)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let call = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "tools/call",
+        "params": { "name": "advisor", "arguments": { "prompt": "x" } },
+    });
+    writeln!(server.stdin.take().unwrap(), "{}", call).unwrap();
+    let output = server.wait_with_output().unwrap();
+    let response: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(error_code(&response), -32603);
+}
+
+#[tokio::test]
+async fn tool_call_runs_agent() {
+    let dir = common::workspace();
+    let backend = common::mock_backend(
+        dir.path(),
+        "script.ndjson",
+        "{\"stdout\": {\"session_id\": \"s-1\", \"content\": \"done\"}}\n",
+    );
+    let response = request(
+        "tools/call",
+        json!({
+            "name": "researcher",
+            "arguments": {
+                "prompt": "look around",
+                "working_dir": dir.path(),
+                "backend": backend.to_string(),
+                "max_retries": 0,
+            },
+        }),
+    )
+    .await;
+    let result = &response["result"];
+    assert_eq!(result["isError"], false);
+    assert_eq!(result["structuredContent"]["SESSION_ID"], "s-1");
+    assert_eq!(result["structuredContent"]["result"], "done");
+
+    // 执行失败通过 isError 报告，而不是 JSON-RPC 错误
+    std::fs::write(dir.path().join("script.ndjson"), "{\"exit\": 0}\n").unwrap();
+    let response = request(
+        "tools/call",
+        json!({
+            "name": "researcher",
+            "arguments": {
+                "prompt": "look around",
+                "working_dir": dir.path(),
+                "backend": backend.to_string(),
+                "max_retries": 0,
+            },
+        }),
+    )
+    .await;
+    assert_eq!(response["result"]["isError"], true);
+}
+
+#[tokio::test]
+async fn prompts_and_resources() {
+    let prompt = request("prompts/get", json!({ "name": "chore" })).await;
+    let text = prompt["result"]["messages"][0]["content"]["text"]
+        .as_str()
+        .unwrap();
+    assert!(!text.is_empty());
+
+    let resource = request("resources/read", json!({ "uri": "omcc://skills/looker" })).await;
+    assert_eq!(
+        resource["result"]["contents"][0]["uri"],
+        "omcc://skills/looker"
+    );
+
+    let unknown = request("resources/read", json!({ "uri": "omcc://skills/writer" })).await;
+    assert_eq!(error_code(&unknown), -32602);
+}
+
+#[tokio::test]
+async fn serve_answers_each_line() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (server_read, server_write) = tokio::io::split(server);
+    let serving = tokio::spawn(McpServer::new().serve(BufReader::new(server_read), server_write));
+
+    let (client_read, mut client_write) = tokio::io::split(client);
+    client_write
+        .write_all(b"{not json}\n\n{\"jsonrpc\":\"2.0\",\"id\":\"a\",\"method\":\"ping\"}\n")
+        .await
+        .unwrap();
+    client_write.shutdown().await.unwrap();
+
+    let mut lines = BufReader::new(client_read).lines();
+    let mut responses = Vec::new();
+    while let Some(line) = lines.next_line().await.unwrap() {
+        responses.push(serde_json::from_str::<Value>(&line).unwrap());
+    }
+    serving.await.unwrap().unwrap();
+
+    assert_eq!(responses.len(), 2);
+    assert_eq!(error_code(&responses[0]), -32700);
+    assert_eq!(responses[0]["id"], Value::Null);
+    assert_eq!(responses[1]["id"], "a");
+}
+
+/// 长时间运行的工具调用请求
+fn hanging_call(dir: &std::path::Path, id: Value) -> Value {
+    let backend = common::mock_backend(
+        dir,
+        "script.ndjson",
+        "{\"stdout\": {\"content\": \"tick\"}, \"sleep_ms\": 30000}\n",
+    );
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": "tools/call",
+        "params": {
+            "name": "researcher",
+            "arguments": {
+                "prompt": "look around",
+                "working_dir": dir,
+                "backend": backend.to_string(),
+                "max_retries": 0,
+            },
+        },
+    })
+}
+
+#[tokio::test]
+async fn cancelled_notification_stops_tool_call() {
+    let dir = common::workspace();
+    let server = McpServer::new();
+    let call = tokio::spawn({
+        let server = server.clone();
+        let request = hanging_call(dir.path(), json!("call-1"));
+        async move { server.handle(request).await }
+    });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // 未知请求的取消通知被忽略
+    let other = json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/cancelled",
+        "params": { "requestId": "call-2" },
+    });
+    assert_eq!(server.handle(other).await, None);
+    assert!(!call.is_finished());
+
+    let cancel = json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/cancelled",
+        "params": { "requestId": "call-1", "reason": "user" },
+    });
+    assert_eq!(server.handle(cancel).await, None);
+    let response = tokio::time::timeout(Duration::from_secs(10), call)
+        .await
+        .expect("取消后工具调用应尽快结束")
+        .unwrap()
+        .unwrap();
+    assert_eq!(response["result"]["isError"], true);
+    assert_eq!(
+        response["result"]["structuredContent"]["error_kind"],
+        "cancelled"
+    );
+}
+
+#[tokio::test]
+async fn root_cancellation_stops_serving() {
+    let dir = common::workspace();
+    let cancel = CancellationToken::new();
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (server_read, server_write) = tokio::io::split(server);
+    let serving = tokio::spawn(
+        McpServer::new()
+            .with_cancellation(cancel.clone())
+            .serve(BufReader::new(server_read), server_write),
+    );
+
+    let (client_read, mut client_write) = tokio::io::split(client);
+    let request = hanging_call(dir.path(), json!(1));
+    client_write
+        .write_all(format!("{}\n", request).as_bytes())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    cancel.cancel();
+
+    // 输入未关闭：根令牌触发后服务器仍应回复进行中的请求并退出
+    tokio::time::timeout(Duration::from_secs(10), serving)
+        .await
+        .expect("根令牌取消后服务器应退出")
+        .unwrap()
+        .unwrap();
+    let mut lines = BufReader::new(client_read).lines();
+    let line = lines.next_line().await.unwrap().unwrap();
+    let response: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(response["result"]["isError"], true);
+}
@@ -0,0 +1,187 @@
+//! OpenAI 兼容 HTTP 后端测试
+//!
+//! 在本地启动一个返回 SSE 流的桩服务器，覆盖流式解析、用量统计、会话续接和 HTTP 错误映射
+
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+
+use omcc::{
+    AgentConfig, AgentExecutor, AgentResult, AgentType, ApiSettings, BackendSettings, BackendSpec,
+    CliTool, ErrorKind,
+};
+
+use common::error_kind;
+
+/// 桩服务器：按模型名返回不同的响应，成功时回显收到的消息数
+async fn chat(headers: HeaderMap, Json(body): Json<Value>) -> Response {
+    if headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        != Some("Bearer test-key")
+    {
+        return (StatusCode::UNAUTHORIZED, "missing key").into_response();
+    }
+    let messages = body["messages"].as_array().map_or(0, Vec::len);
+    match body["model"].as_str() {
+        Some("limited") => (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(json!({ "error": { "message": "Rate limit reached" } })),
+        )
+            .into_response(),
+        Some("broken") => (StatusCode::INTERNAL_SERVER_ERROR, "boom").into_response(),
+        Some("empty") => sse(&[json!({ "choices": [{ "delta": { "role": "assistant" } }] })]),
+        Some("slow") => {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            sse(&[])
+        }
+        _ => sse(&[
+            json!({ "choices": [{ "delta": { "content": "hello " } }] }),
+            json!({ "choices": [{ "delta": { "content": "world\nmessages=" } }] }),
+            json!({ "choices": [{ "delta": { "content": messages.to_string() } }] }),
+            json!({ "choices": [], "usage": { "prompt_tokens": 42, "completion_tokens": 7 } }),
+        ]),
+    }
+}
+
+/// 以 SSE 格式返回数据块
+fn sse(chunks: &[Value]) -> Response {
+    let mut body: String = chunks
+        .iter()
+        .map(|chunk| format!("data: {}\n\n", chunk))
+        .collect();
+    body.push_str("data: [DONE]\n\n");
+    ([(header::CONTENT_TYPE, "text/event-stream")], body).into_response()
+}
+
+/// 启动桩服务器，返回监听地址
+async fn serve() -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/v1/chat/completions", post(chat));
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    addr
+}
+
+/// 以 Researcher 在 openai 后端上构建配置，默认不重试（HTTP 后端不访问工作目录）
+async fn config(model: &str) -> AgentConfig {
+    let addr = serve().await;
+    let mut config = common::config(AgentType::Researcher, &std::env::temp_dir());
+    config.backend = Some(BackendSpec::new(CliTool::OpenAi, Some(model.to_string())));
+    config.backend_settings.insert(
+        CliTool::OpenAi,
+        BackendSettings {
+            api: Some(ApiSettings {
+                base_url: format!("http://{}/v1", addr),
+                api_key: Some("test-key".to_string()),
+                ..ApiSettings::default()
+            }),
+            ..BackendSettings::default()
+        },
+    );
+    config
+}
+
+#[tokio::test]
+async fn streams_result_and_usage() {
+    let mut config = config("gpt-test").await;
+    config.return_metrics = true;
+    match AgentExecutor::new(config).execute().await {
+        AgentResult::Success(success) => {
+            assert_eq!(success.result, "hello world\nmessages=1");
+            assert_eq!(success.backend.as_deref(), Some("openai"));
+            let metrics = success.metrics.expect("应包含执行指标");
+            assert_eq!(metrics.input_tokens, Some(42));
+            assert_eq!(metrics.output_tokens, Some(7));
+        }
+        AgentResult::Failure(failure) => panic!("预期成功，实际失败：{}", failure.error),
+    }
+}
+
+#[tokio::test]
+async fn session_resumes_from_history() {
+    let config = config("gpt-test").await;
+    let AgentResult::Success(first) = AgentExecutor::new(config.clone()).execute().await else {
+        panic!("首次调用应成功");
+    };
+
+    let mut resumed = config;
+    resumed.session_id = Some(first.session_id.clone());
+    match AgentExecutor::new(resumed).execute().await {
+        AgentResult::Success(success) => {
+            assert_eq!(success.session_id, first.session_id);
+            // 历史中的用户和助手消息 + 本轮用户消息
+            assert_eq!(success.result, "hello world\nmessages=3");
+        }
+        AgentResult::Failure(failure) => panic!("预期成功，实际失败：{}", failure.error),
+    }
+}
+
+#[tokio::test]
+async fn unknown_session_is_config_error() {
+    let mut config = config("gpt-test").await;
+    config.session_id = Some("no-such-session".to_string());
+    let result = AgentExecutor::new(config).execute().await;
+    assert_eq!(error_kind(&result), ErrorKind::ConfigError);
+}
+
+#[tokio::test]
+async fn http_429_is_rate_limited() {
+    let config = config("limited").await;
+    let result = AgentExecutor::new(config).execute().await;
+    assert_eq!(error_kind(&result), ErrorKind::RateLimited);
+}
+
+#[tokio::test]
+async fn http_500_is_upstream_error() {
+    let config = config("broken").await;
+    let result = AgentExecutor::new(config).execute().await;
+    assert_eq!(error_kind(&result), ErrorKind::UpstreamError);
+}
+
+#[tokio::test]
+async fn missing_key_is_config_error() {
+    let mut config = config("gpt-test").await;
+    if let Some(api) = config
+        .backend_settings
+        .get_mut(&CliTool::OpenAi)
+        .and_then(|s| s.api.as_mut())
+    {
+        api.api_key = None;
+        api.api_key_env = "OMCC_TEST_UNSET_KEY".to_string();
+    }
+    let result = AgentExecutor::new(config).execute().await;
+    assert_eq!(error_kind(&result), ErrorKind::ConfigError);
+}
+
+#[tokio::test]
+async fn dry_run_redacts_api_key() {
+    let report = AgentExecutor::new(config("gpt-test").await)
+        .dry_run()
+        .unwrap();
+    let output = serde_json::to_string(&report).unwrap();
+    assert!(!output.contains("test-key"), "{}", output);
+    assert!(output.contains("[REDACTED]"));
+}
+
+#[tokio::test]
+async fn empty_stream_is_empty_result() {
+    let config = config("empty").await;
+    let result = AgentExecutor::new(config).execute().await;
+    assert_eq!(error_kind(&result), ErrorKind::EmptyResult);
+}
+
+#[tokio::test]
+async fn slow_response_hits_idle_timeout() {
+    let mut config = config("slow").await;
+    config.timeout = Some(1);
+    let result = AgentExecutor::new(config).execute().await;
+    assert_eq!(error_kind(&result), ErrorKind::IdleTimeout);
+}
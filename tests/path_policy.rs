@@ -0,0 +1,96 @@
+//! 路径策略测试
+//!
+//! 覆盖 `PathPolicy::check` 的允许列表、禁止列表、文件名模式和无效模式
+
+use omcc::{OmccError, PathPolicy, PathViolation};
+
+/// 构建策略
+fn policy(allow: &[&str], deny: &[&str]) -> PathPolicy {
+    PathPolicy {
+        allow: allow.iter().map(|p| p.to_string()).collect(),
+        deny: deny.iter().map(|p| p.to_string()).collect(),
+        rollback: false,
+    }
+}
+
+/// 违反策略的路径及匹配到的禁止模式
+fn violations(policy: &PathPolicy, paths: &[&str]) -> Vec<(String, Option<String>)> {
+    policy
+        .check(paths.iter().copied())
+        .unwrap()
+        .into_iter()
+        .map(|PathViolation { path, pattern }| (path, pattern))
+        .collect()
+}
+
+#[test]
+fn empty_policy_allows_everything() {
+    let policy = PathPolicy::default();
+    assert!(policy.is_empty());
+    assert!(violations(&policy, &["src/main.rs", ".env", "a/b/c"]).is_empty());
+}
+
+#[test]
+fn allow_list_limits_paths() {
+    let policy = policy(&["src/**", "tests/*.rs"], &[]);
+    assert_eq!(
+        violations(
+            &policy,
+            &[
+                "src/a.rs",
+                "src/deep/b.rs",
+                "tests/t.rs",
+                "tests/sub/t.rs",
+                "README.md"
+            ]
+        ),
+        [
+            ("tests/sub/t.rs".to_string(), None),
+            ("README.md".to_string(), None)
+        ],
+        "`*` 不跨越目录，`**` 匹配任意层级"
+    );
+}
+
+#[test]
+fn deny_wins_over_allow_and_reports_pattern() {
+    let policy = policy(&["**"], &["migrations/**", "Cargo.lock"]);
+    assert_eq!(
+        violations(&policy, &["src/a.rs", "migrations/001.sql", "Cargo.lock"]),
+        [
+            (
+                "migrations/001.sql".to_string(),
+                Some("migrations/**".to_string())
+            ),
+            ("Cargo.lock".to_string(), Some("Cargo.lock".to_string()))
+        ]
+    );
+}
+
+#[test]
+fn patterns_without_slash_match_file_names() {
+    let names = policy(&[], &[".env*"]);
+    assert_eq!(
+        violations(&names, &[".env", "config/.env.local", "src/env.rs"]),
+        [
+            (".env".to_string(), Some(".env*".to_string())),
+            ("config/.env.local".to_string(), Some(".env*".to_string()))
+        ]
+    );
+
+    // 含 `/` 的模式只匹配完整路径
+    let paths = policy(&[], &["config/*.toml"]);
+    assert_eq!(
+        violations(&paths, &["config/app.toml", "other/config/app.toml"]).len(),
+        1
+    );
+}
+
+#[test]
+fn invalid_pattern_is_config_error() {
+    let policy = policy(&["src/["], &[]);
+    assert!(matches!(
+        policy.check(["src/a.rs"]),
+        Err(OmccError::ConfigError(_))
+    ));
+}
@@ -0,0 +1,169 @@
+//! 超时后终止整个进程树
+//!
+//! 使用会派生孙进程的假 opencode 脚本，验证空闲超时和总时长超时后不遗留孤儿进程；
+//! 使用直接退出、但孙进程仍持有 stdout 的自定义命令，验证读取剩余输出期间超时仍会清理进程组；
+//! 使用不读取 stdin 的假 claude 脚本，验证写入 prompt 期间取消和总时长超时仍然生效
+
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Once;
+use std::time::Duration;
+
+use omcc::agents::CancellationToken;
+use omcc::process::is_alive;
+use omcc::{
+    AgentExecutor, AgentResult, AgentType, ArgTemplate, BackendSpec, CustomCommand, ErrorKind,
+    PromptDelivery,
+};
+use tempfile::TempDir;
+
+/// 假 opencode：在当前目录写入孙进程 PID；prompt 包含 `chatty` 时持续输出，否则静默挂起
+const FAKE_OPENCODE: &str = r#"#!/bin/sh
+sleep 300 &
+echo $! >> pids
+(sleep 300 & echo $! >> pids; sleep 300) &
+echo $! >> pids
+echo '{"session_id":"fake-session","content":"started"}'
+case "$*" in
+  *chatty*)
+    while true; do
+      echo '{"content":"tick"}'
+      sleep 0.2
+    done
+    ;;
+  *)
+    sleep 300
+    ;;
+esac
+"#;
+
+/// 安装假 opencode 并通过 `OMCC_OPENCODE_BIN` 指向它（所有测试共用同一个脚本）
+fn install_fake_opencode() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let path = common::shared_dir("bin").join("opencode");
+        std::fs::write(&path, FAKE_OPENCODE).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::env::set_var("OMCC_OPENCODE_BIN", &path);
+    });
+}
+
+/// 读取假 CLI 记录的孙进程 PID
+fn recorded_pids(dir: &Path) -> Vec<u32> {
+    std::fs::read_to_string(dir.join("pids"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| l.trim().parse().ok())
+        .collect()
+}
+
+/// 执行 Chore（底层为 opencode）并返回结果和工作目录
+async fn run_chore(prompt: &str, timeout: u64, max_duration: u64) -> (AgentResult, TempDir) {
+    install_fake_opencode();
+    let dir = common::workspace();
+    let mut config = common::config(AgentType::Chore, dir.path());
+    config.prompt = prompt.to_string();
+    config.timeout = Some(timeout);
+    config.max_duration = Some(max_duration);
+    (AgentExecutor::new(config).execute().await, dir)
+}
+
+/// 断言失败类型，且假 CLI 派生的所有孙进程都已退出
+fn assert_no_orphans(result: &AgentResult, dir: &Path, expected: ErrorKind) {
+    match result {
+        AgentResult::Failure(failure) => assert_eq!(failure.error_kind, expected),
+        AgentResult::Success(_) => panic!("预期失败，实际成功"),
+    }
+    let pids = recorded_pids(dir);
+    assert_eq!(pids.len(), 3, "假 CLI 应记录 3 个孙进程 PID");
+    let alive: Vec<_> = pids.into_iter().filter(|pid| is_alive(*pid)).collect();
+    assert!(alive.is_empty(), "残留的孤儿进程：{:?}", alive);
+}
+
+#[tokio::test]
+async fn idle_timeout_kills_grandchildren() {
+    let (result, dir) = run_chore("silent", 1, 60).await;
+    assert_no_orphans(&result, dir.path(), ErrorKind::IdleTimeout);
+}
+
+#[tokio::test]
+async fn max_duration_kills_grandchildren() {
+    let (result, dir) = run_chore("chatty", 30, 1).await;
+    assert_no_orphans(&result, dir.path(), ErrorKind::Timeout);
+}
+
+#[tokio::test]
+async fn exited_child_leaves_no_orphans() {
+    // 直接子进程立即退出，后台的孙进程继承 stdout 并一直挂起
+    let command = CustomCommand {
+        program: "sh".to_string(),
+        args: vec![
+            ArgTemplate::Arg("-c".to_string()),
+            ArgTemplate::Arg("sleep 300 & echo $! >> pids; echo started".to_string()),
+        ],
+        prompt: PromptDelivery::Stdin,
+        output: None,
+    };
+    let dir = common::workspace();
+    let mut config = common::config(AgentType::Researcher, dir.path());
+    config.backend = Some(BackendSpec::custom("orphan", None));
+    config.commands.insert("orphan".to_string(), command);
+    config.timeout = Some(60);
+    // 总时长超时落在子进程退出后读取剩余输出的宽限期内
+    config.max_duration = Some(1);
+
+    let result = AgentExecutor::new(config).execute().await;
+    assert_eq!(common::error_kind(&result), ErrorKind::Timeout);
+    let pids = recorded_pids(dir.path());
+    assert_eq!(pids.len(), 1);
+    assert!(!is_alive(pids[0]), "残留的孤儿进程：{}", pids[0]);
+}
+
+/// 假 claude：从不读取 stdin
+const FAKE_CLAUDE: &str = "#!/bin/sh\nexec sleep 300\n";
+
+/// 安装假 claude 并通过 `OMCC_CLAUDE_BIN` 指向它
+fn install_fake_claude() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let path = common::shared_dir("bin").join("claude");
+        std::fs::write(&path, FAKE_CLAUDE).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::env::set_var("OMCC_CLAUDE_BIN", &path);
+    });
+}
+
+/// 以超过管道缓冲区的 prompt 在假 claude 上执行（写入 stdin 会一直阻塞）
+async fn run_blocked_claude(max_duration: u64, cancel: CancellationToken) -> AgentResult {
+    install_fake_claude();
+    let dir = common::workspace();
+    let mut config = common::config(AgentType::Advisor, dir.path());
+    config.backend = Some("claude".parse().unwrap());
+    config.prompt = "x".repeat(1 << 20);
+    config.timeout = Some(0);
+    config.max_duration = Some(max_duration);
+    let executor = AgentExecutor::new(config).with_cancellation(cancel);
+    tokio::time::timeout(Duration::from_secs(20), executor.execute())
+        .await
+        .expect("写入 stdin 阻塞时执行也应按时结束")
+}
+
+#[tokio::test]
+async fn max_duration_applies_while_writing_prompt() {
+    let result = run_blocked_claude(1, CancellationToken::new()).await;
+    assert_eq!(common::error_kind(&result), ErrorKind::Timeout);
+}
+
+#[tokio::test]
+async fn cancel_applies_while_writing_prompt() {
+    let cancel = CancellationToken::new();
+    let trigger = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        trigger.cancel();
+    });
+    let result = run_blocked_claude(0, cancel).await;
+    assert_eq!(common::error_kind(&result), ErrorKind::Cancelled);
+}
@@ -0,0 +1,379 @@
+//! 工作区保护测试
+//!
+//! 在临时 Git 仓库中覆盖检查点的创建与恢复、隔离工作树的应用、保存为分支和应用失败时的回退、只读校验，
+//! 以及按仓库加锁
+
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+
+use omcc::agents::CancellationToken;
+use omcc::workspace::{Checkpoint, WorkspaceLock};
+use omcc::{
+    AgentConfig, AgentExecutor, AgentResult, AgentType, ArgTemplate, BackendSpec, CustomCommand,
+    ErrorKind, IsolationMode, IsolationReport, OmccError, PromptDelivery, WorktreeAction,
+};
+use tempfile::TempDir;
+
+/// 在 `dir` 中执行 git 命令，返回去掉首尾空白的标准输出
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(["-c", "user.name=test", "-c", "user.email=test@localhost"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git {} 失败：{}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// 写入文件（自动创建父目录）
+fn write(dir: &Path, path: &str, content: &str) {
+    let path = dir.join(path);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+/// 读取文件，不存在时返回 `None`
+fn read(dir: &Path, path: &str) -> Option<String> {
+    std::fs::read_to_string(dir.join(path)).ok()
+}
+
+/// 创建仓库：已提交 `.gitignore`（忽略 `*.log`）、`a.txt`、`b.txt`，
+/// 另有被忽略的 `keep.log` 和未跟踪的 `notes.txt`
+fn repo() -> TempDir {
+    let dir = common::workspace();
+    let root = dir.path();
+    git(root, &["init", "-q", "-b", "main"]);
+    write(root, ".gitignore", "*.log\n");
+    write(root, "a.txt", "a\n");
+    write(root, "b.txt", "b\n");
+    git(root, &["add", "-A"]);
+    git(root, &["commit", "-q", "-m", "init"]);
+    write(root, "keep.log", "user log\n");
+    write(root, "notes.txt", "notes\n");
+    dir
+}
+
+#[tokio::test]
+async fn checkpoint_restores_files_and_keeps_ignored() {
+    let dir = repo();
+    let root = dir.path();
+    let checkpoint = Checkpoint::create(root).await.unwrap();
+
+    // Agent 修改、删除、新增文件，并放开忽略规则（`keep.log` 在当前规则下像是新增文件）
+    write(root, "a.txt", "changed\n");
+    std::fs::remove_file(root.join("b.txt")).unwrap();
+    write(root, "new.txt", "new\n");
+    write(root, "dir/nested.txt", "nested\n");
+    write(root, ".gitignore", "");
+    write(root, "agent.log", "agent log\n");
+    git(root, &["add", "-A"]);
+
+    checkpoint.restore().await.unwrap();
+    assert_eq!(read(root, "a.txt").as_deref(), Some("a\n"));
+    assert_eq!(read(root, "b.txt").as_deref(), Some("b\n"));
+    assert_eq!(read(root, ".gitignore").as_deref(), Some("*.log\n"));
+    assert_eq!(read(root, "notes.txt").as_deref(), Some("notes\n"));
+    assert_eq!(read(root, "new.txt"), None);
+    assert!(!root.join("dir").exists(), "新增文件删除后应清理空目录");
+    assert_eq!(
+        read(root, "keep.log").as_deref(),
+        Some("user log\n"),
+        "被忽略的用户文件不应被删除"
+    );
+    assert!(
+        root.join("agent.log").exists(),
+        "按检查点时的规则被忽略的文件不在检查点范围内"
+    );
+    assert_eq!(git(root, &["status", "--porcelain"]), "?? notes.txt");
+    checkpoint.discard().await.unwrap();
+}
+
+#[tokio::test]
+async fn checkpoint_restores_branch_without_moving_others() {
+    let dir = repo();
+    let root = dir.path();
+    let head = git(root, &["rev-parse", "HEAD"]);
+    let checkpoint = Checkpoint::create(root).await.unwrap();
+
+    // Agent 在当前分支提交，然后切到新分支继续提交
+    write(root, "a.txt", "on main\n");
+    git(root, &["commit", "-q", "-am", "agent on main"]);
+    git(root, &["checkout", "-q", "-b", "other"]);
+    write(root, "a.txt", "on other\n");
+    git(root, &["commit", "-q", "-am", "agent on other"]);
+    let other = git(root, &["rev-parse", "other"]);
+
+    checkpoint.restore().await.unwrap();
+    assert_eq!(git(root, &["symbolic-ref", "HEAD"]), "refs/heads/main");
+    assert_eq!(git(root, &["rev-parse", "main"]), head);
+    assert_eq!(
+        git(root, &["rev-parse", "other"]),
+        other,
+        "不应移动 Agent 切换到的分支"
+    );
+    assert_eq!(read(root, "a.txt").as_deref(), Some("a\n"));
+    assert_eq!(git(root, &["status", "--porcelain"]), "?? notes.txt");
+    checkpoint.discard().await.unwrap();
+}
+
+#[tokio::test]
+async fn checkpoint_restores_detached_head() {
+    let dir = repo();
+    let root = dir.path();
+    let head = git(root, &["rev-parse", "HEAD"]);
+    git(root, &["checkout", "-q", "--detach"]);
+    let checkpoint = Checkpoint::create(root).await.unwrap();
+
+    git(root, &["checkout", "-q", "main"]);
+    write(root, "a.txt", "on main\n");
+    git(root, &["commit", "-q", "-am", "agent on main"]);
+    let main = git(root, &["rev-parse", "main"]);
+
+    checkpoint.restore().await.unwrap();
+    assert!(
+        Command::new("git")
+            .current_dir(root)
+            .args(["symbolic-ref", "-q", "HEAD"])
+            .output()
+            .unwrap()
+            .status
+            .code()
+            == Some(1),
+        "应恢复为分离 HEAD"
+    );
+    assert_eq!(git(root, &["rev-parse", "HEAD"]), head);
+    assert_eq!(git(root, &["rev-parse", "main"]), main);
+    checkpoint.discard().await.unwrap();
+}
+
+/// 在 `dir` 中运行 Agent，底层 CLI 为执行 `script` 后输出 `done` 的 `sh`
+fn shell_agent(agent_type: AgentType, dir: &Path, script: &str) -> AgentConfig {
+    let mut config = common::config(agent_type, dir);
+    config.backend = Some(BackendSpec::custom("edit", None));
+    config.commands.insert(
+        "edit".to_string(),
+        CustomCommand {
+            program: "sh".to_string(),
+            args: vec![
+                ArgTemplate::Arg("-c".to_string()),
+                ArgTemplate::Arg(format!("{}; echo done", script)),
+            ],
+            prompt: PromptDelivery::Stdin,
+            output: None,
+        },
+    );
+    config
+}
+
+/// 在隔离工作树中运行 Chore
+fn isolated_chore(root: &Path, script: &str, on_finish: WorktreeAction) -> AgentConfig {
+    let mut config = shell_agent(AgentType::Chore, root, script);
+    config.isolate = IsolationMode::Worktree;
+    config.on_finish = on_finish;
+    config
+}
+
+/// 执行并返回隔离报告
+async fn isolation(config: AgentConfig) -> IsolationReport {
+    *common::run(config)
+        .await
+        .isolation
+        .expect("隔离执行应附带报告")
+}
+
+#[tokio::test]
+async fn worktree_applies_changes_to_main_workspace() {
+    let dir = repo();
+    let root = dir.path();
+    let config = isolated_chore(
+        root,
+        "echo agent > a.txt; echo new > new.txt",
+        WorktreeAction::Apply,
+    );
+
+    let report = isolation(config).await;
+    assert_eq!(report.action, WorktreeAction::Apply);
+    assert_eq!(report.apply_error, None);
+    assert!(report.diff.contains("new.txt"));
+    assert_eq!(read(root, "a.txt").as_deref(), Some("agent\n"));
+    assert_eq!(read(root, "new.txt").as_deref(), Some("new\n"));
+    assert_eq!(
+        git(root, &["diff", "--cached", "--name-only"]),
+        "",
+        "应用改动不应修改暂存区"
+    );
+}
+
+#[tokio::test]
+async fn worktree_falls_back_to_branch_when_apply_fails() {
+    let dir = repo();
+    let root = dir.path();
+    // 主工作区有未提交的冲突改动
+    write(root, "a.txt", "user\n");
+    let config = isolated_chore(root, "echo agent > a.txt", WorktreeAction::Apply);
+
+    let report = isolation(config).await;
+    assert_eq!(report.action, WorktreeAction::Branch);
+    assert!(report.apply_error.is_some());
+    let branch = report.branch.expect("应用失败时应保存为分支");
+    assert!(branch.starts_with("omcc/chore-"), "{}", branch);
+    assert_eq!(read(root, "a.txt").as_deref(), Some("user\n"));
+    assert_eq!(git(root, &["show", &format!("{}:a.txt", branch)]), "agent");
+    assert_eq!(
+        git(root, &["rev-parse", &format!("{}^", branch)]),
+        report.base
+    );
+}
+
+#[tokio::test]
+async fn worktree_saves_branch_and_keeps_main_workspace() {
+    let dir = repo();
+    let root = dir.path();
+    let config = isolated_chore(root, "echo agent > a.txt", WorktreeAction::Branch);
+
+    let report = isolation(config).await;
+    assert_eq!(report.action, WorktreeAction::Branch);
+    assert_eq!(report.apply_error, None);
+    let branch = report.branch.unwrap();
+    assert_eq!(git(root, &["show", &format!("{}:a.txt", branch)]), "agent");
+    assert_eq!(read(root, "a.txt").as_deref(), Some("a\n"));
+    assert_eq!(
+        git(root, &["worktree", "list", "--porcelain"])
+            .matches("worktree ")
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn worktree_without_changes_is_discarded() {
+    let dir = repo();
+    let root = dir.path();
+    let config = isolated_chore(root, "true", WorktreeAction::Branch);
+
+    let report = isolation(config).await;
+    assert_eq!(report.action, WorktreeAction::Discard);
+    assert_eq!(report.branch, None);
+    assert!(report.diff.is_empty());
+    assert_eq!(git(root, &["branch", "--list", "omcc/*"]), "");
+}
+
+#[tokio::test]
+async fn read_only_run_that_edits_tracked_files_fails() {
+    let dir = repo();
+    let root = dir.path();
+    let config = shell_agent(AgentType::Researcher, root, "echo agent > a.txt; rm b.txt");
+
+    let result = AgentExecutor::new(config).execute().await;
+    assert_eq!(common::error_kind(&result), ErrorKind::SandboxViolation);
+    let AgentResult::Failure(failure) = result else {
+        unreachable!()
+    };
+    let changes = failure.changes.expect("应列出被改动的文件");
+    let paths =
+        |files: &[omcc::FileChange]| files.iter().map(|f| f.path.clone()).collect::<Vec<_>>();
+    assert_eq!(paths(&changes.modified), ["a.txt"]);
+    assert_eq!(paths(&changes.deleted), ["b.txt"]);
+}
+
+#[tokio::test]
+async fn read_only_check_covers_only_tracked_files_in_working_dir() {
+    let dir = repo();
+    let root = dir.path();
+    write(root, "sub/c.txt", "c\n");
+    git(root, &["add", "sub/c.txt"]);
+    git(root, &["commit", "-q", "-m", "sub"]);
+    let index = std::fs::metadata(root.join(".git/index"))
+        .unwrap()
+        .modified()
+        .unwrap();
+
+    // 工作目录之外的被跟踪文件、未跟踪的新文件和被忽略的文件不在检查范围内
+    let config = shell_agent(
+        AgentType::Researcher,
+        &root.join("sub"),
+        "echo agent > ../a.txt; echo new > new.txt; echo log > c.log",
+    );
+    let success = common::run(config).await;
+    assert!(success.changes.is_none());
+    assert_eq!(
+        std::fs::metadata(root.join(".git/index"))
+            .unwrap()
+            .modified()
+            .unwrap(),
+        index,
+        "只读校验不应改动暂存区"
+    );
+}
+
+#[tokio::test]
+async fn write_lock_covers_whole_repository() {
+    let dir = repo();
+    let root = dir.path();
+    std::fs::create_dir_all(root.join("one")).unwrap();
+    std::fs::create_dir_all(root.join("two")).unwrap();
+    let cancel = CancellationToken::new();
+
+    // 检查点回滚覆盖整个仓库：同一仓库不同子目录上的可写任务也互斥
+    let lock = WorkspaceLock::acquire(&root.join("one"), AgentType::Chore, None, &cancel)
+        .await
+        .unwrap();
+    let second = WorkspaceLock::acquire(&root.join("two"), AgentType::Chore, None, &cancel).await;
+    assert!(matches!(second, Err(OmccError::WorkspaceLocked(_))));
+
+    // 不同仓库互不影响
+    let other = repo();
+    let other_lock = WorkspaceLock::acquire(other.path(), AgentType::Chore, None, &cancel)
+        .await
+        .unwrap();
+    drop(other_lock);
+
+    drop(lock);
+    WorkspaceLock::acquire(&root.join("two"), AgentType::Chore, None, &cancel)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn lock_with_reused_pid_is_reclaimed() {
+    common::setup();
+    let dir = repo();
+    let cancel = CancellationToken::new();
+    let lock = WorkspaceLock::acquire(dir.path(), AgentType::Chore, None, &cancel)
+        .await
+        .unwrap();
+
+    // 模拟持有者已退出、PID 被当前进程复用：PID 存活但启动时间不符
+    let root = std::fs::canonicalize(dir.path()).unwrap();
+    let locks = omcc::state::state_subdir("locks").unwrap();
+    let path = std::fs::read_dir(&locks)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| {
+            path.extension().is_some_and(|ext| ext == "lock")
+                && std::fs::read_to_string(path)
+                    .ok()
+                    .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+                    .is_some_and(|owner| owner["working_dir"] == root.to_str().unwrap())
+        })
+        .expect("应写入锁文件");
+    let mut owner: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(owner["pid"], std::process::id());
+    assert!(owner["pid_start"].is_u64(), "锁文件应记录持有者启动时间");
+    owner["pid_start"] = serde_json::json!(1);
+    std::fs::write(&path, serde_json::to_vec(&owner).unwrap()).unwrap();
+    std::mem::forget(lock);
+
+    WorkspaceLock::acquire(dir.path(), AgentType::Chore, None, &cancel)
+        .await
+        .unwrap();
+}